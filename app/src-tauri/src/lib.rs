@@ -7,19 +7,111 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Password protection for an output file
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptionSpec {
+    user_password: Option<String>,
+    owner_password: Option<String>,
+    /// "aes128" or "aes256"; AES-256 when missing
+    algorithm: Option<String>,
+    /// Permissions for readers with the user password; allowed when missing
+    print: Option<bool>,
+    copy: Option<bool>,
+    modify: Option<bool>,
+    annotate: Option<bool>,
+}
+
+impl EncryptionSpec {
+    fn into_options(self) -> Result<pdf_core::EncryptionOptions, String> {
+        let algorithm = match self.algorithm.as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("aes256") => pdf_core::EncryptionAlgorithm::Aes256,
+            Some("aes128") => pdf_core::EncryptionAlgorithm::Aes128,
+            Some(other) => return Err(format!("Unknown encryption algorithm: {}", other)),
+        };
+        Ok(pdf_core::EncryptionOptions {
+            user_password: self.user_password.unwrap_or_default(),
+            owner_password: self.owner_password.unwrap_or_default(),
+            algorithm,
+            permissions: pdf_core::Permissions {
+                print: self.print.unwrap_or(true),
+                copy: self.copy.unwrap_or(true),
+                modify: self.modify.unwrap_or(true),
+                annotate: self.annotate.unwrap_or(true),
+            },
+        })
+    }
+}
+
+fn encryption_options(
+    spec: Option<EncryptionSpec>,
+) -> Result<Option<pdf_core::EncryptionOptions>, String> {
+    spec.map(EncryptionSpec::into_options).transpose()
+}
+
 #[tauri::command]
 async fn split_pdf(
     input: String,
-    start: u32,
-    end: u32,
+    start: Option<u32>,
+    end: Option<u32>,
+    ranges: Option<String>,
     output_dir: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
 ) -> Result<Vec<String>, String> {
     let input_path = PathBuf::from(input);
     let output_path = PathBuf::from(output_dir);
+    let encryption = encryption_options(encryption)?;
+
+    // `ranges` takes precedence, e.g. "1-3,5,8-" or "every 10 pages"
+    let ranges = ranges.unwrap_or_else(|| match end {
+        Some(end) => format!("{}-{}", start.unwrap_or(1), end),
+        None => format!("{}-", start.unwrap_or(1)),
+    });
 
     // Run blocking IO in a separate thread
     let result = tauri::async_runtime::spawn_blocking(move || {
-        pdf_core::split(input_path, output_path, "split", start, end)
+        pdf_core::split_ranges(
+            input_path,
+            output_path,
+            "split",
+            &ranges,
+            password.as_deref(),
+            encryption.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    match result {
+        Ok(paths) => Ok(paths
+            .into_iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn split_pdf_by_bookmarks(
+    input: String,
+    depth: Option<usize>,
+    output_dir: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<Vec<String>, String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output_dir);
+    let encryption = encryption_options(encryption)?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::split_bookmarks(
+            input_path,
+            output_path,
+            depth.unwrap_or(1),
+            password.as_deref(),
+            encryption.as_ref(),
+        )
     })
     .await
     .map_err(|e: tauri::Error| e.to_string())?;
@@ -33,26 +125,270 @@ async fn split_pdf(
     }
 }
 
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SplitPartInfo {
+    path: String,
+    first_page: u32,
+    last_page: u32,
+    size: u64,
+}
+
+#[tauri::command]
+async fn split_pdf_by_size(
+    input: String,
+    max_bytes: u64,
+    output_dir: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<Vec<SplitPartInfo>, String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output_dir);
+    let encryption = encryption_options(encryption)?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::split_size(
+            input_path,
+            output_path,
+            "split",
+            max_bytes,
+            password.as_deref(),
+            encryption.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    match result {
+        Ok(parts) => Ok(parts
+            .into_iter()
+            .map(|p| SplitPartInfo {
+                path: p.path.to_string_lossy().to_string(),
+                first_page: p.first_page,
+                last_page: p.last_page,
+                size: p.size,
+            })
+            .collect()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+async fn merge_pdf(
+    inputs: Vec<String>,
+    output: String,
+    outline_per_file: Option<bool>,
+    passwords: Option<Vec<Option<String>>>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<(), String> {
+    // `passwords[i]` belongs to `inputs[i]`
+    let mut passwords = passwords.unwrap_or_default().into_iter();
+    let inputs: Vec<pdf_core::MergeInput> = inputs
+        .into_iter()
+        .map(|path| pdf_core::MergeInput {
+            password: passwords.next().flatten(),
+            ..pdf_core::MergeInput::new(path)
+        })
+        .collect();
+    let output_path = PathBuf::from(output);
+    let options = pdf_core::MergeOptions {
+        outline_per_file: outline_per_file.unwrap_or(false),
+        encryption: encryption_options(encryption)?,
+        ..Default::default()
+    };
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::merge_pages(&inputs, output_path, &options)
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+#[derive(serde::Deserialize)]
+struct MergeInputSpec {
+    path: String,
+    /// Page-range expression, e.g. "1-3,7"; all pages when missing
+    pages: Option<String>,
+    reverse: Option<bool>,
+    password: Option<String>,
+}
+
 #[tauri::command]
-async fn merge_pdf(inputs: Vec<String>, output: String) -> Result<(), String> {
-    let input_paths: Vec<PathBuf> = inputs.into_iter().map(PathBuf::from).collect();
+async fn merge_pdf_pages(
+    inputs: Vec<MergeInputSpec>,
+    output: String,
+    interleave: Option<bool>,
+    outline_per_file: Option<bool>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<(), String> {
+    let inputs: Vec<pdf_core::MergeInput> = inputs
+        .into_iter()
+        .map(|spec| pdf_core::MergeInput {
+            path: PathBuf::from(spec.path),
+            pages: spec.pages.filter(|p| !p.trim().is_empty()),
+            reverse: spec.reverse.unwrap_or(false),
+            password: spec.password,
+        })
+        .collect();
     let output_path = PathBuf::from(output);
+    let options = pdf_core::MergeOptions {
+        outline_per_file: outline_per_file.unwrap_or(false),
+        interleave: if interleave.unwrap_or(false) {
+            pdf_core::InterleaveMode::Alternate
+        } else {
+            pdf_core::InterleaveMode::Sequential
+        },
+        encryption: encryption_options(encryption)?,
+    };
 
-    let result =
-        tauri::async_runtime::spawn_blocking(move || pdf_core::merge(&input_paths, output_path))
-            .await
-            .map_err(|e: tauri::Error| e.to_string())?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::merge_pages(&inputs, output_path, &options)
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
 
     result.map_err(|e: PdfError| e.to_string())
 }
 
+/// Page layout for `image_to_pdf`; missing fields keep the defaults of
+/// `pdf_core::ImagePdfOptions`. Lengths are in millimeters.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImagePdfSpec {
+    /// "a4", "a3", "a5", "letter", "legal", "image" (page sized to each image) or "custom"
+    page_size: Option<String>,
+    /// Size of a "custom" page
+    page_width: Option<f32>,
+    page_height: Option<f32>,
+    /// "portrait", "landscape" or "auto" (per image); "auto" when missing
+    orientation: Option<String>,
+    margin: Option<f32>,
+    /// "fit", "fill" or "center"
+    fit: Option<String>,
+    dpi: Option<f32>,
+    /// Grid of images per page; one image per page when both are missing
+    rows: Option<u32>,
+    columns: Option<u32>,
+    /// Space between grid cells
+    gutter: Option<f32>,
+    /// File names under the images
+    captions: Option<bool>,
+    /// Clean up photos of paper documents, keeping "color" or turning them "grayscale" or
+    /// "bilevel" (black and white); no cleanup when missing
+    scan: Option<String>,
+    /// Gray level (0-255) below which "bilevel" pixels turn black; adapts to the lighting
+    /// when missing
+    threshold: Option<u8>,
+    /// Steps of the cleanup, all on when missing
+    detect_edges: Option<bool>,
+    deskew: Option<bool>,
+    trim_borders: Option<bool>,
+}
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+impl ImagePdfSpec {
+    fn into_options(self) -> Result<pdf_core::ImagePdfOptions, String> {
+        let defaults = pdf_core::ImagePdfOptions::default();
+        let page_size = match self.page_size.as_deref().map(str::to_lowercase).as_deref() {
+            None => defaults.page_size,
+            Some("a4") => pdf_core::PageSize::A4,
+            Some("a3") => pdf_core::PageSize::A3,
+            Some("a5") => pdf_core::PageSize::A5,
+            Some("letter") => pdf_core::PageSize::Letter,
+            Some("legal") => pdf_core::PageSize::Legal,
+            Some("image") => pdf_core::PageSize::Image,
+            Some("custom") => match (self.page_width, self.page_height) {
+                (Some(width), Some(height)) if width > 0.0 && height > 0.0 => {
+                    pdf_core::PageSize::Custom {
+                        width: width * POINTS_PER_MM,
+                        height: height * POINTS_PER_MM,
+                    }
+                }
+                _ => return Err("Custom page size needs a width and height".to_string()),
+            },
+            Some(other) => return Err(format!("Unknown page size: {}", other)),
+        };
+        let orientation = match self.orientation.as_deref() {
+            None | Some("auto") => None,
+            Some("portrait") => Some(pdf_core::Orientation::Portrait),
+            Some("landscape") => Some(pdf_core::Orientation::Landscape),
+            Some(other) => return Err(format!("Unknown orientation: {}", other)),
+        };
+        let fit = match self.fit.as_deref() {
+            None | Some("fit") => pdf_core::ImageFit::Fit,
+            Some("fill") => pdf_core::ImageFit::Fill,
+            Some("center") => pdf_core::ImageFit::Center,
+            Some(other) => return Err(format!("Unknown fit mode: {}", other)),
+        };
+        let grid = match (self.rows, self.columns) {
+            (None, None) => None,
+            (rows, columns) => Some(pdf_core::ImageGrid {
+                rows: rows.unwrap_or(1),
+                columns: columns.unwrap_or(1),
+                gutter: self.gutter.unwrap_or(0.0) * POINTS_PER_MM,
+            }),
+        };
+        let color = match self.scan.as_deref() {
+            None => None,
+            Some("color") => Some(pdf_core::ScanColor::Color),
+            Some("grayscale") => Some(pdf_core::ScanColor::Grayscale),
+            Some("bilevel") => Some(pdf_core::ScanColor::Bilevel {
+                threshold: self.threshold,
+            }),
+            Some(other) => return Err(format!("Unknown scan color: {}", other)),
+        };
+        let scan = color.map(|color| {
+            let steps = pdf_core::ScanCleanup::default();
+            pdf_core::ScanCleanup {
+                detect_edges: self.detect_edges.unwrap_or(steps.detect_edges),
+                deskew: self.deskew.unwrap_or(steps.deskew),
+                trim_borders: self.trim_borders.unwrap_or(steps.trim_borders),
+                color,
+            }
+        });
+        Ok(pdf_core::ImagePdfOptions {
+            page_size,
+            orientation,
+            margin: self.margin.map_or(defaults.margin, |mm| mm * POINTS_PER_MM),
+            fit,
+            dpi: self.dpi.unwrap_or(defaults.dpi),
+            grid,
+            captions: self.captions.unwrap_or(defaults.captions),
+            scan,
+        })
+    }
+}
+
 #[tauri::command]
-async fn image_to_pdf(images: Vec<String>, output: String) -> Result<(), String> {
+async fn image_to_pdf(
+    images: Vec<String>,
+    output: String,
+    options: Option<ImagePdfSpec>,
+) -> Result<(), String> {
     let image_paths: Vec<PathBuf> = images.into_iter().map(PathBuf::from).collect();
     let output_path = PathBuf::from(output);
+    let options = match options {
+        Some(spec) => spec.into_options()?,
+        None => pdf_core::ImagePdfOptions::default(),
+    };
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::images_to_pdf_with_options(&image_paths, output_path, &options)
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
 
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+#[tauri::command]
+async fn get_pdf_page_count(input: String, password: Option<String>) -> Result<u32, String> {
+    let input_path = PathBuf::from(input);
     let result = tauri::async_runtime::spawn_blocking(move || {
-        pdf_core::engine::images_to_pdf(&image_paths, output_path)
+        pdf_core::get_page_count(input_path, password.as_deref())
     })
     .await
     .map_err(|e: tauri::Error| e.to_string())?;
@@ -60,10 +396,11 @@ async fn image_to_pdf(images: Vec<String>, output: String) -> Result<(), String>
     result.map_err(|e: PdfError| e.to_string())
 }
 
+/// Lets the UI prompt for a password before running an operation
 #[tauri::command]
-async fn get_pdf_page_count(input: String) -> Result<u32, String> {
+async fn pdf_needs_password(input: String) -> Result<bool, String> {
     let input_path = PathBuf::from(input);
-    let result = tauri::async_runtime::spawn_blocking(move || pdf_core::get_page_count(input_path))
+    let result = tauri::async_runtime::spawn_blocking(move || pdf_core::needs_password(input_path))
         .await
         .map_err(|e: tauri::Error| e.to_string())?;
 
@@ -71,24 +408,152 @@ async fn get_pdf_page_count(input: String) -> Result<u32, String> {
 }
 
 #[tauri::command]
-async fn reorder_pdf(input: String, page_order: Vec<u32>, output: String) -> Result<(), String> {
+async fn reorder_pdf(
+    input: String,
+    page_order: Vec<u32>,
+    output: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<(), String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let encryption = encryption_options(encryption)?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::reorder_pages(
+            input_path,
+            &page_order,
+            output_path,
+            password.as_deref(),
+            encryption.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+/// `rotations` holds `[page, degrees]` pairs, e.g. `[[1, 90], [3, -90]]`
+#[tauri::command]
+async fn rotate_pdf(
+    input: String,
+    rotations: Vec<(u32, i32)>,
+    output: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<(), String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let encryption = encryption_options(encryption)?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::rotate_pages(
+            input_path,
+            &rotations,
+            output_path,
+            password.as_deref(),
+            encryption.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+/// `orientation` is "portrait" or "landscape"; returns the pages that were rotated
+#[tauri::command]
+async fn rotate_pdf_to_orientation(
+    input: String,
+    orientation: String,
+    output: String,
+    password: Option<String>,
+    encryption: Option<EncryptionSpec>,
+) -> Result<Vec<u32>, String> {
+    let orientation = match orientation.to_lowercase().as_str() {
+        "portrait" => pdf_core::Orientation::Portrait,
+        "landscape" => pdf_core::Orientation::Landscape,
+        other => return Err(format!("Unknown orientation: {}", other)),
+    };
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let encryption = encryption_options(encryption)?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::rotate_to_orientation(
+            input_path,
+            orientation,
+            output_path,
+            password.as_deref(),
+            encryption.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+/// Writes a password-protected copy of `input`; `password` opens an already encrypted input
+#[tauri::command]
+async fn encrypt_pdf(
+    input: String,
+    output: String,
+    encryption: EncryptionSpec,
+    password: Option<String>,
+) -> Result<(), String> {
+    let options = encryption.into_options()?;
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::encrypt_pdf(input_path, output_path, &options, password.as_deref())
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+#[tauri::command]
+async fn pdf_to_word(
+    input: String,
+    output: String,
+    password: Option<String>,
+) -> Result<(), String> {
     let input_path = PathBuf::from(input);
     let output_path = PathBuf::from(output);
     let result = tauri::async_runtime::spawn_blocking(move || {
-        pdf_core::reorder_pages(input_path, &page_order, output_path)
+        pdf_core::pdf_to_word(input_path, output_path, password.as_deref())
     })
     .await
     .map_err(|e: tauri::Error| e.to_string())?;
+    result.map_err(|e: PdfError| e.to_string())
+}
 
+#[tauri::command]
+async fn pdf_to_text(
+    input: String,
+    output: String,
+    password: Option<String>,
+) -> Result<(), String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::pdf_to_text(input_path, output_path, password.as_deref())
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
     result.map_err(|e: PdfError| e.to_string())
 }
 
 #[tauri::command]
-async fn pdf_to_word(input: String, output: String) -> Result<(), String> {
+async fn pdf_to_markdown(
+    input: String,
+    output: String,
+    password: Option<String>,
+) -> Result<(), String> {
     let input_path = PathBuf::from(input);
     let output_path = PathBuf::from(output);
     let result = tauri::async_runtime::spawn_blocking(move || {
-        pdf_core::pdf_to_word(input_path, output_path)
+        pdf_core::pdf_to_markdown(input_path, output_path, password.as_deref())
     })
     .await
     .map_err(|e: tauri::Error| e.to_string())?;
@@ -96,12 +561,117 @@ async fn pdf_to_word(input: String, output: String) -> Result<(), String> {
 }
 
 #[tauri::command]
-async fn pdf_to_image(app: tauri::AppHandle, input: String, output: String) -> Result<(), String> {
+async fn pdf_to_html(
+    input: String,
+    output: String,
+    password: Option<String>,
+) -> Result<(), String> {
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        pdf_core::pdf_to_html(input_path, output_path, password.as_deref())
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+    result.map_err(|e: PdfError| e.to_string())
+}
+
+/// Rendering settings for `pdf_to_image`; missing fields keep the defaults of
+/// `pdf_core::RenderOptions`
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RenderSpec {
+    /// Takes precedence over `width`/`height`
+    dpi: Option<f32>,
+    /// Bounds the page is fitted into, in pixels
+    width: Option<u32>,
+    height: Option<u32>,
+    /// "png", "jpeg", "webp" or "tiff"
+    format: Option<String>,
+    /// JPEG quality 1-100; 90 when missing
+    quality: Option<u8>,
+    /// Page-range expression, e.g. "1-3,7"; all pages when missing
+    pages: Option<String>,
+    /// "#rrggbb" or "transparent"
+    background: Option<String>,
+    grayscale: Option<bool>,
+    /// Gray level below which pixels turn black; color when missing
+    threshold: Option<u8>,
+    /// e.g. "{name}_{page:03}"
+    file_name: Option<String>,
+    /// "pages", "tiff" (one multi-page TIFF) or "contactSheet"; "pages" when missing
+    output: Option<String>,
+    /// Contact sheet columns; 4 when missing
+    columns: Option<u32>,
+    /// Contact sheet spacing in pixels; 16 when missing
+    spacing: Option<u32>,
+    /// Largest contact sheet tile side in pixels; 300 when missing
+    tile: Option<u32>,
+}
+
+impl RenderSpec {
+    fn into_options(self) -> Result<pdf_core::RenderOptions, String> {
+        let defaults = pdf_core::RenderOptions::default();
+        let size = match (self.dpi, self.width, self.height) {
+            (Some(dpi), _, _) => pdf_core::RenderSize::Dpi(dpi),
+            (None, None, None) => defaults.size,
+            (None, width, height) => pdf_core::RenderSize::Fit { width, height },
+        };
+        let format = match self.format.as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("png") => pdf_core::RenderFormat::Png,
+            Some("jpeg") | Some("jpg") => pdf_core::RenderFormat::Jpeg {
+                quality: self.quality.unwrap_or(90),
+            },
+            Some("webp") => pdf_core::RenderFormat::WebP,
+            Some("tiff") | Some("tif") => pdf_core::RenderFormat::Tiff,
+            Some(other) => return Err(format!("Unknown image format: {}", other)),
+        };
+        let background = match self.background.as_deref() {
+            None => defaults.background,
+            Some("transparent") => None,
+            Some(color) => Some(parse_color(color)?),
+        };
+        let output = match self.output.as_deref() {
+            None | Some("pages") => pdf_core::RenderOutput::Pages,
+            Some("tiff") => pdf_core::RenderOutput::MultiPageTiff,
+            Some("contactSheet") => pdf_core::RenderOutput::ContactSheet {
+                columns: self.columns.unwrap_or(4),
+                spacing: self.spacing.unwrap_or(16),
+                tile: self.tile.unwrap_or(300),
+            },
+            Some(other) => return Err(format!("Unknown output mode: {}", other)),
+        };
+        Ok(pdf_core::RenderOptions {
+            size,
+            format,
+            output,
+            pages: self.pages,
+            background,
+            grayscale: self.grayscale.unwrap_or(false),
+            threshold: self.threshold,
+            file_name: self.file_name.unwrap_or(defaults.file_name),
+        })
+    }
+}
+
+/// Parses a "#rrggbb" color
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+            .ok_or_else(|| format!("Invalid color: {}", color))
+    };
+    if hex.len() != 6 {
+        return Err(format!("Invalid color: {}", color));
+    }
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// 定位 pdfium 动态库；找不到时返回 None，由内置渲染器代替
+fn pdfium_path(app: &tauri::AppHandle) -> Option<PathBuf> {
     use tauri::Manager;
-    let input_path = std::path::PathBuf::from(input);
-    let output_path = std::path::PathBuf::from(output);
 
-    // 1. 定位 pdfium.dll
     let dll_name = if cfg!(windows) {
         "pdfium.dll"
     } else if cfg!(target_os = "macos") {
@@ -110,12 +680,12 @@ async fn pdf_to_image(app: tauri::AppHandle, input: String, output: String) -> R
         "libpdfium.so"
     };
 
-    let resource_dir = app.path().resource_dir().map_err(|e| e.to_string())?;
-    let mut possible_dll_paths = vec![
-        resource_dir.join(dll_name),
-        resource_dir.join("resources").join(dll_name),
-        resource_dir.join("bin").join(dll_name),
-    ];
+    let mut possible_dll_paths = Vec::new();
+    if let Ok(resource_dir) = app.path().resource_dir() {
+        possible_dll_paths.push(resource_dir.join(dll_name));
+        possible_dll_paths.push(resource_dir.join("resources").join(dll_name));
+        possible_dll_paths.push(resource_dir.join("bin").join(dll_name));
+    }
 
     // 开发环境路径
     if let Ok(cwd) = std::env::current_dir() {
@@ -140,26 +710,130 @@ async fn pdf_to_image(app: tauri::AppHandle, input: String, output: String) -> R
         }
     }
 
-    let dll_path = possible_dll_paths.iter().find(|p| p.exists());
+    possible_dll_paths.into_iter().find(|p| p.exists())
+}
 
-    if let Some(path) = dll_path {
-        let path = path.clone();
-        let result = tauri::async_runtime::spawn_blocking(move || {
-            pdf_core::pdf_to_images(input_path, output_path, path)
-        })
-        .await
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn pdf_to_image(
+    app: tauri::AppHandle,
+    input: String,
+    output: String,
+    options: Option<RenderSpec>,
+    password: Option<String>,
+) -> Result<Vec<String>, String> {
+    let options = match options {
+        Some(spec) => spec.into_options()?,
+        None => pdf_core::RenderOptions::default(),
+    };
+    let input_path = PathBuf::from(input);
+    let output_path = PathBuf::from(output);
+    let dll_path = pdfium_path(&app);
 
-        return result.map_err(|e: PdfError| e.to_string());
-    }
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let renderer = pdf_core::default_renderer(dll_path.as_deref());
+        pdf_core::pdf_to_images(
+            input_path,
+            output_path,
+            renderer.as_ref(),
+            &options,
+            password.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
 
-    // 找不到 DLL 的详细错误
-    let mut err_msg = format!("找不到 {}。请下载并放置在以下任一位置：\n", dll_name);
-    for p in possible_dll_paths {
-        err_msg.push_str(&format!("- {:?}\n", p));
+    match result {
+        Ok(paths) => Ok(paths
+            .into_iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect()),
+        Err(e) => Err(e.to_string()),
     }
+}
+
+/// Longest side of a page preview when the caller gives none
+const DEFAULT_THUMBNAIL_PX: u32 = 256;
+
+fn png_data_url(png: &[u8]) -> String {
+    use base64::Engine;
+    format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png)
+    )
+}
+
+/// Renders one page preview as a PNG data URL
+#[tauri::command]
+async fn render_page_thumbnail(
+    app: tauri::AppHandle,
+    input: String,
+    page: u32,
+    max_px: Option<u32>,
+    password: Option<String>,
+) -> Result<String, String> {
+    let input_path = PathBuf::from(input);
+    let dll_path = pdfium_path(&app);
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let renderer = pdf_core::default_renderer(dll_path.as_deref());
+        pdf_core::render_page_thumbnail(
+            input_path,
+            renderer.as_ref(),
+            page,
+            max_px.unwrap_or(DEFAULT_THUMBNAIL_PX),
+            password.as_deref(),
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
 
-    Err(err_msg)
+    result
+        .map(|png| png_data_url(&png))
+        .map_err(|e: PdfError| e.to_string())
+}
+
+/// A page preview sent through the channel of `render_page_thumbnails`
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Thumbnail {
+    page: u32,
+    data_url: String,
+}
+
+/// Renders page previews, sending each one through `on_thumbnail` as soon as it is ready
+#[tauri::command]
+async fn render_page_thumbnails(
+    app: tauri::AppHandle,
+    input: String,
+    pages: Option<String>,
+    max_px: Option<u32>,
+    password: Option<String>,
+    on_thumbnail: tauri::ipc::Channel<Thumbnail>,
+) -> Result<(), String> {
+    let input_path = PathBuf::from(input);
+    let dll_path = pdfium_path(&app);
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let renderer = pdf_core::default_renderer(dll_path.as_deref());
+        pdf_core::render_page_thumbnails(
+            input_path,
+            renderer.as_ref(),
+            pages.as_deref(),
+            max_px.unwrap_or(DEFAULT_THUMBNAIL_PX),
+            password.as_deref(),
+            // Stop once the window no longer listens
+            |page, png| {
+                on_thumbnail
+                    .send(Thumbnail {
+                        page,
+                        data_url: png_data_url(&png),
+                    })
+                    .is_ok()
+            },
+        )
+    })
+    .await
+    .map_err(|e: tauri::Error| e.to_string())?;
+
+    result.map_err(|e: PdfError| e.to_string())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -171,12 +845,24 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             greet,
             split_pdf,
+            split_pdf_by_bookmarks,
+            split_pdf_by_size,
             merge_pdf,
+            merge_pdf_pages,
             image_to_pdf,
             get_pdf_page_count,
+            pdf_needs_password,
             reorder_pdf,
+            rotate_pdf,
+            rotate_pdf_to_orientation,
+            encrypt_pdf,
             pdf_to_word,
-            pdf_to_image
+            pdf_to_text,
+            pdf_to_markdown,
+            pdf_to_html,
+            pdf_to_image,
+            render_page_thumbnail,
+            render_page_thumbnails
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -92,7 +92,7 @@ export default function ImageToPdf({ isActive }: { isActive: boolean }) {
         const unlisten = listen('tauri://drag-drop', (event: any) => {
             if (!isActive) return; // Only handle drop if active
             const droppedFiles = event.payload.paths as string[];
-            const validExtensions = ['.jpg', '.jpeg', '.png', '.bmp', '.gif', '.tif', '.tiff'];
+            const validExtensions = ['.jpg', '.jpeg', '.png', '.bmp', '.gif', '.webp', '.tif', '.tiff'];
             const filtered = droppedFiles.filter(path =>
                 validExtensions.some(ext => path.toLowerCase().endsWith(ext))
             );
@@ -125,7 +125,7 @@ export default function ImageToPdf({ isActive }: { isActive: boolean }) {
         try {
             const selected = await open({
                 multiple: true,
-                filters: [{ name: 'Images', extensions: ['jpg', 'jpeg', 'png', 'bmp'] }]
+                filters: [{ name: 'Images', extensions: ['jpg', 'jpeg', 'png', 'bmp', 'gif', 'webp', 'tif', 'tiff'] }]
             });
 
             if (selected) {
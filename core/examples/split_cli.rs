@@ -1,4 +1,4 @@
-use pdf_core::{PdfError, merge, split};
+use pdf_core::{merge, pdf_to_html, pdf_to_markdown, pdf_to_text, split_ranges};
 use std::env;
 use std::path::PathBuf;
 
@@ -12,23 +12,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     match args[1].as_str() {
         "split" => {
             if args.len() < 5 {
-                println!("Usage: split <input> <start_page> <end_page> <output_dir>");
+                println!("Usage: split <input> <ranges> <output_dir>");
                 return Ok(());
             }
             let input = PathBuf::from(&args[2]);
-            let start: u32 = args[3].parse()?;
-            let end: u32 = args[4].parse()?;
-            let output_dir = PathBuf::from(&args[5]);
+            let ranges = &args[3];
+            let output_dir = PathBuf::from(&args[4]);
 
             if !output_dir.exists() {
                 std::fs::create_dir_all(&output_dir)?;
             }
 
             println!(
-                "Splitting {:?} pages {}-{} to {:?}",
-                input, start, end, output_dir
+                "Splitting {:?} pages \"{}\" to {:?}",
+                input, ranges, output_dir
             );
-            let paths = split(&input, &output_dir, "split_output", start, end)?;
+            let paths = split_ranges(&input, &output_dir, "split_output", ranges, None, None)?;
             for p in paths {
                 println!("Created: {:?}", p);
             }
@@ -45,6 +44,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             merge(&inputs, output)?;
             println!("Done.");
         }
+        "text" => {
+            if args.len() < 4 {
+                println!("Usage: text <input> <output_file>");
+                return Ok(());
+            }
+            let input = PathBuf::from(&args[2]);
+            let output = PathBuf::from(&args[3]);
+
+            println!("Converting {:?} to {:?}", input, output);
+            pdf_to_text(input, output, None)?;
+            println!("Done.");
+        }
+        "markdown" => {
+            if args.len() < 4 {
+                println!("Usage: markdown <input> <output_file>");
+                return Ok(());
+            }
+            let input = PathBuf::from(&args[2]);
+            let output = PathBuf::from(&args[3]);
+
+            println!("Converting {:?} to {:?}", input, output);
+            pdf_to_markdown(input, output, None)?;
+            println!("Done.");
+        }
+        "html" => {
+            if args.len() < 4 {
+                println!("Usage: html <input> <output_file>");
+                return Ok(());
+            }
+            let input = PathBuf::from(&args[2]);
+            let output = PathBuf::from(&args[3]);
+
+            println!("Converting {:?} to {:?}", input, output);
+            pdf_to_html(input, output, None)?;
+            println!("Done.");
+        }
         _ => print_usage(),
     }
 
@@ -54,6 +89,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 fn print_usage() {
     println!("PDF CLI Example");
     println!("Commands:");
-    println!("  split <input> <start_page> <end_page> <output_dir>");
+    println!("  split <input> <ranges> <output_dir>");
+    println!("        ranges: e.g. \"1-3,5,8-\", \"every 10 pages\", \"odd,even\"");
     println!("  merge <output_file> <input1> <input2> ...");
+    println!("  text <input> <output_file>");
+    println!("  markdown <input> <output_file>");
+    println!("  html <input> <output_file>");
 }
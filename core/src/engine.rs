@@ -1,34 +1,199 @@
 use crate::error::PdfError;
-use lopdf::content::Content;
+use crate::export::{to_html, to_markdown, to_text};
+use crate::font::FontCache;
+use crate::layout::PageLayout;
+use crate::outline::{
+    build_name_tree, decode_text_string, destination_page, drop_destinations_to,
+    encode_text_string, named_destinations, outline_top_items, page_labels, read_outline,
+    rename_destination_refs, sanitize_file_name,
+};
+use crate::page_range::{parse_page_groups, parse_page_list};
+use crate::render;
+use crate::renderer::PageRenderer;
+use crate::scan;
+use crate::security::{encrypt_document, load_document, EncryptionOptions};
+use crate::text::{runs_to_text, ContentWalker};
+use crate::word::build_docx;
+use crate::xobject::{decode_image_file, ImageXObject};
+use lopdf::content::{Content, Operation};
 use lopdf::dictionary;
-use lopdf::{Document, Object, ObjectId, Stream};
-use std::collections::BTreeMap;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::Path;
+use std::sync::PoisonError;
+
+/// How the selected pages of the merge inputs are ordered in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InterleaveMode {
+    /// All pages of the first input, then all pages of the second, ...
+    #[default]
+    Sequential,
+    /// One page from each input in turn; longer inputs continue once shorter ones run out.
+    /// Combined with [`MergeInput::reverse`] this reassembles double-sided scans.
+    Alternate,
+}
+
+/// Options for [`merge_pdf_with_options`] and [`merge_pdf_pages`].
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Nest each input's bookmarks under one top-level bookmark named after the input file.
+    pub outline_per_file: bool,
+    pub interleave: InterleaveMode,
+    /// Password-protect the merged file.
+    pub encryption: Option<EncryptionOptions>,
+}
+
+/// One input of [`merge_pdf_pages`].
+#[derive(Debug, Clone)]
+pub struct MergeInput {
+    pub path: std::path::PathBuf,
+    /// Page-range expression selecting the pages to take (see [`parse_page_groups`]),
+    /// e.g. "1-3" or "7". `None` takes every page.
+    pub pages: Option<String>,
+    /// Take the selected pages in reverse order.
+    pub reverse: bool,
+    /// User password, for encrypted inputs.
+    pub password: Option<String>,
+}
+
+impl MergeInput {
+    /// Takes every page of `path`, in order.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        MergeInput {
+            path: path.as_ref().to_path_buf(),
+            pages: None,
+            reverse: false,
+            password: None,
+        }
+    }
+}
 
-/// Merges multiple PDF files into one.
 /// Merges multiple PDF files into one.
 pub fn merge_pdf<P: AsRef<Path>>(documents: &[P], output: P) -> Result<(), PdfError> {
+    merge_pdf_with_options(documents, output, &MergeOptions::default())
+}
+
+/// Merges multiple PDF files into one, keeping their navigation data:
+/// bookmarks, named destinations and form fields (renamed where inputs collide), link
+/// annotations, the forms' default resources and page labels. Viewer preferences are taken
+/// from the first input.
+pub fn merge_pdf_with_options<P: AsRef<Path>>(
+    documents: &[P],
+    output: P,
+    options: &MergeOptions,
+) -> Result<(), PdfError> {
+    let inputs: Vec<MergeInput> = documents.iter().map(MergeInput::new).collect();
+    merge_pdf_pages(&inputs, output, options)
+}
+
+/// Merges a selection of pages from each input, e.g. "pages 1-3 of A, all of B, page 7 of C".
+/// Navigation data is kept as in [`merge_pdf_with_options`]; bookmarks and links to pages
+/// that were left out lose their destination. Page labels are only kept when every input
+/// is taken whole and in sequence.
+pub fn merge_pdf_pages<P: AsRef<Path>>(
+    inputs: &[MergeInput],
+    output: P,
+    options: &MergeOptions,
+) -> Result<(), PdfError> {
     let mut documents_loaded = Vec::new();
-    for path in documents {
-        documents_loaded.push(Document::load(path.as_ref())?);
+    for input in inputs {
+        let title = input
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let doc = load_document(&input.path, input.password.as_deref())?;
+        documents_loaded.push((doc, title, input));
     }
 
+    let keep_labels = options.interleave == InterleaveMode::Sequential
+        && inputs.iter().all(|i| i.pages.is_none() && !i.reverse);
+
     let mut max_id = 1;
     let mut final_objects = BTreeMap::new();
-    let mut final_page_ids = Vec::new();
 
-    for mut doc in documents_loaded {
+    // Navigation data gathered from every input
+    let mut outline_parts: Vec<OutlinePart> = Vec::new();
+    let mut dests_by_name: Vec<(Vec<u8>, Object)> = Vec::new();
+    let mut dests_by_string: Vec<(Vec<u8>, Object)> = Vec::new();
+    let mut used_names: HashSet<Vec<u8>> = HashSet::new();
+    let mut used_strings: HashSet<Vec<u8>> = HashSet::new();
+    let mut labels: Vec<(i64, Object)> = Vec::new();
+    let mut any_labels = false;
+    let mut form = MergedForm::default();
+    let mut viewer_entries: Vec<(Vec<u8>, Object)> = Vec::new();
+
+    let mut selections: Vec<Vec<ObjectId>> = Vec::new();
+    let mut all_page_ids: HashSet<ObjectId> = HashSet::new();
+
+    for (index, (mut doc, title, input)) in documents_loaded.into_iter().enumerate() {
         doc.renumber_objects_with(max_id);
         max_id = doc.max_id + 1;
 
-        let pages = doc.get_pages();
-        let mut doc_page_ids: Vec<ObjectId> = pages.into_values().collect();
-        doc_page_ids.sort(); // Ensure order
+        // Page-number order of the page tree, which is not necessarily object-number order
+        let doc_page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        all_page_ids.extend(doc_page_ids.iter().copied());
+
+        let mut selected: Vec<ObjectId> = match &input.pages {
+            Some(expr) => parse_page_list(expr, doc_page_ids.len() as u32)?
+                .into_iter()
+                .map(|page| doc_page_ids[page as usize - 1])
+                .collect(),
+            None => doc_page_ids.clone(),
+        };
+        if input.reverse {
+            selected.reverse();
+        }
+
+        // Named destinations must stay unique across inputs; rename colliding ones
+        // and rewrite the links and bookmarks of this input that use them.
+        let dests = named_destinations(&doc);
+        let name_renames = unique_names(&dests.by_name, &mut used_names, index);
+        let string_renames = unique_names(&dests.by_string, &mut used_strings, index);
+        rename_destination_refs(&mut doc, &name_renames, &string_renames);
+        for (name, dest) in dests.by_name {
+            let name = name_renames.get(&name).cloned().unwrap_or(name);
+            dests_by_name.push((name, dest));
+        }
+        for (name, dest) in dests.by_string {
+            let name = string_renames.get(&name).cloned().unwrap_or(name);
+            dests_by_string.push((name, dest));
+        }
+
+        // Page labels are keyed by page index, so shift them past the previous inputs
+        let doc_labels = page_labels(&doc);
+        let offset: usize = selections.iter().map(Vec::len).sum();
+        let offset = offset as i64;
+        if doc_labels.is_empty() {
+            labels.push((offset, Object::Dictionary(dictionary! { "S" => "D" })));
+        } else {
+            any_labels = true;
+            labels.extend(doc_labels.into_iter().map(|(i, label)| (i + offset, label)));
+        }
 
-        for id in &doc_page_ids {
-            final_page_ids.push(*id);
+        merge_acro_form(&mut doc, index, &mut form);
+        if let Ok(catalog) = doc.catalog() {
+            if index == 0 {
+                for key in [
+                    b"ViewerPreferences".as_slice(),
+                    b"PageMode",
+                    b"PageLayout",
+                    b"Lang",
+                ] {
+                    if let Ok(value) = catalog.get(key) {
+                        viewer_entries.push((key.to_vec(), value.clone()));
+                    }
+                }
+            }
         }
 
+        outline_parts.push(OutlinePart {
+            title,
+            first_page: selected.first().copied(),
+            top_items: outline_top_items(&doc),
+        });
+        selections.push(selected);
+
         for (id, object) in doc.objects {
             // We keep the object if it's NOT the catalog or pages root of this sub-doc
             // But identifying them is hard without traversing.
@@ -42,32 +207,66 @@ pub fn merge_pdf<P: AsRef<Path>>(documents: &[P], output: P) -> Result<(), PdfEr
     result.objects = final_objects;
     result.max_id = max_id;
 
-    let pages_root_id = result.new_object_id();
-    let catalog_id = result.new_object_id();
+    let final_page_ids = interleave_pages(selections, options.interleave);
 
-    // Update all pages to point to new parent
-    for page_id in &final_page_ids {
-        if let Some(Object::Dictionary(dict)) = result.objects.get_mut(page_id) {
-            dict.set(b"Parent".to_vec(), Object::Reference(pages_root_id));
-        }
+    // Cut every link into pages that were left out, so pruning can drop them
+    let placed: HashSet<ObjectId> = final_page_ids.iter().copied().collect();
+    let removed_pages: HashSet<ObjectId> = all_page_ids.difference(&placed).copied().collect();
+    if !removed_pages.is_empty() {
+        drop_destinations_to(&mut result, &removed_pages);
+        let points_to_removed = |dest: &Object| {
+            destination_page(&result, dest).is_some_and(|page| removed_pages.contains(&page))
+        };
+        dests_by_name.retain(|(_, dest)| !points_to_removed(dest));
+        dests_by_string.retain(|(_, dest)| !points_to_removed(dest));
     }
 
-    // Create Pages Dictionary
-    use lopdf::dictionary;
-    let pages_dict = dictionary! {
-        "Type" => "Pages",
-        "Count" => final_page_ids.len() as i32,
-        "Kids" => final_page_ids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
-    };
-    result
-        .objects
-        .insert(pages_root_id, Object::Dictionary(pages_dict));
+    // The old page trees of the inputs came along with their objects, so inherited
+    // attributes are still reachable here and get copied onto the pages.
+    let pages_root_id = rebuild_page_tree(&mut result, &final_page_ids);
+    let catalog_id = result.new_object_id();
 
     // Create Catalog
-    let catalog_dict = dictionary! {
+    let mut catalog_dict = dictionary! {
         "Type" => "Catalog",
         "Pages" => Object::Reference(pages_root_id),
     };
+    for (key, value) in viewer_entries {
+        catalog_dict.set(key, value);
+    }
+    if let Some(outlines_id) = build_merged_outline(&mut result, &outline_parts, options) {
+        catalog_dict.set("Outlines", outlines_id);
+    }
+    if !dests_by_name.is_empty() {
+        let mut dests = lopdf::Dictionary::new();
+        for (name, dest) in dests_by_name {
+            dests.set(name, dest);
+        }
+        catalog_dict.set("Dests", result.add_object(dests));
+    }
+    if !dests_by_string.is_empty() {
+        let tree_id = result.add_object(build_name_tree(dests_by_string));
+        catalog_dict.set("Names", dictionary! { "Dests" => tree_id });
+    }
+    if any_labels && keep_labels {
+        let nums: Vec<Object> = labels
+            .into_iter()
+            .flat_map(|(i, label)| [Object::Integer(i), label])
+            .collect();
+        catalog_dict.set("PageLabels", dictionary! { "Nums" => nums });
+    }
+    if let Some(mut form_dict) = form.dict {
+        form_dict.set("Fields", form.fields);
+        if !form.resources.is_empty() {
+            let resources = form
+                .resources
+                .into_iter()
+                .map(|(category, entries)| (category, Object::Dictionary(entries)))
+                .collect::<Dictionary>();
+            form_dict.set("DR", resources);
+        }
+        catalog_dict.set("AcroForm", result.add_object(form_dict));
+    }
     result
         .objects
         .insert(catalog_id, Object::Dictionary(catalog_dict));
@@ -80,490 +279,4821 @@ pub fn merge_pdf<P: AsRef<Path>>(documents: &[P], output: P) -> Result<(), PdfEr
     result.prune_objects();
 
     // Save
-    let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
-    result.save_to(&mut file)?;
-
-    Ok(())
+    save_document(&mut result, output.as_ref(), options.encryption.as_ref())
 }
 
-/// Splits a PDF into chunks of `pages_per_file`.
-/// `output_prefix`: e.g. "output_part" -> "output_part_1.pdf", "output_part_2.pdf"
-pub fn split_pdf<P: AsRef<Path>>(
-    input: P,
-    output_dir: P,
-    output_prefix: &str,
-    start_page: u32,
-    end_page: u32,
-) -> Result<Vec<std::path::PathBuf>, PdfError> {
-    let doc = Document::load(input.as_ref())?;
+/// The interactive forms of the merge inputs, combined.
+#[derive(Default)]
+struct MergedForm {
+    /// The first input's `/AcroForm`, whose other entries are kept
+    dict: Option<Dictionary>,
+    fields: Vec<Object>,
+    field_names: HashSet<Vec<u8>>,
+    /// Default resources (`/DR`) by category, e.g. `/Font`
+    resources: BTreeMap<Vec<u8>, Dictionary>,
+    /// Every default resource as it was resolved, to tell identical entries from clashes
+    resolved: HashMap<(Vec<u8>, Vec<u8>), Object>,
+}
 
-    // Validate range
-    let pages = doc.get_pages();
-    let mut sorted_pages: Vec<ObjectId> = pages.into_values().collect();
-    sorted_pages.sort();
+/// Adds the form of one merge input to `merged`. Top-level fields named like a field of an
+/// earlier input are renamed, since fields of the same name are one field with one value.
+/// Default resources that clash with an earlier input's are renamed too, and the default
+/// appearance (`/DA`) strings of this input are rewritten to use the new font names.
+fn merge_acro_form(doc: &mut Document, index: usize, merged: &mut MergedForm) {
+    let Some(form) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+        .cloned()
+    else {
+        return;
+    };
+    let mut fields = form
+        .get(b"Fields")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .cloned()
+        .unwrap_or_default();
 
-    if start_page == 0 || end_page as usize > sorted_pages.len() || start_page > end_page {
-        return Err(PdfError::InvalidPage(start_page));
+    let names: Vec<(Vec<u8>, Object)> = fields
+        .iter()
+        .filter_map(|field| {
+            let (_, field) = doc.dereference(field).ok()?;
+            let name = field.as_dict().ok()?.get(b"T").ok()?.as_str().ok()?;
+            Some((decode_text_string(name).into_bytes(), Object::Null))
+        })
+        .collect();
+    let renames = unique_names(&names, &mut merged.field_names, index);
+    for field in &mut fields {
+        let dict = match field {
+            Object::Reference(id) => doc.get_dictionary_mut(*id).ok(),
+            Object::Dictionary(dict) => Some(dict),
+            _ => None,
+        };
+        let Some(dict) = dict else {
+            continue;
+        };
+        let name = dict.get(b"T").and_then(Object::as_str).ok();
+        if let Some(new_name) = name.and_then(|n| renames.get(decode_text_string(n).as_bytes())) {
+            dict.set("T", encode_text_string(&String::from_utf8_lossy(new_name)));
+        }
     }
+    merged.fields.extend(fields);
 
-    let range_indices = (start_page as usize - 1)..(end_page as usize);
-    let target_page_ids = &sorted_pages[range_indices];
-
-    // Create a new document containing only these pages.
-    // We can Clone the doc, retain only selected pages, and prune.
-    // Cloning the whole doc structure is cheap (objects).
+    let mut font_renames: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    let resources = form
+        .get(b"DR")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+        .cloned()
+        .unwrap_or_default();
+    for (category, entries) in resources.iter() {
+        let Some(entries) = doc
+            .dereference(entries)
+            .ok()
+            .and_then(|(_, o)| o.as_dict().ok())
+        else {
+            continue;
+        };
+        let target = merged.resources.entry(category.clone()).or_default();
+        for (name, value) in entries.iter() {
+            let resolved = doc
+                .dereference(value)
+                .map(|(_, o)| o.clone())
+                .unwrap_or(Object::Null);
+            let mut new_name = name.clone();
+            let mut n = index + 1;
+            loop {
+                match merged.resolved.get(&(category.clone(), new_name.clone())) {
+                    None => {
+                        target.set(new_name.clone(), value.clone());
+                        merged
+                            .resolved
+                            .insert((category.clone(), new_name.clone()), resolved);
+                        break;
+                    }
+                    Some(existing) if *existing == resolved => break,
+                    Some(_) => {
+                        new_name = name.clone();
+                        new_name.extend_from_slice(format!("_{}", n).as_bytes());
+                        n += 1;
+                    }
+                }
+            }
+            if new_name != *name && category == b"Font" {
+                font_renames.insert(name.clone(), new_name);
+            }
+        }
+    }
+    if !font_renames.is_empty() {
+        for object in doc.objects.values_mut() {
+            let dict = match object {
+                Object::Dictionary(dict) => dict,
+                Object::Stream(stream) => &mut stream.dict,
+                _ => continue,
+            };
+            if let Ok(Object::String(da, _)) = dict.get_mut(b"DA") {
+                *da = rename_da_fonts(da, &font_renames);
+            }
+        }
+    }
 
-    // We need to rewrite the Pages tree to only include target_page_ids.
-    // High-level:
-    // 1. Get Catalog -> Pages.
-    // 2. Set "Kids" to only our target pages.
-    // 3. Update "Count".
-    // 4. Update "Parent" of those pages (if we created a new Pages root, but here we reuse).
+    match &mut merged.dict {
+        None => {
+            let mut dict = form;
+            dict.remove(b"Fields");
+            dict.remove(b"DR");
+            if let Ok(Object::String(da, _)) = dict.get_mut(b"DA") {
+                *da = rename_da_fonts(da, &font_renames);
+            }
+            merged.dict = Some(dict);
+        }
+        Some(dict) => {
+            if form
+                .get(b"NeedAppearances")
+                .and_then(Object::as_bool)
+                .unwrap_or(false)
+            {
+                dict.set("NeedAppearances", true);
+            }
+            let flags = |d: &Dictionary| d.get(b"SigFlags").and_then(Object::as_i64).unwrap_or(0);
+            if flags(&form) != 0 {
+                dict.set("SigFlags", flags(dict) | flags(&form));
+            }
+        }
+    }
+}
 
-    // Simplified approach used in lopdf examples:
-    // "compress" or "delete_pages".
-    // lopdf doesn't have a direct "keep_only_pages" method, but we can construct one.
+/// A default appearance string with the font names in `renames` replaced.
+fn rename_da_fonts(da: &[u8], renames: &HashMap<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+    let is_delimiter = |b: u8| b.is_ascii_whitespace() || b"()<>[]{}/%".contains(&b);
+    let mut out = Vec::with_capacity(da.len());
+    let mut i = 0;
+    while i < da.len() {
+        if da[i] == b'/' {
+            let end = (i + 1..da.len())
+                .find(|&j| is_delimiter(da[j]))
+                .unwrap_or(da.len());
+            out.push(b'/');
+            let name = &da[i + 1..end];
+            out.extend_from_slice(renames.get(name).map_or(name, Vec::as_slice));
+            i = end;
+        } else {
+            out.push(da[i]);
+            i += 1;
+        }
+    }
+    out
+}
 
-    // Let's go with the "Create New Doc" approach similar to merge, but from same source.
-    let mut result = doc.clone();
+/// Orders the selected pages of every input according to `mode`.
+fn interleave_pages(selections: Vec<Vec<ObjectId>>, mode: InterleaveMode) -> Vec<ObjectId> {
+    match mode {
+        InterleaveMode::Sequential => selections.into_iter().flatten().collect(),
+        InterleaveMode::Alternate => {
+            let longest = selections.iter().map(Vec::len).max().unwrap_or(0);
+            (0..longest)
+                .flat_map(|i| selections.iter().filter_map(move |pages| pages.get(i)))
+                .copied()
+                .collect()
+        }
+    }
+}
 
-    // doc.objects are already in result because of clone.
-    // result.max_id is correct.
+/// The outline of one merge input.
+struct OutlinePart {
+    title: String,
+    first_page: Option<ObjectId>,
+    top_items: Option<(ObjectId, ObjectId)>,
+}
 
-    // But we need to fix the Pages Tree.
-    // The easiest way is to build a new Pages Root and Catalog, point to the selected Page IDs, and Prune.
-    let pages_root_id = result.new_object_id();
-    let catalog_id = result.new_object_id();
+/// Chains the outlines of all merge inputs under a new `/Outlines` root.
+/// With `outline_per_file`, each input gets a top-level bookmark holding its own outline.
+fn build_merged_outline(
+    result: &mut Document,
+    parts: &[OutlinePart],
+    options: &MergeOptions,
+) -> Option<ObjectId> {
+    if !options.outline_per_file && parts.iter().all(|p| p.top_items.is_none()) {
+        return None;
+    }
+    let outlines_id = result.new_object_id();
 
-    // Update Parents
-    for page_id in target_page_ids {
-        if let Some(Object::Dictionary(dict)) = result.objects.get_mut(page_id) {
-            dict.set(b"Parent".to_vec(), Object::Reference(pages_root_id));
+    // (first, last) of every top-level run to link together
+    let mut top_level: Vec<(ObjectId, ObjectId)> = Vec::new();
+    for part in parts {
+        if options.outline_per_file {
+            let Some(first_page) = part.first_page else {
+                continue;
+            };
+            let mut item = dictionary! {
+                "Title" => encode_text_string(&part.title),
+                "Parent" => outlines_id,
+                "Dest" => vec![first_page.into(), "Fit".into()],
+            };
+            let item_id = result.new_object_id();
+            if let Some((first, last)) = part.top_items {
+                item.set("First", first);
+                item.set("Last", last);
+                item.set("Count", set_outline_parent(result, first, item_id) as i64);
+            }
+            result.objects.insert(item_id, Object::Dictionary(item));
+            top_level.push((item_id, item_id));
+        } else if let Some((first, last)) = part.top_items {
+            set_outline_parent(result, first, outlines_id);
+            top_level.push((first, last));
         }
     }
+    if top_level.is_empty() {
+        return None;
+    }
 
-    let pages_dict = dictionary! {
-        "Type" => "Pages",
-        "Count" => target_page_ids.len() as i32,
-        "Kids" => target_page_ids.iter().map(|&id| Object::Reference(id)).collect::<Vec<_>>(),
-    };
-    result
-        .objects
-        .insert(pages_root_id, Object::Dictionary(pages_dict));
+    for pair in top_level.windows(2) {
+        let (prev_last, next_first) = (pair[0].1, pair[1].0);
+        if let Ok(dict) = result.get_dictionary_mut(prev_last) {
+            dict.set("Next", next_first);
+        }
+        if let Ok(dict) = result.get_dictionary_mut(next_first) {
+            dict.set("Prev", prev_last);
+        }
+    }
+    if let Ok(dict) = result.get_dictionary_mut(top_level[0].0) {
+        dict.remove(b"Prev");
+    }
+    if let Ok(dict) = result.get_dictionary_mut(top_level[top_level.len() - 1].1) {
+        dict.remove(b"Next");
+    }
 
-    let catalog_dict = dictionary! {
-        "Type" => "Catalog",
-        "Pages" => Object::Reference(pages_root_id),
-    };
-    result
-        .objects
-        .insert(catalog_id, Object::Dictionary(catalog_dict));
-    result
-        .trailer
-        .set(b"Root".to_vec(), Object::Reference(catalog_id));
+    let mut count = 0;
+    let mut next = Some(top_level[0].0);
+    while let Some(id) = next {
+        count += 1;
+        next = result
+            .get_dictionary(id)
+            .and_then(|d| d.get(b"Next"))
+            .and_then(Object::as_reference)
+            .ok();
+        if count > 100_000 {
+            break;
+        }
+    }
 
-    result.prune_objects(); // Critical: Remove all the other pages and resources not used by our selected pages.
+    result.objects.insert(
+        outlines_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Outlines",
+            "First" => top_level[0].0,
+            "Last" => top_level[top_level.len() - 1].1,
+            "Count" => count,
+        }),
+    );
+    Some(outlines_id)
+}
 
-    let output_filename = format!("{}_{}-{}.pdf", output_prefix, start_page, end_page);
-    let output_path = output_dir.as_ref().join(output_filename);
+/// Points every item in the sibling chain starting at `first` to `parent`, returning the chain length.
+fn set_outline_parent(doc: &mut Document, first: ObjectId, parent: ObjectId) -> usize {
+    let mut visited = HashSet::new();
+    let mut next = Some(first);
+    while let Some(id) = next {
+        if !visited.insert(id) {
+            break;
+        }
+        let Ok(item) = doc.get_dictionary_mut(id) else {
+            break;
+        };
+        item.set("Parent", parent);
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    visited.len()
+}
 
-    let mut file = std::io::BufWriter::new(std::fs::File::create(&output_path)?);
-    result.save_to(&mut file)?;
+/// Picks a new name for every entry whose name was already used by an earlier input.
+fn unique_names(
+    entries: &[(Vec<u8>, Object)],
+    used: &mut HashSet<Vec<u8>>,
+    index: usize,
+) -> HashMap<Vec<u8>, Vec<u8>> {
+    let mut renames = HashMap::new();
+    for (name, _) in entries {
+        if used.insert(name.clone()) {
+            continue;
+        }
+        let mut n = index + 1;
+        let new_name = loop {
+            let mut candidate = name.clone();
+            candidate.extend_from_slice(format!("_{}", n).as_bytes());
+            if used.insert(candidate.clone()) {
+                break candidate;
+            }
+            n += 1;
+        };
+        renames.insert(name.clone(), new_name);
+    }
+    renames
+}
 
-    Ok(vec![output_path])
+/// Splits a PDF, writing pages `start_page..=end_page` into a single file.
+/// `output_prefix`: e.g. "output_part" -> "output_part_1-3.pdf"
+pub fn split_pdf<P: AsRef<Path>>(
+    input: P,
+    output_dir: P,
+    output_prefix: &str,
+    start_page: u32,
+    end_page: u32,
+) -> Result<Vec<std::path::PathBuf>, PdfError> {
+    split_pdf_by_ranges(
+        input,
+        output_dir,
+        output_prefix,
+        &format!("{}-{}", start_page, end_page),
+        None,
+        None,
+    )
 }
 
-// Wrapper for public export that matches the plan better if needed,
-// for now I'll redirect the public functions to these implementations.
+/// Splits a PDF into one file per group of a page-range expression
+/// (see [`parse_page_groups`]), e.g. "1-3,5,8-", "every 10 pages" or "odd,even".
+/// The input is loaded once and every group is extracted from it.
+/// `output_prefix`: e.g. "output_part" -> "output_part_1-3.pdf", "output_part_5.pdf"
+/// With `encryption` every output file is password protected.
+pub fn split_pdf_by_ranges<P: AsRef<Path>>(
+    input: P,
+    output_dir: P,
+    output_prefix: &str,
+    ranges: &str,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<Vec<std::path::PathBuf>, PdfError> {
+    let doc = load_document(input, password)?;
 
-pub use merge_pdf as merge;
-pub use split_pdf as split;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let groups = parse_page_groups(ranges, page_ids.len() as u32)?;
 
-pub fn images_to_pdf(
-    image_paths: &[std::path::PathBuf],
-    output_path: std::path::PathBuf,
-) -> Result<(), PdfError> {
-    let mut doc = Document::with_version("1.5");
-    let pages_id = doc.new_object_id();
-    let mut pages = dictionary! {
-        "Type" => "Pages",
-        "Count" => image_paths.len() as i32,
-        "Kids" => Vec::<Object>::new(),
-    };
+    let mut outputs = Vec::with_capacity(groups.len());
+    for group in groups {
+        let target_page_ids: Vec<ObjectId> = group
+            .pages
+            .iter()
+            .map(|&page| page_ids[page as usize - 1])
+            .collect();
 
-    let mut page_ids = Vec::new();
+        let mut result = extract_pages(&doc, &target_page_ids);
 
-    for path in image_paths {
-        // Load image
-        let img = image::open(path)
-            .map_err(|e| PdfError::Operation(format!("Failed to open image {:?}: {}", path, e)))?;
-        let (width, height) = (img.width(), img.height());
+        let output_filename = format!("{}_{}.pdf", output_prefix, group.label);
+        let output_path = output_dir.as_ref().join(output_filename);
+        save_document(&mut result, &output_path, encryption)?;
+        outputs.push(output_path);
+    }
 
-        // Convert to JPEG buffer for PDF embedding
-        let mut jpeg_data = Vec::new();
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg_data);
-        encoder.encode_image(&img).map_err(|e| {
-            PdfError::Operation(format!("Failed to encode image {:?}: {}", path, e))
-        })?;
+    Ok(outputs)
+}
 
-        // Create Image XObject
-        let image_stream = Stream::new(
-            dictionary! {
-                "Type" => "XObject",
-                "Subtype" => "Image",
-                "Width" => width as i32,
-                "Height" => height as i32,
-                "ColorSpace" => "DeviceRGB",
-                "BitsPerComponent" => 8,
-                "Filter" => "DCTDecode",
-            },
-            jpeg_data,
-        );
-        let image_id = doc.add_object(image_stream);
+/// Splits a PDF into one file per bookmark, using the document outline.
+/// Bookmarks up to `depth` levels deep (1 = top-level only) start a new section that runs
+/// until the next such bookmark. Pages before the first bookmark belong to the first section.
+/// Each file is named after its bookmark title, e.g. "Chapter 1.pdf". Names that repeat a title
+/// or an existing file in `output_dir` are numbered, e.g. "Chapter 1 (2).pdf", so no file is
+/// overwritten.
+/// With `encryption` every output file is password protected.
+pub fn split_pdf_by_bookmarks<P: AsRef<Path>>(
+    input: P,
+    output_dir: P,
+    depth: usize,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<Vec<std::path::PathBuf>, PdfError> {
+    let doc = load_document(input, password)?;
 
-        // Create Page
-        // Content stream to draw image filling the page: q width 0 0 height 0 0 cm /Im1 Do Q
-        let content_stream = Stream::new(
-            dictionary! {},
-            format!("q {} 0 0 {} 0 0 cm /Im1 Do Q", width, height)
-                .as_bytes()
-                .to_vec(),
-        );
-        let content_id = doc.add_object(content_stream);
+    let pages = doc.get_pages();
+    let page_numbers: BTreeMap<ObjectId, u32> = pages.iter().map(|(&n, &id)| (id, n)).collect();
+    let page_ids: Vec<ObjectId> = pages.into_values().collect();
 
-        let page_id = doc.add_object(dictionary! {
-            "Type" => "Page",
-            "Parent" => pages_id,
-            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
-            "Contents" => content_id,
-            "Resources" => dictionary! {
-                "XObject" => dictionary! {
-                    "Im1" => image_id,
-                },
-            },
-        });
+    // (first page, title) of every section, in page order
+    let mut sections: Vec<(u32, String)> = Vec::new();
+    for entry in read_outline(&doc) {
+        if entry.depth > depth.max(1) {
+            continue;
+        }
+        let Some(&page) = entry.page.and_then(|id| page_numbers.get(&id)) else {
+            continue;
+        };
+        sections.push((page, entry.title));
+    }
+    sections.sort_by_key(|(page, _)| *page);
+    // Several bookmarks on the same page only start one section
+    sections.dedup_by_key(|(page, _)| *page);
 
-        page_ids.push(Object::Reference(page_id));
+    if sections.is_empty() {
+        return Err(PdfError::Operation(
+            "PDF has no bookmarks pointing to its pages".to_string(),
+        ));
     }
+    sections[0].0 = 1;
 
-    pages.set("Kids", page_ids);
-    doc.objects.insert(pages_id, Object::Dictionary(pages));
+    let mut used_names = HashSet::new();
+    let mut outputs = Vec::with_capacity(sections.len());
+    for (i, (start, title)) in sections.iter().enumerate() {
+        let end = sections
+            .get(i + 1)
+            .map(|(next, _)| next - 1)
+            .unwrap_or(page_ids.len() as u32);
+        let target_page_ids = &page_ids[*start as usize - 1..end as usize];
 
-    let catalog_id = doc.add_object(dictionary! {
-        "Type" => "Catalog",
-        "Pages" => pages_id,
-    });
+        let base_name = sanitize_file_name(title);
+        let mut name = base_name.clone();
+        let mut n = 2;
+        while output_dir.as_ref().join(format!("{}.pdf", name)).exists()
+            || !used_names.insert(name.to_lowercase())
+        {
+            name = format!("{} ({})", base_name, n);
+            n += 1;
+        }
 
-    doc.trailer.set("Root", catalog_id);
-    doc.max_id = doc.objects.keys().max().copied().unwrap_or((0, 0)).0; // Update max_id
+        let mut result = extract_pages(&doc, target_page_ids);
+        let output_path = output_dir.as_ref().join(format!("{}.pdf", name));
+        save_document(&mut result, &output_path, encryption)?;
+        outputs.push(output_path);
+    }
 
-    doc.save(output_path).map(|_| ()).map_err(|e| e.into())
+    Ok(outputs)
 }
 
-pub fn get_page_count<P: AsRef<Path>>(input: P) -> Result<u32, PdfError> {
-    let doc = Document::load(input).map_err(PdfError::Parse)?;
-    Ok(doc.get_pages().len() as u32)
+/// One file produced by [`split_pdf_by_size`].
+#[derive(Debug, Clone)]
+pub struct SplitPart {
+    pub path: std::path::PathBuf,
+    /// 1-based, inclusive page range contained in this part.
+    pub first_page: u32,
+    pub last_page: u32,
+    /// Size of the written file in bytes.
+    pub size: u64,
 }
 
-pub fn reorder_pages<P: AsRef<Path>>(
+/// Fixed cost of a part: header, Catalog, Pages root, xref table and trailer.
+const PART_OVERHEAD_BYTES: usize = 512;
+
+/// Splits a PDF into consecutive parts that each stay under `max_bytes`.
+/// Pages are grouped greedily. Objects shared between pages (fonts, images, ...) are counted
+/// once per part, since every part gets its own copy of them after pruning.
+/// Every part is serialized in memory and checked against the budget before it is written;
+/// a single page that is larger than the budget on its own is written as its own part.
+/// With `encryption` every part is password protected; the check uses the encrypted size.
+pub fn split_pdf_by_size<P: AsRef<Path>>(
     input: P,
-    page_order: &[u32],
-    output: P,
-) -> Result<(), PdfError> {
-    let doc = Document::load(input).map_err(PdfError::Parse)?;
-    let pages = doc.get_pages();
-    let mut page_map = BTreeMap::new();
-    for (i, (_page_num, object_id)) in pages.iter().enumerate() {
-        page_map.insert(i as u32 + 1, *object_id);
+    output_dir: P,
+    output_prefix: &str,
+    max_bytes: u64,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<Vec<SplitPart>, PdfError> {
+    if max_bytes == 0 {
+        return Err(PdfError::Operation(
+            "Size limit must be positive".to_string(),
+        ));
     }
-
-    // Create a new list of page ObjectIds based on input order
-    let mut new_pages = Vec::new();
-    for &page_num in page_order {
-        if let Some(&object_id) = page_map.get(&page_num) {
-            new_pages.push(object_id);
-        }
+    let mut doc = load_document(input, password)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if page_ids.is_empty() {
+        return Err(PdfError::Operation("PDF has no pages".to_string()));
+    }
+    // Inherited resources must count towards every page that uses them
+    for &page_id in &page_ids {
+        inherit_page_attributes(&mut doc, page_id);
     }
 
-    if new_pages.is_empty() {
-        return Err(PdfError::Operation("No valid pages selected".to_string()));
+    let dependencies: Vec<HashSet<ObjectId>> = page_ids
+        .iter()
+        .map(|&id| page_dependencies(&doc, id))
+        .collect();
+    let mut object_sizes = HashMap::new();
+    for id in dependencies.iter().flatten() {
+        object_sizes
+            .entry(*id)
+            .or_insert_with(|| doc.objects.get(id).map(estimate_object_size).unwrap_or(0));
     }
 
-    // Create new document strategy:
-    // 1. Clone doc (expensive but safe) or manipulate in place?
-    // split_pdf approach: clone doc, then prune.
-    let mut result = doc.clone();
+    // (page index range, estimate budget), processed in page order
+    let mut pending: VecDeque<(std::ops::Range<usize>, f64)> = VecDeque::new();
+    pending.push_back((0..page_ids.len(), max_bytes as f64));
 
-    // Map old page IDs to new pages array
-    let catalog = result.catalog_mut().map_err(PdfError::Parse)?;
-    let pages_id = catalog
-        .get(b"Pages")
-        .and_then(|o| o.as_reference())
-        .map_err(PdfError::Parse)?;
-
-    // We need to rebuild the page tree. For simplicity in lopdf (and this specific task),
-    // we can often get away with just replacing the "Kids" of the root Pages object
-    // if the original PDF structure isn't too complex (nested page trees).
-    // However, split_pdf does a deep copy and prune.
-
-    // Let's try the simpler approach first: Re-assigning the whole Page Tree is hard.
-    // Instead, let's just make a new document from the selected pages.
-    // Actually, lopdf's `doc.extract_pages` is relevant if we had it, but we don't.
-    // Let's stick to the split_pdf logic: clone and modify.
-
-    // Core logic:
-    // 1. We have `new_pages` (ObjectIds).
-    // 2. We need to reset the `Pages` dictionary to point to these.
-
-    let pages_dict = result
-        .get_object_mut(pages_id)
-        .and_then(|o| o.as_dict_mut())
-        .map_err(PdfError::Parse)?;
-    pages_dict.set("Count", new_pages.len() as i32);
-    pages_dict.set(
-        "Kids",
-        new_pages
-            .iter()
-            .map(|&id| Object::Reference(id))
-            .collect::<Vec<_>>(),
-    ); // Assuming flat tree for simplicity or that we just updated the root.
-       // Note: If the original PDF has a nested page tree, this simply updating "Kids" on the root node
-       // might break navigation if the Kids were intermediate nodes.
-       // A robust solution is complex.
-       // BUT, `lopdf` Documentation says `get_pages` returns all page objects.
-       // If we just set "Kids" to all leaf page objects, we flatten the tree. This is valid PDF (mostly).
-
-    // Update Parent reference for all pages to point to the root Pages object
-    for page_id in &new_pages {
-        if let Ok(page) = result.get_object_mut(*page_id) {
-            if let Ok(dict) = page.as_dict_mut() {
-                dict.set("Parent", Object::Reference(pages_id));
-                // Also remove "Next", "Prev" if they exist?? Not usually strict for pages.
-            }
+    let mut outputs = Vec::new();
+    while let Some((range, budget)) = pending.pop_front() {
+        let groups = group_pages_by_size(&dependencies, &object_sizes, range, budget);
+        let mut groups = groups.into_iter();
+        let Some(group) = groups.next() else {
+            continue;
+        };
+        // Only the first group is written now, the rest is re-queued in order
+        for rest in groups.rev() {
+            pending.push_front((rest, max_bytes as f64));
         }
-    }
 
-    // Important: We need to remove pages that are NOT in `new_pages`?
-    // `prune_objects` will remove objects not reachable from Catalog.
-    // Since we updated Pages root to only point to `new_pages`, the others are now orphans.
-    result.prune_objects();
+        let mut part = extract_pages(&doc, &page_ids[group.clone()]);
+        if let Some(encryption) = encryption {
+            encrypt_document(&mut part, encryption)?;
+        }
+        let mut buffer = Vec::new();
+        part.save_to(&mut buffer)?;
 
-    // Fix object renumbering (optional but good)
-    result.save(output).map(|_| ()).map_err(PdfError::Io)
-}
+        if buffer.len() as u64 > max_bytes && group.len() > 1 {
+            // The estimate was too optimistic: retry this group with a proportionally tighter budget
+            let tighter = budget * (max_bytes as f64 / buffer.len() as f64) * 0.95;
+            pending.push_front((group, tighter));
+            continue;
+        }
 
-pub fn pdf_to_word<P: AsRef<Path>>(input: P, output: P) -> Result<(), PdfError> {
-    use docx_rs::{Docx, Paragraph, Run};
-    use std::fs::File;
+        let (first_page, last_page) = (group.start as u32 + 1, group.end as u32);
+        let label = if first_page == last_page {
+            first_page.to_string()
+        } else {
+            format!("{}-{}", first_page, last_page)
+        };
+        let output_path = output_dir
+            .as_ref()
+            .join(format!("{}_{}.pdf", output_prefix, label));
+        std::fs::write(&output_path, &buffer)?;
 
-    let doc = Document::load(input).map_err(PdfError::Parse)?;
-    let mut docx = Docx::new();
+        outputs.push(SplitPart {
+            path: output_path,
+            first_page,
+            last_page,
+            size: buffer.len() as u64,
+        });
+    }
 
-    for page_id in doc.get_pages().values() {
-        let content_data = doc
-            .get_page_content(*page_id)
-            .unwrap_or_else(|_| Vec::new());
-        let content = Content::decode(&content_data).unwrap_or(Content { operations: vec![] });
+    Ok(outputs)
+}
 
-        let mut text_buffer = String::new();
+/// Greedily groups the pages of `range` so that the estimated size of each group fits `budget`.
+fn group_pages_by_size(
+    dependencies: &[HashSet<ObjectId>],
+    object_sizes: &HashMap<ObjectId, usize>,
+    range: std::ops::Range<usize>,
+    budget: f64,
+) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = range.start;
+    let mut included: HashSet<ObjectId> = HashSet::new();
+    let mut size = PART_OVERHEAD_BYTES;
 
-        for operation in content.operations {
-            match operation.operator.as_str() {
-                "Tj" | "TJ" | "'" | "\"" => {
-                    // Collect text from operands
-                    for operand in operation.operands {
-                        if let Ok(text) = extract_text_from_object(&operand) {
-                            text_buffer.push_str(&text);
-                        }
-                    }
-                }
-                "ET" => {
-                    // End of text object, could add a newline or space
-                    text_buffer.push(' ');
-                }
-                _ => {}
-            }
+    for i in range.clone() {
+        let added: usize = dependencies[i]
+            .difference(&included)
+            .map(|id| object_sizes.get(id).copied().unwrap_or(0))
+            .sum();
+        if i > start && (size + added) as f64 > budget {
+            groups.push(start..i);
+            start = i;
+            included.clear();
+            size = PART_OVERHEAD_BYTES
+                + dependencies[i]
+                    .iter()
+                    .map(|id| object_sizes[id])
+                    .sum::<usize>();
+        } else {
+            size += added;
         }
+        included.extend(dependencies[i].iter().copied());
+    }
+    if start < range.end {
+        groups.push(start..range.end);
+    }
+    groups
+}
 
-        // Add text to docx as a paragraph (very simple mapping)
-        // Split by newlines to create separate paragraphs if needed,
-        // but PDF text is often fragmented.
-        if !text_buffer.trim().is_empty() {
-            docx = docx.add_paragraph(Paragraph::new().add_run(Run::new().add_text(text_buffer)));
+/// Collects every object reachable from a page, without following `/Parent` back up the tree
+/// or links into other pages, which `extract_pages` drops when those pages are left out.
+fn page_dependencies(doc: &Document, page_id: ObjectId) -> HashSet<ObjectId> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![page_id];
+    while let Some(id) = stack.pop() {
+        if seen.contains(&id) {
+            continue;
+        }
+        let object = doc.objects.get(&id);
+        let is_other_page = id != page_id
+            && object
+                .and_then(|o| o.as_dict().ok())
+                .and_then(|dict| dict.get(b"Type").and_then(Object::as_name).ok())
+                == Some(b"Page".as_slice());
+        if is_other_page {
+            continue;
+        }
+        seen.insert(id);
+        if let Some(object) = object {
+            collect_references(object, &mut stack);
         }
-        // Add a page break? docx-rs doesn't easily support page breaks in this simple API manner
-        // without more verbose code, but separate paragraphs are a start.
     }
-
-    let file = File::create(output).map_err(|e| PdfError::Io(e.into()))?;
-    docx.build()
-        .pack(file)
-        .map_err(|e| PdfError::Operation(format!("Docx Error: {}", e)))?;
-
-    Ok(())
+    seen
 }
 
-pub fn pdf_to_images<P: AsRef<Path>>(
-    input: P,
-    output_dir: P,
-    pdfium_path: P,
+fn collect_references(object: &Object, refs: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => refs.push(*id),
+        Object::Array(items) => items.iter().for_each(|o| collect_references(o, refs)),
+        Object::Dictionary(dict) => dict
+            .iter()
+            .filter(|(key, _)| key.as_slice() != b"Parent")
+            .for_each(|(_, o)| collect_references(o, refs)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter()
+            .filter(|(key, _)| key.as_slice() != b"Parent")
+            .for_each(|(_, o)| collect_references(o, refs)),
+        _ => {}
+    }
+}
+
+/// Rough serialized size of an indirect object, including its `obj`/`endobj` and xref entry.
+fn estimate_object_size(object: &Object) -> usize {
+    fn inline_size(object: &Object) -> usize {
+        match object {
+            Object::Null | Object::Boolean(_) => 5,
+            Object::Integer(_) | Object::Real(_) => 8,
+            Object::Name(name) => name.len() + 2,
+            Object::String(bytes, _) => bytes.len() + 3,
+            Object::Reference(_) => 10,
+            Object::Array(items) => 2 + items.iter().map(inline_size).sum::<usize>(),
+            Object::Dictionary(dict) => dict_size(dict),
+            Object::Stream(stream) => dict_size(&stream.dict) + stream.content.len() + 20,
+        }
+    }
+    fn dict_size(dict: &lopdf::Dictionary) -> usize {
+        4 + dict
+            .iter()
+            .map(|(key, value)| key.len() + 2 + inline_size(value))
+            .sum::<usize>()
+    }
+    inline_size(object) + 40
+}
+
+/// Page attributes that may be inherited from an ancestor `/Pages` node.
+const INHERITABLE_PAGE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Copies inheritable attributes the page does not set itself from its nearest ancestor
+/// that does, so the page keeps them once it is moved under a different `/Pages` node.
+/// Must run while the page still points at its original parent.
+fn inherit_page_attributes(doc: &mut Document, page_id: ObjectId) {
+    let Ok(page) = doc.get_dictionary(page_id) else {
+        return;
+    };
+    let mut inherited = Vec::new();
+    for key in INHERITABLE_PAGE_ATTRIBUTES {
+        if page.has(key) {
+            continue;
+        }
+        let mut visited = HashSet::new();
+        let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+        while let Some(parent_id) = parent {
+            if !visited.insert(parent_id) {
+                break;
+            }
+            let Ok(node) = doc.get_dictionary(parent_id) else {
+                break;
+            };
+            if let Ok(value) = node.get(key) {
+                inherited.push((key, value.clone()));
+                break;
+            }
+            parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+        }
+    }
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+        for (key, value) in inherited {
+            page.set(key, value);
+        }
+    }
+}
+
+/// Replaces the page tree of `doc` with a new, flat `/Pages` root holding `page_ids` in order,
+/// and returns its id. Inherited attributes are materialized onto every page before it is
+/// re-parented, and intermediate `/Pages` nodes of the old tree are left unreferenced.
+/// Every function that restructures pages (split, merge, reorder) goes through here.
+/// A page listed more than once gets a copy of its page dictionary, and of its annotations,
+/// for every repeat.
+fn rebuild_page_tree(doc: &mut Document, page_ids: &[ObjectId]) -> ObjectId {
+    let pages_root_id = doc.new_object_id();
+
+    let mut kids = Vec::with_capacity(page_ids.len());
+    let mut placed = HashSet::new();
+    for &page_id in page_ids {
+        inherit_page_attributes(doc, page_id);
+        if placed.insert(page_id) {
+            kids.push(page_id);
+        } else if let Ok(page) = doc.get_object(page_id).cloned() {
+            let copy_id = doc.add_object(page);
+            copy_page_annotations(doc, page_id, copy_id);
+            kids.push(copy_id);
+        }
+    }
+
+    // Update Parents
+    for page_id in &kids {
+        if let Some(Object::Dictionary(dict)) = doc.objects.get_mut(page_id) {
+            dict.set(b"Parent".to_vec(), Object::Reference(pages_root_id));
+        }
+    }
+
+    let pages_dict = dictionary! {
+        "Type" => "Pages",
+        "Count" => kids.len() as i32,
+        "Kids" => kids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+    };
+    doc.objects
+        .insert(pages_root_id, Object::Dictionary(pages_dict));
+    pages_root_id
+}
+
+/// Field entries of a form field dictionary, as opposed to those of its widget annotation.
+const FIELD_KEYS: [&[u8]; 12] = [
+    b"FT", b"T", b"TU", b"TM", b"Ff", b"V", b"DV", b"Opt", b"TI", b"I", b"MaxLen", b"Lock",
+];
+
+/// Gives the page copy `copy_id` its own copies of the annotations of `page_id`, since an
+/// annotation belongs to exactly one page (`/P`). Popups are re-linked to the copied markup
+/// annotations. A copied widget becomes one more widget of the same field, so both pages
+/// show and edit one value; a field that is its own widget is first split into a field and
+/// a widget on `page_id`.
+fn copy_page_annotations(doc: &mut Document, page_id: ObjectId, copy_id: ObjectId) {
+    let annots = match doc
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"Annots"))
+    {
+        Ok(Object::Array(annots)) => annots.clone(),
+        Ok(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(annots)) => annots.clone(),
+            _ => return,
+        },
+        _ => return,
+    };
+
+    let mut originals = Vec::with_capacity(annots.len());
+    let mut copies = HashMap::new();
+    for annot in annots {
+        let Ok(id) = annot.as_reference() else {
+            continue;
+        };
+        let id = split_field_widget(doc, id, page_id);
+        let Ok(mut copy) = doc.get_dictionary(id).cloned() else {
+            continue;
+        };
+        copy.set("P", copy_id);
+        copies.insert(id, doc.add_object(copy));
+        originals.push(id);
+    }
+
+    for id in &originals {
+        let copy = copies[id];
+        let Ok(dict) = doc.get_dictionary_mut(copy) else {
+            continue;
+        };
+        for key in [b"Popup".as_slice(), b"IRT", b"Parent"] {
+            if let Ok(target) = dict.get(key).and_then(Object::as_reference) {
+                if let Some(&new_target) = copies.get(&target) {
+                    dict.set(key, new_target);
+                }
+            }
+        }
+        let is_widget = dict.get(b"Subtype").ok() == Some(&Object::Name(b"Widget".to_vec()));
+        if let (true, Ok(parent)) = (
+            is_widget,
+            dict.get(b"Parent").and_then(Object::as_reference),
+        ) {
+            add_field_kid(doc, parent, copy);
+        }
+    }
+
+    let annots: Vec<Object> = originals
+        .iter()
+        .map(|id| Object::Reference(copies[id]))
+        .collect();
+    if let Ok(copy) = doc.get_dictionary_mut(copy_id) {
+        copy.set("Annots", annots);
+    }
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+        page.set(
+            "Annots",
+            originals
+                .into_iter()
+                .map(Object::Reference)
+                .collect::<Vec<_>>(),
+        );
+    }
+}
+
+/// If the annotation `id` is a widget merged with its field dictionary, moves the widget
+/// into a new kid of the field and returns the kid's id; otherwise returns `id`.
+/// The field keeps its object id, so references to it from `/AcroForm` stay valid.
+fn split_field_widget(doc: &mut Document, id: ObjectId, page_id: ObjectId) -> ObjectId {
+    let Ok(dict) = doc.get_dictionary(id) else {
+        return id;
+    };
+    let is_widget = dict.get(b"Subtype").ok() == Some(&Object::Name(b"Widget".to_vec()));
+    if !is_widget || !FIELD_KEYS.iter().any(|key| dict.has(key)) {
+        return id;
+    }
+
+    let mut widget = dict.clone();
+    for key in FIELD_KEYS {
+        widget.remove(key);
+    }
+    widget.remove(b"Kids");
+    widget.set("Parent", id);
+    if !widget.has(b"P") {
+        widget.set("P", page_id);
+    }
+    let widget_keys: Vec<Vec<u8>> = widget
+        .iter()
+        .map(|(key, _)| key.clone())
+        .filter(|key| !matches!(key.as_slice(), b"Parent" | b"DA" | b"Q" | b"AA"))
+        .collect();
+    let widget_id = doc.add_object(widget);
+
+    if let Ok(field) = doc.get_dictionary_mut(id) {
+        for key in widget_keys {
+            field.remove(&key);
+        }
+        field.set("Kids", vec![Object::Reference(widget_id)]);
+    }
+    widget_id
+}
+
+/// Appends the widget `kid` to the `/Kids` of the field `field_id`.
+fn add_field_kid(doc: &mut Document, field_id: ObjectId, kid: ObjectId) {
+    let mut kids = match doc
+        .get_dictionary(field_id)
+        .and_then(|field| field.get(b"Kids"))
+    {
+        Ok(Object::Array(kids)) => kids.clone(),
+        Ok(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(kids)) => kids.clone(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    kids.push(Object::Reference(kid));
+    if let Ok(field) = doc.get_dictionary_mut(field_id) {
+        field.set("Kids", kids);
+    }
+}
+
+/// Builds a copy of `doc` whose page tree holds only `page_ids`, in the given order.
+/// A new Pages root and Catalog are created and everything unreachable is pruned; links to
+/// the pages left out are dropped first so they cannot keep those pages alive.
+fn extract_pages(doc: &Document, page_ids: &[ObjectId]) -> Document {
+    let mut result = doc.clone();
+
+    let kept: HashSet<ObjectId> = page_ids.iter().copied().collect();
+    let removed: HashSet<ObjectId> = doc
+        .get_pages()
+        .into_values()
+        .filter(|id| !kept.contains(id))
+        .collect();
+    if !removed.is_empty() {
+        drop_destinations_to(&mut result, &removed);
+    }
+
+    let pages_root_id = rebuild_page_tree(&mut result, page_ids);
+    let catalog_id = result.new_object_id();
+
+    let catalog_dict = dictionary! {
+        "Type" => "Catalog",
+        "Pages" => Object::Reference(pages_root_id),
+    };
+    result
+        .objects
+        .insert(catalog_id, Object::Dictionary(catalog_dict));
+    result
+        .trailer
+        .set(b"Root".to_vec(), Object::Reference(catalog_id));
+
+    result.prune_objects(); // Critical: Remove all the other pages and resources not used by our selected pages.
+    result
+}
+
+fn save_document(
+    doc: &mut Document,
+    path: &Path,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<(), PdfError> {
+    if let Some(encryption) = encryption {
+        encrypt_document(doc, encryption)?;
+    }
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    doc.save_to(&mut file)?;
+    Ok(())
+}
+
+// Wrapper for public export that matches the plan better if needed,
+// for now I'll redirect the public functions to these implementations.
+
+pub use merge_pdf as merge;
+pub use merge_pdf_pages as merge_pages;
+pub use merge_pdf_with_options as merge_with_options;
+pub use rotate_pages_to_orientation as rotate_to_orientation;
+pub use split_pdf as split;
+pub use split_pdf_by_bookmarks as split_bookmarks;
+pub use split_pdf_by_ranges as split_ranges;
+pub use split_pdf_by_size as split_size;
+
+/// Paper size of the pages written by [`images_to_pdf_with_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PageSize {
+    /// Each page takes the size of its image at [`ImagePdfOptions::dpi`], plus the margins.
+    Image,
+    #[default]
+    A4,
+    A3,
+    A5,
+    Letter,
+    Legal,
+    /// Width and height in points (1/72 inch).
+    Custom {
+        width: f32,
+        height: f32,
+    },
+}
+
+impl PageSize {
+    /// Width and height in points as given, portrait for the standard sizes. `None` for
+    /// [`PageSize::Image`].
+    pub fn dimensions(self) -> Option<(f32, f32)> {
+        let mm = |width: f32, height: f32| (width * 72.0 / 25.4, height * 72.0 / 25.4);
+        match self {
+            PageSize::Image => None,
+            PageSize::A4 => Some(mm(210.0, 297.0)),
+            PageSize::A3 => Some(mm(297.0, 420.0)),
+            PageSize::A5 => Some(mm(148.0, 210.0)),
+            PageSize::Letter => Some((612.0, 792.0)),
+            PageSize::Legal => Some((612.0, 1008.0)),
+            PageSize::Custom { width, height } => Some((width, height)),
+        }
+    }
+}
+
+/// How [`images_to_pdf_with_options`] sizes each image within the page margins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Scale the image to the largest size that shows all of it.
+    #[default]
+    Fit,
+    /// Scale the image to cover the whole area, cropping what overflows.
+    Fill,
+    /// Keep the image at its size at [`ImagePdfOptions::dpi`], only scaling it down if it
+    /// does not fit.
+    Center,
+}
+
+/// Cells that [`images_to_pdf_with_options`] places images in, several to a page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageGrid {
+    pub rows: u32,
+    pub columns: u32,
+    /// Space between neighboring cells, in points.
+    pub gutter: f32,
+}
+
+/// Colors of the pages after [`ScanCleanup`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScanColor {
+    /// Keep the colors of the photo.
+    #[default]
+    Color,
+    Grayscale,
+    /// Black and white, stored as CCITT Group 4 fax data, the smallest by far for text.
+    /// Pixels darker than `threshold` (0 to 255) turn black; `None` picks the threshold for
+    /// each pixel from its surroundings, which copes with shadows and uneven light.
+    Bilevel {
+        threshold: Option<u8>,
+    },
+}
+
+/// Preprocessing for photographed paper documents, for [`ImagePdfOptions::scan`]. The steps
+/// run in the order of the fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanCleanup {
+    /// Find the sheet of paper in the photo, cut it out and straighten its perspective into a
+    /// rectangle. Photos without a sheet that stands out lighter from its surroundings are
+    /// left as they are.
+    pub detect_edges: bool,
+    /// Level lines of text that are tilted by up to 10 degrees.
+    pub deskew: bool,
+    /// Cut off uniform borders, such as dark edges around the sheet and blank margins, so the
+    /// content fills the page.
+    pub trim_borders: bool,
+    pub color: ScanColor,
+}
+
+impl Default for ScanCleanup {
+    /// Every step, keeping the colors.
+    fn default() -> Self {
+        ScanCleanup {
+            detect_edges: true,
+            deskew: true,
+            trim_borders: true,
+            color: ScanColor::default(),
+        }
+    }
+}
+
+/// JPEG quality that cleaned up color and gray pages are stored at
+const SCAN_QUALITY: u8 = 85;
+
+/// Options for [`images_to_pdf_with_options`].
+#[derive(Debug, Clone)]
+pub struct ImagePdfOptions {
+    pub page_size: PageSize,
+    /// Orientation of every page. `None` turns each page to match its image, or with a
+    /// [`grid`](Self::grid), makes pages landscape if the grid has more columns than rows. Not
+    /// used with [`PageSize::Image`].
+    pub orientation: Option<Orientation>,
+    /// Blank space on every side of the page, in points. It must leave room on the page.
+    pub margin: f32,
+    /// How images are sized within the page margins, or within their cell of the grid.
+    pub fit: ImageFit,
+    /// Pixels per inch of the images, which sets their size for [`PageSize::Image`] and
+    /// [`ImageFit::Center`].
+    pub dpi: f32,
+    /// Lay out images in a grid, filled left to right, top to bottom, starting a new page
+    /// when it is full. `None` gives every image its own page. Grid pages cannot take their
+    /// size from an image, so [`PageSize::Image`] makes them A4.
+    pub grid: Option<ImageGrid>,
+    /// Write the file name under every image; frames of a multi-frame file are numbered.
+    /// Latin, Chinese, Japanese, Korean, Greek and Cyrillic names are shown as they are;
+    /// characters of other scripts, such as Arabic, become "?".
+    pub captions: bool,
+    /// Clean up photos of paper documents before placing them. Cleaned images are stored as
+    /// JPEG, or as Group 4 fax data in black and white, rather than without loss. `None`
+    /// places images as they are.
+    pub scan: Option<ScanCleanup>,
+}
+
+impl Default for ImagePdfOptions {
+    /// Images fitted on A4 pages turned to match them, without margins, at 300 DPI.
+    fn default() -> Self {
+        ImagePdfOptions {
+            page_size: PageSize::default(),
+            orientation: None,
+            margin: 0.0,
+            fit: ImageFit::default(),
+            dpi: 300.0,
+            grid: None,
+            captions: false,
+            scan: None,
+        }
+    }
+}
+
+/// Writes one A4 page per image. Images are embedded without loss: JPEG files as they are,
+/// other formats as compressed samples in their own color space and bit depth, with
+/// transparency kept as a soft mask. Photos are turned upright as their EXIF orientation
+/// says, and every page of a TIFF and every frame of an animated GIF, WebP or PNG gets its
+/// own page. JPEG, PNG, BMP, GIF, WebP and TIFF files are read; HEIC is not supported.
+pub fn images_to_pdf(
+    image_paths: &[std::path::PathBuf],
+    output_path: std::path::PathBuf,
+) -> Result<(), PdfError> {
+    images_to_pdf_with_options(image_paths, output_path, &ImagePdfOptions::default())
+}
+
+/// Writes the images on pages sized and laid out according to `options`, one per page or
+/// several in a grid. Images are embedded as in [`images_to_pdf`].
+pub fn images_to_pdf_with_options(
+    image_paths: &[std::path::PathBuf],
+    output_path: std::path::PathBuf,
+    options: &ImagePdfOptions,
+) -> Result<(), PdfError> {
+    if !options.dpi.is_finite() || options.dpi <= 0.0 {
+        return Err(PdfError::Operation(format!(
+            "Invalid resolution: {} DPI",
+            options.dpi
+        )));
+    }
+    if let PageSize::Custom { width, height } = options.page_size {
+        if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+            return Err(PdfError::Operation(format!(
+                "Invalid page size: {} x {} pt",
+                width, height
+            )));
+        }
+    }
+    let margin = options.margin;
+    let no_room = options
+        .page_size
+        .dimensions()
+        .is_some_and(|(width, height)| 2.0 * margin >= width.min(height));
+    if !margin.is_finite() || margin < 0.0 || no_room {
+        return Err(PdfError::Operation(format!(
+            "Invalid margin: {} pt",
+            margin
+        )));
+    }
+    let (rows, columns, gutter) = match options.grid {
+        Some(grid) => (grid.rows.max(1), grid.columns.max(1), grid.gutter.max(0.0)),
+        None => (1, 1, 0.0),
+    };
+    // Height of the caption line under each image
+    let caption_band = if options.captions {
+        CAPTION_SIZE * 1.6
+    } else {
+        0.0
+    };
+
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let mut pages = dictionary! {
+        "Type" => "Pages",
+        "Kids" => Vec::<Object>::new(),
+    };
+
+    let mut page_ids = Vec::new();
+    let mut caption_fonts = CaptionFonts::default();
+
+    // Load images, one each for every page or frame of a file, with their captions
+    let mut images = Vec::new();
+    for path in image_paths {
+        let data = std::fs::read(path).map_err(PdfError::Io)?;
+        let frames = match options.scan {
+            Some(cleanup) => decode_image_file(&data).map(|frames| {
+                frames
+                    .into_iter()
+                    .map(|image| {
+                        let image = scan::clean(image, &cleanup);
+                        match cleanup.color {
+                            ScanColor::Bilevel { .. } => ImageXObject::from_image(image),
+                            _ => ImageXObject::from_photo(image, SCAN_QUALITY),
+                        }
+                    })
+                    .collect()
+            }),
+            None => ImageXObject::from_file_data(&data),
+        }
+        .map_err(|e| PdfError::Operation(format!("Failed to open image {:?}: {}", path, e)))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let count = frames.len();
+        images.extend(frames.into_iter().enumerate().map(|(i, image)| {
+            let caption = if count > 1 {
+                format!("{} ({})", name, i + 1)
+            } else {
+                name.clone()
+            };
+            (image, caption)
+        }));
+    }
+
+    // Size of an image at its resolution, as displayed, in points
+    let natural_size = |image: &ImageXObject| {
+        let (width, height) = image.display_size();
+        (
+            width as f32 * 72.0 / options.dpi,
+            height as f32 * 72.0 / options.dpi,
+        )
+    };
+
+    let mut images = images.into_iter().peekable();
+    while let Some((first, _)) = images.peek() {
+        // Size the page
+        let (page_width, page_height) = match (options.page_size.dimensions(), options.grid) {
+            (None, None) => {
+                let natural = natural_size(first);
+                (
+                    natural.0 + 2.0 * margin,
+                    natural.1 + 2.0 * margin + caption_band,
+                )
+            }
+            (size, grid) => {
+                let (width, height) = size.unwrap_or_else(|| PageSize::A4.dimensions().unwrap());
+                let landscape = match (options.orientation, grid) {
+                    (Some(orientation), _) => orientation == Orientation::Landscape,
+                    (None, Some(grid)) => grid.columns > grid.rows,
+                    (None, None) => {
+                        let natural = natural_size(first);
+                        natural.0 > natural.1
+                    }
+                };
+                if landscape == (width < height) {
+                    (height, width)
+                } else {
+                    (width, height)
+                }
+            }
+        };
+
+        // Split the area within the margins into cells
+        let area = (
+            (page_width - 2.0 * margin).max(1.0),
+            (page_height - 2.0 * margin).max(1.0),
+        );
+        let cell = (
+            ((area.0 - (columns - 1) as f32 * gutter) / columns as f32).max(1.0),
+            ((area.1 - (rows - 1) as f32 * gutter) / rows as f32).max(1.0),
+        );
+
+        let mut operations = Vec::new();
+        let mut xobjects = Dictionary::new();
+        for (index, (image, caption)) in images.by_ref().take((rows * columns) as usize).enumerate()
+        {
+            let (column, row) = (index as u32 % columns, index as u32 / columns);
+            let cell_x = margin + column as f32 * (cell.0 + gutter);
+            let cell_y = page_height - margin - (row + 1) as f32 * cell.1 - row as f32 * gutter;
+            // The image goes above the caption
+            let frame = (cell.0, (cell.1 - caption_band).max(1.0));
+            let frame_y = cell_y + cell.1 - frame.1;
+
+            // Place the image within its frame
+            let natural = natural_size(&image);
+            let fit_scale = (frame.0 / natural.0).min(frame.1 / natural.1);
+            let scale = match options.fit {
+                ImageFit::Fit => fit_scale,
+                ImageFit::Fill => (frame.0 / natural.0).max(frame.1 / natural.1),
+                ImageFit::Center => fit_scale.min(1.0),
+            };
+            let (width, height) = (natural.0 * scale, natural.1 * scale);
+            let (x, y) = (
+                cell_x + (frame.0 - width) / 2.0,
+                frame_y + (frame.1 - height) / 2.0,
+            );
+            let placement = image.placement(x, y, width, height);
+
+            // Create Image XObject
+            let name = format!("Im{}", index + 1);
+            xobjects.set(name.as_bytes(), image.add_to(&mut doc));
+
+            // Clip to the frame and draw the image there
+            operations.push(Operation::new("q", vec![]));
+            operations.push(Operation::new(
+                "re",
+                vec![
+                    cell_x.into(),
+                    frame_y.into(),
+                    frame.0.into(),
+                    frame.1.into(),
+                ],
+            ));
+            operations.push(Operation::new("W", vec![]));
+            operations.push(Operation::new("n", vec![]));
+            operations.push(Operation::new(
+                "cm",
+                placement.iter().map(|&v| v.into()).collect(),
+            ));
+            operations.push(Operation::new("Do", vec![Object::Name(name.into_bytes())]));
+            operations.push(Operation::new("Q", vec![]));
+
+            if options.captions && !caption.is_empty() {
+                let (font, text, text_width) = caption_fonts.encode(&mut doc, &caption);
+                // Right under the image, shrunk to the cell's width if too long
+                let band_y = y.max(frame_y) - caption_band;
+                let size = CAPTION_SIZE.min(cell.0 / text_width).max(1.0);
+                let text_x = cell_x + ((cell.0 - text_width * size) / 2.0).max(0.0);
+                let text_y = band_y + (caption_band - size) / 2.0 + size * 0.2;
+                operations.push(Operation::new("q", vec![]));
+                operations.push(Operation::new(
+                    "re",
+                    vec![
+                        cell_x.into(),
+                        band_y.into(),
+                        cell.0.into(),
+                        caption_band.into(),
+                    ],
+                ));
+                operations.push(Operation::new("W", vec![]));
+                operations.push(Operation::new("n", vec![]));
+                operations.push(Operation::new("BT", vec![]));
+                operations.push(Operation::new(
+                    "Tf",
+                    vec![Object::Name(font.to_vec()), size.into()],
+                ));
+                operations.push(Operation::new("Td", vec![text_x.into(), text_y.into()]));
+                operations.push(Operation::new(
+                    "Tj",
+                    vec![Object::String(text, lopdf::StringFormat::Hexadecimal)],
+                ));
+                operations.push(Operation::new("ET", vec![]));
+                operations.push(Operation::new("Q", vec![]));
+            }
+        }
+
+        // Create Page
+        let content = Content { operations }
+            .encode()
+            .map_err(|e| PdfError::Operation(format!("Failed to write page content: {}", e)))?;
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+
+        let mut resources = dictionary! {
+            "XObject" => xobjects,
+        };
+        if let Some(fonts) = caption_fonts.resources() {
+            resources.set("Font", fonts);
+        }
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), page_width.into(), page_height.into()],
+            "Contents" => content_id,
+            "Resources" => resources,
+        });
+
+        page_ids.push(Object::Reference(page_id));
+    }
+
+    pages.set("Count", page_ids.len() as i32);
+    pages.set("Kids", page_ids);
+    doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+
+    doc.trailer.set("Root", catalog_id);
+    doc.max_id = doc.objects.keys().max().copied().unwrap_or((0, 0)).0; // Update max_id
+
+    doc.save(output_path).map(|_| ()).map_err(|e| e.into())
+}
+
+/// Font size of image captions, in points
+const CAPTION_SIZE: f32 = 9.0;
+
+/// An Adobe CJK font that viewers supply themselves, addressed through a UTF-16 CMap.
+struct CaptionCidFont {
+    resource: &'static [u8],
+    base_font: &'static str,
+    cmap: &'static str,
+    ordering: &'static str,
+    supplement: i64,
+    /// The legacy character set matching the font's character collection
+    charset: &'static encoding_rs::Encoding,
+    bbox: [i64; 4],
+    cap_height: i64,
+    stem_v: i64,
+}
+
+/// Chinese, Japanese and Korean caption fonts, in that order.
+const CAPTION_CID_FONTS: [CaptionCidFont; 3] = [
+    CaptionCidFont {
+        resource: b"F2",
+        base_font: "STSong-Light",
+        cmap: "UniGB-UTF16-H",
+        ordering: "GB1",
+        supplement: 4,
+        charset: encoding_rs::GBK,
+        bbox: [-25, -254, 1000, 880],
+        cap_height: 880,
+        stem_v: 93,
+    },
+    CaptionCidFont {
+        resource: b"F3",
+        base_font: "KozMinPro-Regular",
+        cmap: "UniJIS-UTF16-H",
+        ordering: "Japan1",
+        supplement: 4,
+        charset: encoding_rs::SHIFT_JIS,
+        bbox: [-195, -272, 1110, 1075],
+        cap_height: 740,
+        stem_v: 86,
+    },
+    CaptionCidFont {
+        resource: b"F4",
+        base_font: "HYSMyeongJo-Medium",
+        cmap: "UniKS-UTF16-H",
+        ordering: "Korea1",
+        supplement: 1,
+        charset: encoding_rs::EUC_KR,
+        bbox: [0, -148, 1001, 880],
+        cap_height: 880,
+        stem_v: 93,
+    },
+];
+
+/// Fonts for image captions, added to the document when first used: Helvetica for text it
+/// can show, else one of the Adobe Chinese, Japanese or Korean fonts, which also cover Greek
+/// and Cyrillic. The font that can show the most of the text wins, preferring the one for its
+/// script; characters it lacks, such as Arabic, are shown as "?".
+#[derive(Default)]
+struct CaptionFonts {
+    latin: Option<ObjectId>,
+    cid: [Option<ObjectId>; 3],
+}
+
+impl CaptionFonts {
+    /// The resource name of the font to show `text` in, its encoded bytes, and its width in
+    /// em.
+    fn encode(&mut self, doc: &mut Document, text: &str) -> (&'static [u8], Vec<u8>, f32) {
+        let lacks = |charset: &'static encoding_rs::Encoding, c: char| {
+            charset.encode(c.encode_utf8(&mut [0; 4])).2
+        };
+        let is_hangul = |c: char| matches!(c, '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7A3}');
+        let is_kana = |c: char| matches!(c, '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}');
+        let order = if text.chars().any(is_hangul) {
+            [2, 1, 0]
+        } else if text.chars().any(is_kana) {
+            [1, 0, 2]
+        } else {
+            [0, 1, 2]
+        };
+        // Helvetica first, then the CJK fonts for the text's script
+        let candidates = std::iter::once(None).chain(order.map(Some));
+        let charset = |font: Option<usize>| {
+            font.map_or(encoding_rs::WINDOWS_1252, |i| CAPTION_CID_FONTS[i].charset)
+        };
+        let font = candidates
+            .min_by_key(|&font| text.chars().filter(|&c| lacks(charset(font), c)).count())
+            .flatten();
+        let text: String = text
+            .chars()
+            .map(|c| if lacks(charset(font), c) { '?' } else { c })
+            .collect();
+
+        let Some(index) = font else {
+            self.latin.get_or_insert_with(|| {
+                doc.add_object(dictionary! {
+                    "Type" => "Font",
+                    "Subtype" => "Type1",
+                    "BaseFont" => "Helvetica",
+                    "Encoding" => "WinAnsiEncoding",
+                })
+            });
+            let bytes = encoding_rs::WINDOWS_1252.encode(&text).0.into_owned();
+            return (b"F1", bytes, crate::font::helvetica_width(&text));
+        };
+
+        let font = &CAPTION_CID_FONTS[index];
+        self.cid[index].get_or_insert_with(|| {
+            let cid_font = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "CIDFontType0",
+                "BaseFont" => font.base_font,
+                "CIDSystemInfo" => dictionary! {
+                    "Registry" => Object::string_literal("Adobe"),
+                    "Ordering" => Object::string_literal(font.ordering),
+                    "Supplement" => font.supplement,
+                },
+                "FontDescriptor" => dictionary! {
+                    "Type" => "FontDescriptor",
+                    "FontName" => font.base_font,
+                    "Flags" => 6,
+                    "FontBBox" => font.bbox.map(Object::Integer).to_vec(),
+                    "ItalicAngle" => 0,
+                    "Ascent" => 880,
+                    "Descent" => -120,
+                    "CapHeight" => font.cap_height,
+                    "StemV" => font.stem_v,
+                },
+                "DW" => 1000,
+                // The proportional Latin glyphs
+                "W" => vec![1.into(), 95.into(), 500.into()],
+            });
+            doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type0",
+                "BaseFont" => format!("{}-{}", font.base_font, font.cmap),
+                "Encoding" => font.cmap,
+                "DescendantFonts" => vec![cid_font.into()],
+            })
+        });
+        let bytes = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let width = text
+            .chars()
+            .map(|c| if c.is_ascii() { 0.5 } else { 1.0 })
+            .sum();
+        (font.resource, bytes, width)
+    }
+
+    /// The `/Font` resources of the fonts used so far.
+    fn resources(&self) -> Option<Dictionary> {
+        if self.latin.is_none() && self.cid.iter().all(Option::is_none) {
+            return None;
+        }
+        let mut fonts = Dictionary::new();
+        if let Some(id) = self.latin {
+            fonts.set("F1", id);
+        }
+        for (font, id) in CAPTION_CID_FONTS.iter().zip(self.cid) {
+            if let Some(id) = id {
+                fonts.set(font.resource, id);
+            }
+        }
+        Some(fonts)
+    }
+}
+
+pub fn get_page_count<P: AsRef<Path>>(input: P, password: Option<&str>) -> Result<u32, PdfError> {
+    let doc = load_document(input, password)?;
+    Ok(doc.get_pages().len() as u32)
+}
+
+/// Writes the pages listed in `page_order` (1-based, may repeat or omit pages) to `output`.
+/// The document catalog is kept; links and bookmarks to omitted pages lose their destination.
+/// An encrypted input is written without protection unless `encryption` is given, which
+/// password protects the output.
+pub fn reorder_pages<P: AsRef<Path>>(
+    input: P,
+    page_order: &[u32],
+    output: P,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
 ) -> Result<(), PdfError> {
-    use pdfium_render::prelude::*;
+    let doc = load_document(input, password)?;
+    let pages = doc.get_pages();
+
+    // Create a new list of page ObjectIds based on input order.
+    // `get_pages` is keyed by page number in page tree order.
+    let new_pages: Vec<ObjectId> = page_order
+        .iter()
+        .filter_map(|page_num| pages.get(page_num).copied())
+        .collect();
+
+    if new_pages.is_empty() {
+        return Err(PdfError::Operation("No valid pages selected".to_string()));
+    }
+
+    let mut result = doc.clone();
+
+    // Build a fresh, flat page tree instead of rewriting the root's "Kids":
+    // with nested trees the old intermediate nodes would otherwise linger.
+    let pages_root_id = rebuild_page_tree(&mut result, &new_pages);
+    result
+        .catalog_mut()
+        .map_err(PdfError::Parse)?
+        .set("Pages", Object::Reference(pages_root_id));
+
+    let kept: HashSet<ObjectId> = new_pages.iter().copied().collect();
+    let removed: HashSet<ObjectId> = pages
+        .values()
+        .filter(|id| !kept.contains(id))
+        .copied()
+        .collect();
+    if !removed.is_empty() {
+        drop_destinations_to(&mut result, &removed);
+    }
+
+    // `prune_objects` will remove objects not reachable from Catalog,
+    // including the omitted pages and the old page tree.
+    result.prune_objects();
+
+    save_document(&mut result, output.as_ref(), encryption)
+}
+
+/// Rotates pages by a multiple of 90 degrees, clockwise for positive values.
+/// `rotations` holds `(page, degrees)` pairs with 1-based page numbers; the rotation is added
+/// to the page's current `/Rotate`, including a value inherited from its `/Pages` node.
+/// As with [`reorder_pages`], only `encryption` password protects the output.
+pub fn rotate_pages<P: AsRef<Path>>(
+    input: P,
+    rotations: &[(u32, i32)],
+    output: P,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<(), PdfError> {
+    let mut doc = load_document(input, password)?;
+    let pages = doc.get_pages();
+
+    for &(page_num, degrees) in rotations {
+        if degrees % 90 != 0 {
+            return Err(PdfError::Operation(format!(
+                "Rotation must be a multiple of 90 degrees, got {}",
+                degrees
+            )));
+        }
+        let page_id = *pages
+            .get(&page_num)
+            .ok_or(PdfError::InvalidPage(page_num))?;
+        let current = page_rotation(&mut doc, page_id);
+        set_page_rotation(&mut doc, page_id, current + degrees as i64)?;
+    }
+
+    save_document(&mut doc, output.as_ref(), encryption)
+}
+
+/// Target orientation for [`rotate_pages_to_orientation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Rotates every page that is not displayed in `orientation` by 90 degrees clockwise,
+/// e.g. to make all pages of a scanned document portrait. Square pages are left alone.
+/// Returns the 1-based numbers of the pages that were rotated. As with [`reorder_pages`],
+/// only `encryption` password protects the output.
+pub fn rotate_pages_to_orientation<P: AsRef<Path>>(
+    input: P,
+    orientation: Orientation,
+    output: P,
+    password: Option<&str>,
+    encryption: Option<&EncryptionOptions>,
+) -> Result<Vec<u32>, PdfError> {
+    let mut doc = load_document(input, password)?;
+
+    let mut rotated = Vec::new();
+    for (page_num, page_id) in doc.get_pages() {
+        let rotation = page_rotation(&mut doc, page_id);
+        let Some((width, height)) = page_size(&doc, page_id) else {
+            continue;
+        };
+        // A quarter turn swaps the displayed width and height
+        let (width, height) = if rotation % 180 == 0 {
+            (width, height)
+        } else {
+            (height, width)
+        };
+        let is_landscape = width > height;
+        let is_portrait = height > width;
+        let needs_turn = match orientation {
+            Orientation::Portrait => is_landscape,
+            Orientation::Landscape => is_portrait,
+        };
+        if needs_turn {
+            set_page_rotation(&mut doc, page_id, rotation + 90)?;
+            rotated.push(page_num);
+        }
+    }
+
+    save_document(&mut doc, output.as_ref(), encryption)?;
+    Ok(rotated)
+}
+
+/// The effective `/Rotate` of a page, normalized to 0, 90, 180 or 270.
+/// Inherited attributes are materialized onto the page first.
+fn page_rotation(doc: &mut Document, page_id: ObjectId) -> i64 {
+    inherit_page_attributes(doc, page_id);
+    doc.get_dictionary(page_id)
+        .and_then(|page| page.get(b"Rotate"))
+        .and_then(|o| doc.dereference(o))
+        .and_then(|(_, o)| o.as_i64())
+        .unwrap_or(0)
+        .rem_euclid(360)
+}
+
+fn set_page_rotation(doc: &mut Document, page_id: ObjectId, degrees: i64) -> Result<(), PdfError> {
+    doc.get_dictionary_mut(page_id)
+        .map_err(PdfError::Parse)?
+        .set("Rotate", degrees.rem_euclid(360));
+    Ok(())
+}
+
+/// Unrotated width and height of a page's visible area (`/CropBox`, else `/MediaBox`).
+fn page_size(doc: &Document, page_id: ObjectId) -> Option<(f32, f32)> {
+    let page = doc.get_dictionary(page_id).ok()?;
+    let bbox = page
+        .get(b"CropBox")
+        .or_else(|_| page.get(b"MediaBox"))
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())?;
+    let values: Vec<f32> = bbox
+        .iter()
+        .filter_map(|v| {
+            v.as_float()
+                .ok()
+                .or_else(|| v.as_i64().ok().map(|i| i as f32))
+        })
+        .collect();
+    if let [x1, y1, x2, y2] = values[..] {
+        Some(((x2 - x1).abs(), (y2 - y1).abs()))
+    } else {
+        None
+    }
+}
+
+/// Writes a password-protected copy of `input` to `output`.
+/// `password` opens `input` if it is already encrypted; its old protection is replaced.
+pub fn encrypt_pdf<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    options: &EncryptionOptions,
+    password: Option<&str>,
+) -> Result<(), PdfError> {
+    let mut doc = load_document(input, password)?;
+    save_document(&mut doc, output.as_ref(), Some(options))
+}
+
+/// Extracts the text of the selected pages, one string per page with lines separated by `\n`.
+/// `pages` is a page-range expression (see [`parse_page_groups`]); `None` selects every page.
+/// Strings are decoded through each font's `/ToUnicode` CMap or its encoding, so CID-keyed
+/// (e.g. CJK) fonts and `/Differences` arrays come out as proper Unicode.
+pub fn extract_text<P: AsRef<Path>>(
+    input: P,
+    pages: Option<&str>,
+    password: Option<&str>,
+) -> Result<Vec<String>, PdfError> {
+    let doc = load_document(input, password)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let selected = match pages {
+        Some(expr) => parse_page_list(expr, page_ids.len() as u32)?,
+        None => (1..=page_ids.len() as u32).collect(),
+    };
+
+    let mut fonts = FontCache::default();
+    Ok(selected
+        .into_iter()
+        .map(|page| {
+            let runs = ContentWalker::new(&doc, &mut fonts).page_runs(page_ids[page as usize - 1]);
+            runs_to_text(&runs)
+        })
+        .collect())
+}
+
+pub fn pdf_to_word<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    password: Option<&str>,
+) -> Result<(), PdfError> {
+    use std::fs::File;
+
+    let doc = load_document(input, password)?;
+    let pages = layout_pages(&doc);
+    let docx = build_docx(&doc, &pages);
+
+    let file = File::create(output).map_err(PdfError::Io)?;
+    docx.build()
+        .pack(file)
+        .map_err(|e| PdfError::Operation(format!("Docx Error: {}", e)))?;
+
+    Ok(())
+}
+
+/// Writes the text of the PDF to `output` as UTF-8 plain text, in reading order: one line per
+/// paragraph with blank lines between paragraphs, table rows as tab-separated cells and a form
+/// feed (`\f`) between pages.
+pub fn pdf_to_text<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    password: Option<&str>,
+) -> Result<(), PdfError> {
+    let doc = load_document(input, password)?;
+    let pages = layout_pages(&doc);
+    std::fs::write(output, to_text(&pages)).map_err(PdfError::Io)
+}
+
+/// Converts the PDF to Markdown: headings by relative font size, bullet and numbered lists,
+/// pipe tables and bold/italic emphasis. Each page starts with a `<!-- Page N -->` comment and
+/// pages are separated by `---`. Images are not exported.
+pub fn pdf_to_markdown<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    password: Option<&str>,
+) -> Result<(), PdfError> {
+    let doc = load_document(input, password)?;
+    let pages = layout_pages(&doc);
+    std::fs::write(output, to_markdown(&pages)).map_err(PdfError::Io)
+}
+
+/// Converts the PDF to a standalone HTML document with one `<section>` per page, using the same
+/// heading, list and table detection as [`pdf_to_markdown`]. Images are not exported.
+pub fn pdf_to_html<P: AsRef<Path>>(
+    input: P,
+    output: P,
+    password: Option<&str>,
+) -> Result<(), PdfError> {
+    let title = input
+        .as_ref()
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let doc = load_document(input, password)?;
+    let pages = layout_pages(&doc);
+    std::fs::write(output, to_html(&pages, &title)).map_err(PdfError::Io)
+}
+
+/// Walks and lays out every page, in page order.
+fn layout_pages(doc: &Document) -> Vec<PageLayout> {
+    let mut fonts = FontCache::default();
+    doc.get_pages()
+        .values()
+        .map(|page_id| PageLayout::new(ContentWalker::new(doc, &mut fonts).page_content(*page_id)))
+        .collect()
+}
+
+/// Pixel size of the pages rendered by [`pdf_to_images`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderSize {
+    /// Pixels per inch of the page's size in the PDF.
+    Dpi(f32),
+    /// Scales each page to fit within `width` x `height` pixels, keeping its aspect ratio. A
+    /// missing bound leaves that side free; with neither, pages render at 72 DPI.
+    Fit {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+}
+
+impl Default for RenderSize {
+    fn default() -> Self {
+        RenderSize::Fit {
+            width: Some(2000),
+            height: Some(2000),
+        }
+    }
+}
+
+/// Image file format written by [`pdf_to_images`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderFormat {
+    #[default]
+    Png,
+    /// JPEG at `quality` 1-100. JPEG has no transparency, so a transparent background is
+    /// rendered white.
+    Jpeg {
+        quality: u8,
+    },
+    /// Lossless WebP.
+    WebP,
+    Tiff,
+}
+
+impl RenderFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            RenderFormat::Png => "png",
+            RenderFormat::Jpeg { .. } => "jpg",
+            RenderFormat::WebP => "webp",
+            RenderFormat::Tiff => "tif",
+        }
+    }
+}
+
+/// How [`pdf_to_images`] stores the rendered pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderOutput {
+    /// One file per page, named by [`RenderOptions::file_name`].
+    #[default]
+    Pages,
+    /// All pages in `<name>.tif`, one TIFF page each. Pages with only black and white pixels
+    /// (see [`RenderOptions::threshold`]) are stored at 1 bit per pixel with CCITT Group 4
+    /// compression, the others with Deflate. [`RenderOptions::format`] is not used.
+    MultiPageTiff,
+    /// All pages tiled into one `<name>_contact_sheet` image, `columns` per row in page order,
+    /// with `spacing` pixels between and around them on the background color. Pages are
+    /// rendered no larger than `tile` x `tile` pixels, or [`RenderOptions::size`] if smaller.
+    ContactSheet {
+        columns: u32,
+        spacing: u32,
+        tile: u32,
+    },
+}
+
+/// Options for [`pdf_to_images`].
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub size: RenderSize,
+    pub format: RenderFormat,
+    pub output: RenderOutput,
+    /// Page-range expression selecting the pages to render (see [`parse_page_list`]), e.g.
+    /// "1-3,7". `None` renders every page.
+    pub pages: Option<String>,
+    /// Color behind the page content. `None` leaves it transparent.
+    pub background: Option<[u8; 3]>,
+    /// Write 8-bit gray images instead of color.
+    pub grayscale: bool,
+    /// Make pages black and white: gray levels below the threshold turn black, the rest
+    /// white. Anti-aliasing is turned off so edges stay sharp.
+    pub threshold: Option<u8>,
+    /// Path of each image relative to the output directory, without extension. `{name}` is
+    /// replaced by the input file stem and `{page}` by the page number, which `{page:03}`
+    /// pads with zeros to three digits. `/` separates subdirectories, which are created as
+    /// needed. The path must stay inside the output directory: no absolute paths and no
+    /// `..`.
+    pub file_name: String,
+}
+
+impl Default for RenderOptions {
+    /// Every page as PNG on white, fitted into 2000 x 2000 pixels, written to
+    /// `<output_dir>/<name>/<name>_page_001.png`, ...
+    fn default() -> Self {
+        RenderOptions {
+            size: RenderSize::default(),
+            format: RenderFormat::default(),
+            output: RenderOutput::default(),
+            pages: None,
+            background: Some([255, 255, 255]),
+            grayscale: false,
+            threshold: None,
+            file_name: "{name}/{name}_page_{page:03}".to_string(),
+        }
+    }
+}
+
+/// Renders the pages of the PDF to image files under `output_dir` with `renderer`, usually
+/// [`crate::default_renderer`]. Returns the paths of the written images in page order, or the
+/// single combined file for [`RenderOutput::MultiPageTiff`] and [`RenderOutput::ContactSheet`].
+pub fn pdf_to_images<P: AsRef<Path>>(
+    input: P,
+    output_dir: P,
+    renderer: &dyn PageRenderer,
+    options: &RenderOptions,
+    password: Option<&str>,
+) -> Result<Vec<std::path::PathBuf>, PdfError> {
+    let input_path = input.as_ref();
+    let output_base = output_dir.as_ref();
+    let file_stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    if let RenderSize::Dpi(dpi) = options.size {
+        if !(dpi.is_finite() && dpi > 0.0) {
+            return Err(PdfError::Operation(format!(
+                "Invalid resolution: {} DPI",
+                dpi
+            )));
+        }
+    }
+
+    // 1. Load Document
+    let data = std::fs::read(input_path).map_err(PdfError::Io)?;
+    let document = renderer.load(data, password)?;
+
+    // 2. Select pages
+    let page_count = document.page_count();
+    let pages = match &options.pages {
+        Some(expr) => parse_page_list(expr, page_count)?,
+        None => (1..=page_count).collect(),
+    };
+    if options.output == RenderOutput::Pages {
+        render::check_file_name(&options.file_name, pages.len() > 1)?;
+    }
+
+    // 3. Render Pages
+    let background = render::background(options);
+    // Black and white pages keep sharp edges
+    let smooth = options.threshold.is_none();
+
+    let mut paths = Vec::new();
+    let mut tiff_file = None;
+    let mut sheet = Vec::new();
+    if options.output == RenderOutput::MultiPageTiff {
+        let path = output_base.join(format!("{}.tif", file_stem));
+        render::create_parent(&path)?;
+        let file = std::fs::File::create(&path).map_err(PdfError::Io)?;
+        tiff_file = Some(std::io::BufWriter::new(file));
+        paths.push(path);
+    }
+    let dpi = match options.size {
+        RenderSize::Dpi(dpi) => Some(dpi),
+        RenderSize::Fit { .. } => None,
+    };
+    let mut tiff = match &mut tiff_file {
+        Some(file) => Some(render::MultiPageTiff::new(file, dpi)?),
+        None => None,
+    };
+
+    for page_number in pages {
+        let page_size = document.page_size(page_number)?;
+        let mut scale = render::scale(options.size, page_size)?;
+        if let RenderOutput::ContactSheet { tile, .. } = options.output {
+            // Render straight at tile size, so that only the tiles are held for the sheet
+            let tile = RenderSize::Fit {
+                width: Some(tile.max(1)),
+                height: Some(tile.max(1)),
+            };
+            scale = scale.min(render::scale(tile, page_size)?);
+        }
+        let bitmap = document.render(page_number, scale, background, smooth)?;
+        let image = render::prepare(image::DynamicImage::ImageRgba8(bitmap), options);
+
+        // 4. Save Image
+        match options.output {
+            RenderOutput::Pages => {
+                let file_name = render::file_name(&options.file_name, file_stem, page_number);
+                let image_path =
+                    output_base.join(format!("{}.{}", file_name, options.format.extension()));
+                render::save(&image, &image_path, options.format)?;
+                paths.push(image_path);
+            }
+            RenderOutput::MultiPageTiff => {
+                if let Some(tiff) = &mut tiff {
+                    tiff.add_page(&image)?;
+                }
+            }
+            RenderOutput::ContactSheet { .. } => sheet.push(image),
+        }
+    }
+
+    // Dropping the buffer would write out the rest of the TIFF but swallow any error
+    if let Some(mut file) = tiff_file {
+        std::io::Write::flush(&mut file).map_err(PdfError::Io)?;
+    }
+
+    if let RenderOutput::ContactSheet {
+        columns, spacing, ..
+    } = options.output
+    {
+        if !sheet.is_empty() {
+            let image = render::contact_sheet(&sheet, columns, spacing, background)?;
+            let image = render::prepare(image::DynamicImage::ImageRgba8(image), options);
+            let path = output_base.join(format!(
+                "{}_contact_sheet.{}",
+                file_stem,
+                options.format.extension()
+            ));
+            render::save(&image, &path, options.format)?;
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Renders one page (1-based) of the PDF as a PNG of at most `max_px` pixels on either side,
+/// in memory. The document stays loaded for the next call on the same, unchanged file, so
+/// previews can be requested page by page.
+pub fn render_page_thumbnail<P: AsRef<Path>>(
+    input: P,
+    renderer: &dyn PageRenderer,
+    page: u32,
+    max_px: u32,
+    password: Option<&str>,
+) -> Result<Vec<u8>, PdfError> {
+    let document = render::cached_document(renderer, input.as_ref(), password)?;
+    let document = document.lock().unwrap_or_else(PoisonError::into_inner);
+    render::thumbnail(document.as_ref(), page, max_px)
+}
+
+/// Renders thumbnails like [`render_page_thumbnail`] for the pages selected by the page-range
+/// expression `pages` (see [`parse_page_list`]), or every page, handing each PNG to
+/// `on_thumbnail` with its page number as soon as it is rendered. Returning `false` from
+/// `on_thumbnail` stops rendering.
+pub fn render_page_thumbnails<P, F>(
+    input: P,
+    renderer: &dyn PageRenderer,
+    pages: Option<&str>,
+    max_px: u32,
+    password: Option<&str>,
+    mut on_thumbnail: F,
+) -> Result<(), PdfError>
+where
+    P: AsRef<Path>,
+    F: FnMut(u32, Vec<u8>) -> bool,
+{
+    let document = render::cached_document(renderer, input.as_ref(), password)?;
+    let lock = || document.lock().unwrap_or_else(PoisonError::into_inner);
+    let page_count = lock().page_count();
+    let pages = match pages {
+        Some(expr) => parse_page_list(expr, page_count)?,
+        None => (1..=page_count).collect(),
+    };
+    for page in pages {
+        // Locked page by page, so single thumbnails asked for meanwhile are not held up
+        let thumbnail = render::thumbnail(lock().as_ref(), page, max_px)?;
+        if !on_thumbnail(page, thumbnail) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{HayroRenderer, RenderDocument};
+    use crate::security::{EncryptionAlgorithm, Permissions};
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Dictionary, Stream};
+
+    fn create_dummy_pdf(path: &Path, pages: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! {
+                "F1" => Object::Reference(font_id),
+            },
+        });
+
+        let mut page_ids = Vec::new();
+
+        for i in 1..=pages {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 48.into()]),
+                    Operation::new("Td", vec![100.into(), 600.into()]),
+                    Operation::new(
+                        "Tj",
+                        vec![Object::String(
+                            format!("Page {}", i).into_bytes(),
+                            lopdf::StringFormat::Literal,
+                        )],
+                    ),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let stream = Stream::new(dictionary! {}, content.encode().unwrap());
+            let stream_id = doc.add_object(stream);
+
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => Object::Reference(pages_id),
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                "Contents" => Object::Reference(stream_id),
+                "Resources" => Object::Reference(resources_id),
+            });
+            page_ids.push(Object::Reference(page_id));
+        }
+
+        let pages_dict = dictionary! {
+            "Type" => "Pages",
+            "Kids" => page_ids,
+            "Count" => pages as i32,
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages_dict));
+
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => Object::Reference(pages_id),
+        });
+        doc.trailer
+            .set(b"Root".to_vec(), Object::Reference(catalog_id));
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        doc.save_to(&mut file)?;
+        Ok(())
+    }
+
+    /// Creates a 4-page PDF with a nested page tree whose object numbers run against
+    /// reading order. Pages 1-2 inherit a 300x400 MediaBox and the font resources from
+    /// their `/Pages` node; pages 3-4 inherit `/Rotate 90` and the root's A4 MediaBox.
+    fn create_nested_pdf(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::with_version("1.5");
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let root_id = doc.new_object_id();
+        let second_id = doc.new_object_id();
+        let first_id = doc.new_object_id();
+
+        // Allocate pages last-to-first so object numbers are reversed
+        let mut page_ids = BTreeMap::new();
+        for i in (1..=4).rev() {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                    Operation::new("Td", vec![50.into(), 50.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(format!("Page {}", i))]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let stream_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+            let parent = if i <= 2 { first_id } else { second_id };
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => parent,
+                "Contents" => stream_id,
+            });
+            page_ids.insert(i, page_id);
+        }
+        let kids = |range: std::ops::RangeInclusive<u32>| -> Vec<Object> {
+            range.map(|i| Object::Reference(page_ids[&i])).collect()
+        };
+
+        doc.objects.insert(
+            first_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Parent" => root_id,
+                "Kids" => kids(1..=2),
+                "Count" => 2,
+                "MediaBox" => vec![0.into(), 0.into(), 300.into(), 400.into()],
+                "Resources" => dictionary! {
+                    "Font" => dictionary! { "F1" => font_id },
+                },
+            }),
+        );
+        doc.objects.insert(
+            second_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Parent" => root_id,
+                "Kids" => kids(3..=4),
+                "Count" => 2,
+                "Rotate" => 90,
+                "Resources" => dictionary! {
+                    "Font" => dictionary! { "F1" => font_id },
+                },
+            }),
+        );
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![Object::Reference(first_id), Object::Reference(second_id)],
+                "Count" => 4,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => root_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_page_tree_order() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_nested_order");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("nested.pdf");
+        create_nested_pdf(&input_path).unwrap();
+
+        let result = split_pdf(&input_path, &dir, "split", 2, 3).unwrap();
+        let doc = Document::load(&result[0]).unwrap();
+        assert_eq!(page_texts(&doc), vec!["Page 2", "Page 3"]);
+
+        let pages = doc.get_pages();
+        let page2 = doc.get_dictionary(pages[&1]).unwrap();
+        assert_eq!(
+            page2.get(b"MediaBox").unwrap().as_array().unwrap()[2],
+            300.into()
+        );
+        assert!(page2.has(b"Resources"));
+        let page3 = doc.get_dictionary(pages[&2]).unwrap();
+        assert_eq!(page3.get(b"Rotate").unwrap().as_i64().unwrap(), 90);
+        assert_eq!(
+            page3.get(b"MediaBox").unwrap().as_array().unwrap()[2],
+            595.into()
+        );
+
+        let output = dir.join("reordered.pdf");
+        reorder_pages(&input_path, &[4, 1, 3], &output, None, None).unwrap();
+        let doc = Document::load(&output).unwrap();
+        assert_eq!(page_texts(&doc), vec!["Page 4", "Page 1", "Page 3"]);
+        // The intermediate nodes of the old tree are gone
+        let pages_nodes = doc
+            .objects
+            .values()
+            .filter(|o| o.type_name().ok() == Some("Pages"))
+            .count();
+        assert_eq!(pages_nodes, 1);
+    }
+
+    /// Checks that every page of `path` carries the attributes it had in the nested fixture,
+    /// identified by its "Page N" text, on its own page dictionary.
+    fn assert_nested_attributes(path: &Path) {
+        let doc = Document::load(path).unwrap();
+        let texts = page_texts(&doc);
+        for (text, page_id) in texts.iter().zip(doc.get_pages().into_values()) {
+            let n: u32 = text.trim_start_matches("Page ").parse().unwrap();
+            let page = doc.get_dictionary(page_id).unwrap();
+            let width = page.get(b"MediaBox").unwrap().as_array().unwrap()[2].clone();
+            let rotate = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+            if n <= 2 {
+                assert_eq!((width, rotate), (300.into(), 0), "{:?} {}", path, text);
+            } else {
+                assert_eq!((width, rotate), (595.into(), 90), "{:?} {}", path, text);
+            }
+            let font = doc
+                .get_dict_in_dict(page, b"Resources")
+                .and_then(|r| doc.get_dict_in_dict(r, b"Font"))
+                .unwrap();
+            assert!(font.has(b"F1"), "{:?} {}", path, text);
+        }
+    }
+
+    #[test]
+    fn test_nested_page_tree_attributes() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_nested_attributes");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let nested = dir.join("nested.pdf");
+        create_nested_pdf(&nested).unwrap();
+        add_outline(&nested, &[("One", 1), ("Three", 3)]).unwrap();
+        let flat = dir.join("flat.pdf");
+        create_dummy_pdf(&flat, 1).unwrap();
+
+        for part in split_pdf_by_ranges(&nested, &dir, "ranges", "1,2-4", None, None).unwrap() {
+            assert_nested_attributes(&part);
+        }
+        for part in split_pdf_by_bookmarks(&nested, &dir, 1, None, None).unwrap() {
+            assert_nested_attributes(&part);
+        }
+        let size = std::fs::metadata(&nested).unwrap().len();
+        for part in split_pdf_by_size(&nested, &dir, "sized", size / 2, None, None).unwrap() {
+            assert_nested_attributes(&part.path);
+        }
+
+        let reordered = dir.join("reordered.pdf");
+        reorder_pages(&nested, &[3, 1, 1], &reordered, None, None).unwrap();
+        assert_nested_attributes(&reordered);
+
+        let merged = dir.join("merged.pdf");
+        merge_pdf(&[nested.clone(), nested.clone()], merged.clone()).unwrap();
+        let doc = Document::load(&merged).unwrap();
+        assert_eq!(doc.get_pages().len(), 8);
+        assert_nested_attributes(&merged);
+
+        let inputs = vec![
+            MergeInput {
+                pages: Some("4,2".to_string()),
+                ..MergeInput::new(&nested)
+            },
+            MergeInput::new(&flat),
+        ];
+        merge_pdf_pages(&inputs, &merged, &MergeOptions::default()).unwrap();
+        let doc = Document::load(&merged).unwrap();
+        assert_eq!(page_texts(&doc), vec!["Page 4", "Page 2", "Page 1"]);
+        let first = doc.get_dictionary(doc.get_pages()[&1]).unwrap();
+        assert_eq!(first.get(b"Rotate").unwrap().as_i64().unwrap(), 90);
+    }
+
+    #[test]
+    fn test_rotate_pages() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_rotate");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let nested = dir.join("nested.pdf");
+        create_nested_pdf(&nested).unwrap();
+        let output = dir.join("rotated.pdf");
+
+        let rotations = |path: &Path| -> Vec<i64> {
+            let mut doc = Document::load(path).unwrap();
+            let pages = doc.get_pages();
+            pages
+                .into_values()
+                .map(|id| page_rotation(&mut doc, id))
+                .collect()
+        };
+
+        // Page 3 inherits /Rotate 90 from its parent node
+        rotate_pages(&nested, &[(1, -90), (3, 90)], &output, None, None).unwrap();
+        assert_eq!(rotations(&output), vec![270, 0, 180, 90]);
+        assert!(rotate_pages(&nested, &[(1, 45)], &output, None, None).is_err());
+        assert!(rotate_pages(&nested, &[(5, 90)], &output, None, None).is_err());
+
+        // Pages 3-4 are A4 turned a quarter, so they display as landscape
+        let rotated =
+            rotate_pages_to_orientation(&nested, Orientation::Portrait, &output, None, None)
+                .unwrap();
+        assert_eq!(rotated, vec![3, 4]);
+        assert_eq!(rotations(&output), vec![0, 0, 180, 180]);
+        let rotated =
+            rotate_pages_to_orientation(&nested, Orientation::Landscape, &output, None, None)
+                .unwrap();
+        assert_eq!(rotated, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_password_errors() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_password_errors");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("locked.pdf");
+        create_dummy_pdf(&path, 2).unwrap();
+
+        // A standard security handler entry whose /U matches no password
+        let mut doc = Document::load(&path).unwrap();
+        let encrypt_id = doc.add_object(dictionary! {
+            "Filter" => "Standard",
+            "V" => 2,
+            "R" => 3,
+            "Length" => 128,
+            "P" => -4,
+            "O" => Object::String(vec![0x11; 32], lopdf::StringFormat::Hexadecimal),
+            "U" => Object::String(vec![0x22; 32], lopdf::StringFormat::Hexadecimal),
+        });
+        doc.trailer.set("Encrypt", encrypt_id);
+        let id = Object::String(vec![0x33; 16], lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![id.clone(), id]);
+        doc.save(&path).unwrap();
+
+        assert!(crate::security::needs_password(&path).unwrap());
+        assert!(matches!(
+            get_page_count(&path, None),
+            Err(PdfError::PasswordRequired)
+        ));
+        assert!(matches!(
+            get_page_count(&path, Some("secret")),
+            Err(PdfError::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn test_malformed_encryption() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_malformed_encryption");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("malformed.pdf");
+        let crypt_filters = dictionary! {
+            "StdCF" => dictionary! { "CFM" => "AESV3", "Length" => 32 },
+        };
+        let cases = [
+            // A 256-bit key asked of the MD5 based revision 4
+            (5, 4, 32, vec![0x44; 32]),
+            // Revision 6 with a /UE too short to hold the file key
+            (5, 6, 48, vec![0x44; 16]),
+        ];
+        for (version, revision, hash_len, user_key) in cases {
+            create_dummy_pdf(&path, 1).unwrap();
+            let mut doc = Document::load(&path).unwrap();
+            let encrypt_id = doc.add_object(dictionary! {
+                "Filter" => "Standard",
+                "V" => version,
+                "R" => revision,
+                "P" => -4,
+                "CF" => crypt_filters.clone(),
+                "StmF" => "StdCF",
+                "StrF" => "StdCF",
+                "O" => Object::String(vec![0x11; hash_len], lopdf::StringFormat::Hexadecimal),
+                "U" => Object::String(vec![0x22; hash_len], lopdf::StringFormat::Hexadecimal),
+                "OE" => Object::String(user_key.clone(), lopdf::StringFormat::Hexadecimal),
+                "UE" => Object::String(user_key, lopdf::StringFormat::Hexadecimal),
+            });
+            doc.trailer.set("Encrypt", encrypt_id);
+            let id = Object::String(vec![0x33; 16], lopdf::StringFormat::Hexadecimal);
+            doc.trailer.set("ID", vec![id.clone(), id]);
+            doc.save(&path).unwrap();
+
+            assert!(
+                matches!(
+                    get_page_count(&path, Some("secret")),
+                    Err(PdfError::Operation(_))
+                ),
+                "/V {} /R {}",
+                version,
+                revision
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_object_streams() {
+        // Written by tests/fixtures/make_encrypted.py, not by this crate
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for name in ["encrypted_objstm_aes128.pdf", "encrypted_objstm_aes256.pdf"] {
+            let path = fixtures.join(name);
+            assert!(crate::security::needs_password(&path).unwrap());
+            assert!(matches!(
+                get_page_count(&path, Some("nope")),
+                Err(PdfError::WrongPassword)
+            ));
+            for password in ["user", "owner"] {
+                assert_eq!(
+                    get_page_count(&path, Some(password)).unwrap(),
+                    2,
+                    "{}",
+                    name
+                );
+                let doc = load_document(&path, Some(password)).unwrap();
+                assert_eq!(page_texts(&doc), vec!["Page 1", "Page 2"]);
+                // Strings inside the object stream were decrypted with the stream, not again
+                let info = doc
+                    .trailer
+                    .get(b"Info")
+                    .and_then(Object::as_reference)
+                    .and_then(|id| doc.get_dictionary(id))
+                    .unwrap();
+                assert_eq!(
+                    info.get(b"Title").unwrap().as_str().unwrap(),
+                    b"Encrypted fixture"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_text_encodings() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_extract_text");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("encodings.pdf");
+
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let simple_font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => dictionary! {
+                "BaseEncoding" => "WinAnsiEncoding",
+                "Differences" => vec![144.into(), "Euro".into(), "uni4E2D".into()],
+            },
+        });
+        let cmap = b"/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+            1 begincodespacerange <0000> <FFFF> endcodespacerange\n\
+            1 beginbfchar <0001> <4E2D> endbfchar\n\
+            2 beginbfrange <0002> <0003> [<6587> <5B57>]\n<0010> <0012> <0041> endbfrange\n\
+            endcmap\nend\nend\n";
+        let to_unicode = doc.add_object(Stream::new(dictionary! {}, cmap.to_vec()));
+        let cid_font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => "SimSun",
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("GB1"),
+                "Supplement" => 2,
+            },
+            "DW" => 1000,
+        });
+        let composite_font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "SimSun",
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![Object::Reference(cid_font)],
+            "ToUnicode" => Object::Reference(to_unicode),
+        });
+
+        let hex = |bytes: &[u8]| Object::String(bytes.to_vec(), lopdf::StringFormat::Hexadecimal);
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![72.into(), 700.into()]),
+                Operation::new(
+                    "Tj",
+                    vec![Object::string_literal(b"Caf\xe9 \x90\x91".to_vec())],
+                ),
+                Operation::new("ET", vec![]),
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F2".into(), 12.into()]),
+                Operation::new("Td", vec![72.into(), 680.into()]),
+                Operation::new("Tj", vec![hex(&[0, 1, 0, 2, 0, 3])]),
+                // A kerning adjustment wide enough to read as a word space
+                Operation::new("Td", vec![0.into(), (-20).into()]),
+                Operation::new(
+                    "TJ",
+                    vec![vec![hex(&[0, 0x10, 0, 0x11]), (-800).into(), hex(&[0, 0x12])].into()],
+                ),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => Object::Reference(pages_id),
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Contents" => Object::Reference(content_id),
+            "Resources" => dictionary! {
+                "Font" => dictionary! {
+                    "F1" => Object::Reference(simple_font),
+                    "F2" => Object::Reference(composite_font),
+                },
+            },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![Object::Reference(page_id)],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => Object::Reference(pages_id),
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(&path).unwrap();
+
+        let text = extract_text(&path, None, None).unwrap();
+        assert_eq!(text, vec!["Café €中\n中文字\nAB C"]);
+        assert!(matches!(
+            extract_text(&path, Some("2"), None),
+            Err(PdfError::InvalidPage(2))
+        ));
+    }
+
+    /// Writes a PDF with one page per entry, each showing Helvetica text items
+    /// `(x, y, size, text)`.
+    fn create_text_pdf(
+        path: &Path,
+        pages: &[Vec<(i64, i64, i64, &str)>],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let contents = pages
+            .iter()
+            .map(|items| {
+                let mut operations = Vec::new();
+                for &(x, y, size, text) in items {
+                    operations.push(Operation::new("BT", vec![]));
+                    operations.push(Operation::new("Tf", vec!["F1".into(), size.into()]));
+                    operations.push(Operation::new("Td", vec![x.into(), y.into()]));
+                    operations.push(Operation::new("Tj", vec![Object::string_literal(text)]));
+                    operations.push(Operation::new("ET", vec![]));
+                }
+                Content { operations }
+            })
+            .collect();
+        create_content_pdf(path, contents)
+    }
+
+    /// Writes a PDF with one page per content stream. The pages have the standard fonts
+    /// `F1` (Helvetica), `F2` (Helvetica-Bold) and `F3` (Times-Italic).
+    fn create_content_pdf(
+        path: &Path,
+        contents: Vec<Content>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let mut fonts = Dictionary::new();
+        for (name, base_font) in [
+            ("F1", "Helvetica"),
+            ("F2", "Helvetica-Bold"),
+            ("F3", "Times-Italic"),
+        ] {
+            let font_id = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => base_font,
+                "Encoding" => "WinAnsiEncoding",
+            });
+            fonts.set(name, font_id);
+        }
+        let resources_id = doc.add_object(dictionary! { "Font" => fonts });
+
+        let mut page_ids = Vec::new();
+        for content in contents {
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => Object::Reference(pages_id),
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                "Contents" => Object::Reference(content_id),
+                "Resources" => Object::Reference(resources_id),
+            });
+            page_ids.push(Object::Reference(page_id));
+        }
+
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => page_ids.len() as i64,
+                "Kids" => page_ids,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => Object::Reference(pages_id),
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path)?;
+        Ok(())
+    }
+
+    /// The paragraphs of a .docx file as (text, starts a new page).
+    fn docx_paragraphs(path: &Path) -> Vec<(String, bool)> {
+        use docx_rs::{DocumentChild, ParagraphChild, RunChild};
+
+        let docx = docx_rs::read_docx(&std::fs::read(path).unwrap()).unwrap();
+        let mut paragraphs = Vec::new();
+        for child in &docx.document.children {
+            let DocumentChild::Paragraph(p) = child else {
+                continue;
+            };
+            let mut text = String::new();
+            for run in &p.children {
+                if let ParagraphChild::Run(run) = run {
+                    for child in &run.children {
+                        if let RunChild::Text(t) = child {
+                            text.push_str(&t.text);
+                        }
+                    }
+                }
+            }
+            paragraphs.push((text, p.property.page_break_before == Some(true)));
+        }
+        paragraphs
+    }
+
+    #[test]
+    fn test_pdf_to_word_layout() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_word_layout");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("columns.pdf");
+        let output = dir.join("columns.docx");
+
+        // A full-width title over two columns, written right column first
+        let page = vec![
+            (320, 720, 10, "The right column is read"),
+            (320, 708, 10, "after the left one, with a well-"),
+            (320, 696, 10, "known word and a final"),
+            (320, 684, 10, "line."),
+            (
+                72,
+                760,
+                16,
+                "A Study of Two Column Layouts in Portable Documents",
+            ),
+            (72, 720, 10, "The left column starts here"),
+            (72, 708, 10, "and continues on the next line."),
+            (72, 684, 10, "A second paragraph."),
+        ];
+        create_text_pdf(&path, &[page, vec![(72, 760, 12, "Second page")], vec![]]).unwrap();
+
+        pdf_to_word(&path, &output, None).unwrap();
+        let paragraphs = docx_paragraphs(&output);
+        let expected = [
+            ("A Study of Two Column Layouts in Portable Documents", false),
+            (
+                "The left column starts here and continues on the next line.",
+                false,
+            ),
+            ("A second paragraph.", false),
+            (
+                "The right column is read after the left one, with a well-known word and a final line.",
+                false,
+            ),
+            ("Second page", true),
+            ("", true),
+        ];
+        let expected: Vec<(String, bool)> = expected
+            .iter()
+            .map(|&(text, page_break)| (text.to_string(), page_break))
+            .collect();
+        assert_eq!(paragraphs, expected);
+    }
+
+    #[test]
+    fn test_pdf_to_word_formatting() {
+        use docx_rs::{Color, DocumentChild, ParagraphChild, RunFonts, Sz};
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_word_formatting");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("formatting.pdf");
+        let output = dir.join("formatting.docx");
+
+        let text = |s: &str| Operation::new("Tj", vec![Object::string_literal(s)]);
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("rg", vec![1.into(), 0.into(), 0.into()]),
+                Operation::new("Tf", vec!["F2".into(), 24.into()]),
+                Operation::new("Td", vec![72.into(), 760.into()]),
+                text("Annual Report"),
+                Operation::new("g", vec![0.into()]),
+                Operation::new("Tf", vec!["F1".into(), 16.into()]),
+                Operation::new("Td", vec![0.into(), (-40).into()]),
+                text("Introduction"),
+                Operation::new("Tf", vec!["F1".into(), 11.into()]),
+                Operation::new("Td", vec![0.into(), (-30).into()]),
+                text("Body text is set in a smaller size, with "),
+                Operation::new("Tf", vec!["F3".into(), 11.into()]),
+                text("emphasis"),
+                Operation::new("Tf", vec!["F1".into(), 11.into()]),
+                text(" in between."),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        create_content_pdf(&path, vec![content]).unwrap();
+        pdf_to_word(&path, &output, None).unwrap();
+
+        let docx = docx_rs::read_docx(&std::fs::read(&output).unwrap()).unwrap();
+        let paragraphs: Vec<_> = docx
+            .document
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                DocumentChild::Paragraph(p) => Some(p),
+                _ => None,
+            })
+            .collect();
+        let styles: Vec<Option<&str>> = paragraphs
+            .iter()
+            .map(|p| p.property.style.as_ref().map(|s| s.val.as_str()))
+            .collect();
+        assert_eq!(styles, vec![Some("Heading1"), Some("Heading2"), None]);
+        assert!(docx.styles.find_style_by_id("Heading2").is_some());
+
+        let runs: Vec<Vec<_>> = paragraphs
+            .iter()
+            .map(|p| {
+                p.children
+                    .iter()
+                    .filter_map(|child| match child {
+                        ParagraphChild::Run(run) => Some(&run.run_property),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let title = runs[0][0];
+        assert_eq!(title.sz, Some(Sz::new(48)));
+        assert!(title.bold.is_some() && title.italic.is_none());
+        assert_eq!(title.color, Some(Color::new("FF0000")));
+        assert_eq!(runs[1][0].sz, Some(Sz::new(32)));
+        assert!(runs[1][0].bold.is_none() && runs[1][0].color.is_none());
+
+        // Regular, italic and regular again
+        assert_eq!(runs[2].len(), 3);
+        assert!(runs[2][1].italic.is_some() && runs[2][0].italic.is_none());
+        assert_eq!(runs[2][1].sz, Some(Sz::new(22)));
+        let family = |f: &str| RunFonts::new().ascii(f).hi_ansi(f).east_asia(f).cs(f);
+        assert_eq!(runs[2][0].fonts, Some(family("Arial")));
+        assert_eq!(runs[2][1].fonts, Some(family("Times New Roman")));
+    }
+
+    #[test]
+    fn test_pdf_to_word_images() {
+        use docx_rs::{DocumentChild, DrawingData, ParagraphChild, RunChild};
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_word_images");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("images.pdf");
+        let output = dir.join("images.docx");
+
+        let text = |s: &str| Operation::new("Tj", vec![Object::string_literal(s)]);
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 16.into()]),
+                Operation::new("Td", vec![72.into(), 760.into()]),
+                text("Brochure"),
+                Operation::new("ET", vec![]),
+                // A 100 x 50 pt picture, 100 pt right of the text
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        100.into(),
+                        0.into(),
+                        0.into(),
+                        50.into(),
+                        172.into(),
+                        650.into(),
+                    ],
+                ),
+                Operation::new("Do", vec!["Im1".into()]),
+                Operation::new("Q", vec![]),
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 11.into()]),
+                Operation::new("Td", vec![72.into(), 620.into()]),
+                text("Text below the picture."),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        create_content_pdf(&path, vec![content]).unwrap();
+
+        // Add a 2 x 1 RGB image (red, blue) whose soft mask hides the blue pixel
+        let mut doc = Document::load(&path).unwrap();
+        let mut mask = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 1,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            vec![255, 0],
+        );
+        mask.compress().unwrap();
+        let mask_id = doc.add_object(mask);
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 1,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+                "SMask" => mask_id,
+            },
+            vec![255, 0, 0, 0, 0, 255],
+        );
+        image.compress().unwrap();
+        let image_id = doc.add_object(image);
+        let page_id = *doc.get_pages().get(&1).unwrap();
+        let resources_id = doc
+            .get_dictionary(page_id)
+            .unwrap()
+            .get(b"Resources")
+            .and_then(Object::as_reference)
+            .unwrap();
+        doc.get_dictionary_mut(resources_id)
+            .unwrap()
+            .set("XObject", dictionary! { "Im1" => image_id });
+        doc.save(&path).unwrap();
+
+        pdf_to_word(&path, &output, None).unwrap();
+        assert_eq!(
+            docx_paragraphs(&output),
+            vec![
+                ("Brochure".to_string(), false),
+                (String::new(), false),
+                ("Text below the picture.".to_string(), false),
+            ]
+        );
+
+        let docx = docx_rs::read_docx(&std::fs::read(&output).unwrap()).unwrap();
+        let DocumentChild::Paragraph(paragraph) = &docx.document.children[1] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            paragraph.property.indent.as_ref().unwrap().start,
+            Some(2000)
+        );
+        let pic = paragraph
+            .children
+            .iter()
+            .find_map(|child| match child {
+                ParagraphChild::Run(run) => run.children.iter().find_map(|c| match c {
+                    RunChild::Drawing(drawing) => match &drawing.data {
+                        Some(DrawingData::Pic(pic)) => Some(pic),
+                        _ => None,
+                    },
+                    _ => None,
+                }),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(pic.size, (100 * 12700, 50 * 12700));
+
+        let (_, _, _, png) = &docx.images[0];
+        let pixels = image::load_from_memory(&png.0).unwrap().into_rgba8();
+        assert_eq!(pixels.dimensions(), (2, 1));
+        assert_eq!(pixels.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(pixels.get_pixel(1, 0).0, [0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn test_pdf_to_word_tables() {
+        use docx_rs::{DocumentChild, TableCellContent, TableChild, TableRowChild};
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_word_tables");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("tables.pdf");
+        let output = dir.join("tables.docx");
+
+        let line = |x1: i64, y1: i64, x2: i64, y2: i64| {
+            vec![
+                Operation::new("m", vec![x1.into(), y1.into()]),
+                Operation::new("l", vec![x2.into(), y2.into()]),
+            ]
+        };
+        let text = |x: i64, y: i64, s: &str| {
+            vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 10.into()]),
+                Operation::new("Td", vec![x.into(), y.into()]),
+                Operation::new("Tj", vec![Object::string_literal(s)]),
+                Operation::new("ET", vec![]),
+            ]
+        };
+
+        // A ruled 3 x 3 grid whose header spans the last two columns
+        let mut operations = vec![Operation::new(
+            "re",
+            vec![72.into(), 640.into(), 328.into(), 60.into()],
+        )];
+        operations.extend(line(72, 680, 400, 680));
+        operations.extend(line(72, 660, 400, 660));
+        operations.extend(line(200, 640, 200, 700));
+        operations.extend(line(300, 640, 300, 680));
+        operations.push(Operation::new("S", vec![]));
+        for (x, y, s) in [
+            (76, 686, "Item"),
+            (204, 686, "Amount"),
+            (76, 666, "Paper"),
+            (204, 666, "3"),
+            (304, 666, "12.00"),
+            (76, 646, "Ink"),
+            (204, 646, "1"),
+            (304, 646, "8.50"),
+            (72, 760, "Invoice"),
+            (72, 600, "Totals follow."),
+            // Columns without rulings
+            (72, 560, "Qty"),
+            (250, 560, "Price"),
+            (400, 560, "Total"),
+            (72, 545, "2"),
+            (250, 545, "4.00"),
+            (400, 545, "8.00"),
+            (72, 530, "5"),
+            (250, 530, "1.00"),
+            (400, 530, "5.00"),
+        ] {
+            operations.extend(text(x, y, s));
+        }
+        create_content_pdf(&path, vec![Content { operations }]).unwrap();
+        pdf_to_word(&path, &output, None).unwrap();
+
+        let docx = docx_rs::read_docx(&std::fs::read(&output).unwrap()).unwrap();
+        let children = &docx.document.children;
+        assert_eq!(children.len(), 4);
+        assert!(matches!(&children[0], DocumentChild::Paragraph(_)));
+        assert!(matches!(&children[2], DocumentChild::Paragraph(_)));
+
+        // Cell texts per row, and the spans of the header cells
+        let table = |child: &DocumentChild| -> (Vec<Vec<String>>, Vec<String>) {
+            let DocumentChild::Table(table) = child else {
+                panic!("expected a table");
+            };
+            let mut texts = Vec::new();
+            let mut spans = Vec::new();
+            for TableChild::TableRow(row) in &table.rows {
+                let mut cells = Vec::new();
+                for TableRowChild::TableCell(cell) in &row.cells {
+                    let mut text = String::new();
+                    for content in &cell.children {
+                        if let TableCellContent::Paragraph(p) = content {
+                            text.push_str(&p.raw_text());
+                        }
+                    }
+                    cells.push(text);
+                    if texts.is_empty() {
+                        spans.push(format!("{:?}", cell.property));
+                    }
+                }
+                texts.push(cells);
+            }
+            (texts, spans)
+        };
+
+        let (ruled, spans) = table(&children[1]);
+        assert_eq!(
+            ruled,
+            vec![
+                vec!["Item", "Amount"],
+                vec!["Paper", "3", "12.00"],
+                vec!["Ink", "1", "8.50"],
+            ]
+        );
+        assert!(spans[1].contains("GridSpan { val: 2 }"));
+
+        let (aligned, _) = table(&children[3]);
+        assert_eq!(
+            aligned,
+            vec![
+                vec!["Qty", "Price", "Total"],
+                vec!["2", "4.00", "8.00"],
+                vec!["5", "1.00", "5.00"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pdf_export() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_export");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let path = dir.join("export.pdf");
+
+        let text = |font: &str, size: i64, x: i64, y: i64, s: &str| {
+            vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec![font.into(), size.into()]),
+                Operation::new("Td", vec![x.into(), y.into()]),
+                Operation::new("Tj", vec![Object::string_literal(s)]),
+                Operation::new("ET", vec![]),
+            ]
+        };
+        let mut first = Vec::new();
+        for (font, size, x, y, s) in [
+            ("F2", 20, 72, 760, "Summary"),
+            (
+                "F1",
+                11,
+                72,
+                720,
+                "Costs rose in the second half of the year.",
+            ),
+            ("F1", 11, 72, 690, "- Paper"),
+            ("F1", 11, 72, 676, "- Ink"),
+            ("F1", 11, 72, 646, "Prices are given in <EUR> & cents."),
+        ] {
+            first.extend(text(font, size, x, y, s));
+        }
+        let mut second = Vec::new();
+        for (font, size, x, y, s) in [
+            ("F1", 11, 72, 760, "The "),
+            ("F2", 11, 92, 760, "totals"),
+            ("F1", 11, 126, 760, " follow."),
+            ("F1", 10, 72, 700, "Qty"),
+            ("F1", 10, 250, 700, "Price"),
+            ("F1", 10, 400, 700, "Total"),
+            ("F1", 10, 72, 685, "2"),
+            ("F1", 10, 250, 685, "4.00"),
+            ("F1", 10, 400, 685, "8.00"),
+            ("F1", 10, 72, 670, "5"),
+            ("F1", 10, 250, 670, "1.00"),
+            ("F1", 10, 400, 670, "5.00"),
+        ] {
+            second.extend(text(font, size, x, y, s));
+        }
+        create_content_pdf(
+            &path,
+            vec![
+                Content { operations: first },
+                Content { operations: second },
+            ],
+        )
+        .unwrap();
+
+        let output = dir.join("export.txt");
+        pdf_to_text(&path, &output, None).unwrap();
+        let text = std::fs::read_to_string(&output).unwrap();
+        let pages: Vec<&str> = text.split('\u{c}').collect();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].starts_with("Summary\n\nCosts rose"));
+        assert!(pages[1].contains("Qty\tPrice\tTotal\n2\t4.00\t8.00"));
+
+        let output = dir.join("export.md");
+        pdf_to_markdown(&path, &output, None).unwrap();
+        let markdown = std::fs::read_to_string(&output).unwrap();
+        assert!(markdown.starts_with("<!-- Page 1 -->\n\n# Summary\n"));
+        assert!(markdown.contains("- Paper\n- Ink\n"));
+        assert!(markdown.contains("\\<EUR\\>"));
+        assert!(markdown.contains("---\n\n<!-- Page 2 -->"));
+        assert!(markdown.contains("The **totals** follow."));
+        assert!(
+            markdown.contains("| Qty | Price | Total |\n| --- | --- | --- |\n| 2 | 4.00 | 8.00 |")
+        );
+
+        let output = dir.join("export.html");
+        pdf_to_html(&path, &output, None).unwrap();
+        let html = std::fs::read_to_string(&output).unwrap();
+        assert!(html.contains("<title>export</title>"));
+        assert!(html.contains("<section class=\"page\" id=\"page-2\">"));
+        assert!(html.contains("<h1>Summary</h1>"));
+        assert!(html.contains("<ul>\n<li>Paper</li>\n<li>Ink</li>\n</ul>"));
+        assert!(html.contains("&lt;EUR&gt; &amp; cents"));
+        assert!(html.contains("The <b>totals</b> follow."));
+        assert!(html.contains("<tr><td>2</td><td>4.00</td><td>8.00</td></tr>"));
+    }
+
+    #[test]
+    fn test_render_output() {
+        assert_eq!(
+            render::file_name("{name}/{name}_page_{page:03}", "report", 7),
+            "report/report_page_007"
+        );
+        assert_eq!(
+            render::file_name("p{page}-{size}", "report", 12),
+            "p12-{size}"
+        );
+        for template in ["{name}_{page}", "{page:03}/scan"] {
+            assert!(
+                render::check_file_name(template, true).is_ok(),
+                "{}",
+                template
+            );
+        }
+        for template in ["{pages}", "{page:3}", "cover"] {
+            assert!(
+                render::check_file_name(template, true).is_err(),
+                "{}",
+                template
+            );
+            assert!(
+                render::check_file_name(template, false).is_ok(),
+                "{}",
+                template
+            );
+        }
+        for template in [
+            "/tmp/{page}",
+            "../{page}",
+            "a/../../{page}",
+            "C:{page}",
+            "\\x\\{page}",
+        ] {
+            assert!(
+                render::check_file_name(template, false).is_err(),
+                "{}",
+                template
+            );
+        }
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_render");
+        let mut page = image::RgbaImage::from_pixel(20, 10, image::Rgba([255, 255, 255, 0]));
+        page.put_pixel(0, 0, image::Rgba([200, 0, 0, 255]));
+        let page = image::DynamicImage::ImageRgba8(page);
+
+        for (format, grayscale, background, expected) in [
+            (RenderFormat::Png, false, None, image::ColorType::Rgba8),
+            (
+                RenderFormat::Png,
+                true,
+                Some([255, 255, 255]),
+                image::ColorType::L8,
+            ),
+            (
+                RenderFormat::Jpeg { quality: 80 },
+                false,
+                None,
+                image::ColorType::Rgb8,
+            ),
+            // WebP stores gray as color
+            (RenderFormat::WebP, true, None, image::ColorType::Rgba8),
+            (
+                RenderFormat::Tiff,
+                false,
+                Some([0, 0, 0]),
+                image::ColorType::Rgb8,
+            ),
+            // and so does TIFF when gray has alpha
+            (RenderFormat::Tiff, true, None, image::ColorType::Rgba8),
+        ] {
+            let options = RenderOptions {
+                format,
+                grayscale,
+                background,
+                ..Default::default()
+            };
+            let path = dir
+                .join(format!("{:?}_{}", format, grayscale))
+                .join(format!("page.{}", format.extension()));
+            render::save(&render::prepare(page.clone(), &options), &path, format).unwrap();
+            let saved = image::open(&path).unwrap();
+            assert_eq!(saved.color(), expected, "{:?}", format);
+            assert_eq!((saved.width(), saved.height()), (20, 10));
+        }
+    }
+
+    #[test]
+    fn test_render_combined_output() {
+        use tiff::decoder::ifd::Value;
+        use tiff::decoder::{Decoder, DecodingResult};
+        use tiff::tags::Tag;
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_render_combined");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+
+        // A gray page that the threshold turns black and white, and a color page
+        let mut scan = image::RgbaImage::from_pixel(40, 30, image::Rgba([230, 230, 230, 255]));
+        for x in 5..35 {
+            scan.put_pixel(x, 10, image::Rgba([40, 40, 40, 255]));
+        }
+        let bilevel = render::prepare(
+            image::DynamicImage::ImageRgba8(scan),
+            &RenderOptions {
+                threshold: Some(128),
+                ..Default::default()
+            },
+        );
+        let color = render::prepare(
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                20,
+                50,
+                image::Rgba([0, 128, 255, 255]),
+            )),
+            &RenderOptions::default(),
+        );
+
+        let path = dir.join("pages.tif");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        let mut tiff = render::MultiPageTiff::new(&mut file, Some(300.0)).unwrap();
+        tiff.add_page(&bilevel).unwrap();
+        tiff.add_page(&color).unwrap();
+        std::io::Write::flush(&mut file).unwrap();
+
+        let mut decoder = Decoder::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let resolution = |decoder: &mut Decoder<std::fs::File>| {
+            decoder.get_tag(Tag::XResolution).unwrap() == Value::Rational(300, 1)
+        };
+        assert_eq!(decoder.get_tag_u32(Tag::Compression).unwrap(), 4);
+        assert!(resolution(&mut decoder));
+        assert_eq!(decoder.dimensions().unwrap(), (40, 30));
+        let DecodingResult::U8(bits) = decoder.read_image().unwrap() else {
+            panic!("expected 8-bit samples");
+        };
+        // One bit per pixel, rows padded to whole bytes; the decoder turns black into 0
+        let black = |x: usize, y: usize| bits[y * 5 + x / 8] & (0x80 >> (x % 8)) == 0;
+        assert!(black(5, 10) && black(34, 10));
+        assert!(!black(4, 10) && !black(5, 11));
+
+        assert!(decoder.more_images());
+        decoder.next_image().unwrap();
+        assert_eq!(decoder.get_tag_u32(Tag::Compression).unwrap(), 8);
+        assert!(resolution(&mut decoder));
+        assert_eq!(decoder.dimensions().unwrap(), (20, 50));
+        assert!(!decoder.more_images());
+
+        let pages = vec![color.clone(), bilevel.clone(), color];
+        let sheet = render::contact_sheet(&pages, 2, 4, [255, 255, 255, 255]).unwrap();
+        // Cells are 40 x 50 in two columns and two rows
+        assert_eq!(sheet.dimensions(), (2 * 40 + 3 * 4, 2 * 50 + 3 * 4));
+        assert_eq!(sheet.get_pixel(4 + 10, 4).0, [0, 128, 255, 255]);
+        assert_eq!(
+            sheet.get_pixel(4 + 40 + 4 + 5, 4 + 10 + 10).0,
+            [0, 0, 0, 255]
+        );
+        assert_eq!(sheet.get_pixel(2, 2).0, [255, 255, 255, 255]);
+        assert!(render::contact_sheet(&pages, 2, u32::MAX / 2, [0; 4]).is_err());
+    }
+
+    /// Held by the tests that go through the single-document thumbnail cache.
+    static THUMBNAIL_CACHE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Counts the documents it loads, to observe the thumbnail cache.
+    #[derive(Default)]
+    struct CountingRenderer(std::sync::atomic::AtomicUsize);
+
+    impl PageRenderer for CountingRenderer {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn load(
+            &self,
+            data: Vec<u8>,
+            password: Option<&str>,
+        ) -> Result<Box<dyn RenderDocument>, PdfError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            HayroRenderer.load(data, password)
+        }
+    }
+
+    #[test]
+    fn test_thumbnail_cache() {
+        let _cache = THUMBNAIL_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join("rust_pdf_test_thumbnail_cache");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let first = dir.join("first.pdf");
+        let second = dir.join("second.pdf");
+        create_dummy_pdf(&first, 2).unwrap();
+        create_dummy_pdf(&second, 2).unwrap();
+
+        let renderer = CountingRenderer::default();
+        let thumbnail = |path: &Path, password: Option<&str>| {
+            render_page_thumbnail(path, &renderer, 1, 32, password).unwrap();
+            renderer.0.load(std::sync::atomic::Ordering::SeqCst)
+        };
+        assert_eq!(thumbnail(&first, None), 1);
+        render_page_thumbnails(&first, &renderer, None, 32, None, |_, _| true).unwrap();
+        assert_eq!(thumbnail(&first, None), 1);
+
+        // Another file, then the first one again
+        assert_eq!(thumbnail(&second, None), 2);
+        assert_eq!(thumbnail(&first, None), 3);
+
+        // The same file, modified
+        let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&first)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(thumbnail(&first, None), 4);
+        assert_eq!(thumbnail(&first, None), 4);
+
+        // Another password
+        assert_eq!(thumbnail(&first, Some("one")), 5);
+        assert_eq!(thumbnail(&first, Some("one")), 5);
+        assert_eq!(thumbnail(&first, Some("two")), 6);
+    }
+
+    #[test]
+    fn test_pdfium_bind_failure() {
+        use crate::renderer::PdfiumRenderer;
+
+        let path = std::env::temp_dir()
+            .join("rust_pdf_test_missing")
+            .join("libpdfium.so");
+        match (
+            PdfiumRenderer::bind(Some(&path)),
+            PdfiumRenderer::bind(Some(&path)),
+        ) {
+            // PDFium is installed on this system
+            (Ok(_), Ok(_)) => {}
+            (Err(first), Err(second)) => {
+                let message = first.to_string();
+                assert!(
+                    message.contains(&format!("at {}:", path.display())),
+                    "{}",
+                    message
+                );
+                assert_eq!(second.to_string(), message);
+            }
+            _ => panic!("binding PDFium twice gave different results"),
+        }
+    }
+
+    #[test]
+    fn test_thumbnail_missing_input() {
+        let missing = std::env::temp_dir().join("rust_pdf_test_thumbnail_missing.pdf");
+        let result = render_page_thumbnail(&missing, &HayroRenderer, 1, 128, None);
+        assert!(matches!(result, Err(PdfError::Io(_))));
+    }
+
+    #[test]
+    fn test_images_to_pdf_lossless() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_images_lossless");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let rgba = image::RgbaImage::from_fn(64, 48, |x, y| {
+            image::Rgba([
+                x as u8 * 4,
+                y as u8 * 5,
+                200,
+                if x < 32 { 255 } else { 128 },
+            ])
+        });
+        let png = dir.join("alpha.png");
+        rgba.save(&png).unwrap();
+        let gray = image::GrayImage::from_fn(40, 30, |x, _| image::Luma([x as u8 * 6]));
+        let jpeg = dir.join("gray.jpg");
+        gray.save(&jpeg).unwrap();
+        let deep =
+            image::ImageBuffer::<image::Rgb<u16>, _>::from_pixel(8, 8, image::Rgb([1, 2, 3]));
+        let deep_png = dir.join("deep.png");
+        deep.save(&deep_png).unwrap();
+        let scan =
+            image::GrayImage::from_fn(20, 10, |x, y| image::Luma([if x == y { 0 } else { 255 }]));
+        let scan_png = dir.join("scan.png");
+        scan.save(&scan_png).unwrap();
+        // Pen strokes wandering down the page
+        let mut strokes: Vec<u32> = (0..20).map(|i| 15 + i * 29).collect();
+        let mut handwriting = image::GrayImage::from_pixel(600, 400, image::Luma([255]));
+        let mut seed = 12345u32;
+        for y in 0..400 {
+            for x in strokes.iter_mut() {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                *x = (*x + (seed >> 30)).saturating_sub(1).min(597);
+                handwriting.put_pixel(*x, y, image::Luma([0]));
+                handwriting.put_pixel(*x + 1, y, image::Luma([0]));
+            }
+        }
+        let handwriting_png = dir.join("handwriting.png");
+        handwriting.save(&handwriting_png).unwrap();
+
+        let output = dir.join("images.pdf");
+        images_to_pdf(
+            &[png, jpeg.clone(), deep_png, scan_png, handwriting_png],
+            output.clone(),
+        )
+        .unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let images: Vec<(ObjectId, &Stream)> = doc
+            .get_pages()
+            .values()
+            .map(|&page_id| {
+                let resources = doc
+                    .get_dictionary(page_id)
+                    .unwrap()
+                    .get(b"Resources")
+                    .unwrap();
+                let (id, stream) =
+                    crate::xobject::lookup(&doc, resources.as_dict().unwrap(), b"Im1").unwrap();
+                (id.unwrap(), stream)
+            })
+            .collect();
+        let name = |stream: &Stream, key: &[u8]| {
+            stream
+                .dict
+                .get(key)
+                .unwrap()
+                .as_name_str()
+                .unwrap()
+                .to_string()
+        };
+        let bits = |stream: &Stream| {
+            stream
+                .dict
+                .get(b"BitsPerComponent")
+                .unwrap()
+                .as_i64()
+                .unwrap()
+        };
+
+        // PNG with alpha: Flate-compressed RGB with the alpha channel as soft mask
+        let (id, stream) = images[0];
+        assert_eq!(name(stream, b"Filter"), "FlateDecode");
+        assert_eq!(name(stream, b"ColorSpace"), "DeviceRGB");
+        assert!(stream.dict.get(b"SMask").is_ok());
+        let decoded = crate::xobject::decode_image(&doc, id).unwrap();
+        assert_eq!(decoded.to_rgba8(), rgba);
+
+        // JPEG kept byte for byte in its own color space
+        let (_, stream) = images[1];
+        assert_eq!(name(stream, b"Filter"), "DCTDecode");
+        assert_eq!(name(stream, b"ColorSpace"), "DeviceGray");
+        assert_eq!(stream.content, std::fs::read(&jpeg).unwrap());
+
+        // 16-bit samples stay 16-bit, with no mask for opaque images
+        let (_, stream) = images[2];
+        assert_eq!(bits(stream), 16);
+        assert!(stream.dict.get(b"SMask").is_err());
+
+        // Black and white images are packed at 1 bit per pixel
+        let (id, stream) = images[3];
+        assert_eq!(
+            (bits(stream), name(stream, b"ColorSpace")),
+            (1, "DeviceGray".to_string())
+        );
+        let decoded = crate::xobject::decode_image(&doc, id).unwrap();
+        assert_eq!(decoded.to_luma8(), scan);
+
+        // or as Group 4 fax data where that is smaller
+        let (id, stream) = images[4];
+        assert_eq!(name(stream, b"Filter"), "CCITTFaxDecode");
+        assert_eq!(bits(stream), 1);
+        let decoded = crate::xobject::decode_image(&doc, id).unwrap();
+        assert_eq!(decoded.to_luma8(), handwriting);
+
+        // Damaged dimensions are rejected instead of overflowing or allocating
+        let id = images[0].0;
+        let mut doc = doc.clone();
+        for (width, height) in [
+            (i64::MAX, 2),
+            (1 << 40, 1i64 << 40),
+            (-1, 1),
+            (1 << 20, 1 << 20),
+        ] {
+            let dict = &mut doc
+                .get_object_mut(id)
+                .unwrap()
+                .as_stream_mut()
+                .unwrap()
+                .dict;
+            dict.set("Width", width);
+            dict.set("Height", height);
+            assert!(crate::xobject::decode_image(&doc, id).is_none());
+        }
+    }
+
+    #[test]
+    fn test_images_to_pdf_layout() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_images_layout");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        // 2 x 1 inches at 300 DPI
+        let wide = dir.join("wide.png");
+        image::RgbImage::from_pixel(600, 300, image::Rgb([10, 20, 30]))
+            .save(&wide)
+            .unwrap();
+        let output = dir.join("layout.pdf");
+
+        // Page size and the image's cm operation for `options`
+        let layout = |options: ImagePdfOptions| -> (Vec<f32>, Vec<f32>) {
+            images_to_pdf_with_options(std::slice::from_ref(&wide), output.clone(), &options)
+                .unwrap();
+            let doc = Document::load(&output).unwrap();
+            let page_id = *doc.get_pages().get(&1).unwrap();
+            let numbers = |objects: &[Object]| -> Vec<f32> {
+                objects
+                    .iter()
+                    .map(|o| o.as_float().unwrap().round())
+                    .collect()
+            };
+            let media_box = doc
+                .get_dictionary(page_id)
+                .unwrap()
+                .get(b"MediaBox")
+                .unwrap()
+                .as_array()
+                .unwrap();
+            let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+            let cm = content
+                .operations
+                .iter()
+                .find(|op| op.operator == "cm")
+                .unwrap();
+            (numbers(&media_box[2..]), numbers(&cm.operands))
+        };
+
+        // A4 turned landscape to match the image, which is scaled to the full width
+        let (page, cm) = layout(ImagePdfOptions::default());
+        assert_eq!(page, vec![842.0, 595.0]);
+        assert_eq!(cm, vec![842.0, 0.0, 0.0, 421.0, 0.0, 87.0]);
+
+        // Portrait Letter with margins, filled: the image covers the area and overflows it
+        let (page, cm) = layout(ImagePdfOptions {
+            page_size: PageSize::Letter,
+            orientation: Some(Orientation::Portrait),
+            margin: 36.0,
+            fit: ImageFit::Fill,
+            ..Default::default()
+        });
+        assert_eq!(page, vec![612.0, 792.0]);
+        assert_eq!(cm, vec![1440.0, 0.0, 0.0, 720.0, -414.0, 36.0]);
+
+        // Centered at its size
+        let (_, cm) = layout(ImagePdfOptions {
+            fit: ImageFit::Center,
+            ..Default::default()
+        });
+        assert_eq!(&cm[..4], &[144.0, 0.0, 0.0, 72.0]);
+
+        // Page sized to the image at 150 DPI, plus margins
+        let (page, cm) = layout(ImagePdfOptions {
+            page_size: PageSize::Image,
+            margin: 10.0,
+            dpi: 150.0,
+            ..Default::default()
+        });
+        assert_eq!(page, vec![308.0, 164.0]);
+        assert_eq!(cm, vec![288.0, 0.0, 0.0, 144.0, 10.0, 10.0]);
+
+        // Sizes and margins that leave no page are refused
+        let custom = |width: f32, height: f32| ImagePdfOptions {
+            page_size: PageSize::Custom { width, height },
+            ..Default::default()
+        };
+        for options in [
+            custom(0.0, 100.0),
+            custom(-100.0, 100.0),
+            custom(100.0, f32::NAN),
+            custom(100.0, f32::INFINITY),
+            ImagePdfOptions {
+                margin: -1.0,
+                ..Default::default()
+            },
+            ImagePdfOptions {
+                margin: f32::NAN,
+                ..Default::default()
+            },
+            ImagePdfOptions {
+                margin: 50.0,
+                ..custom(100.0, 200.0)
+            },
+        ] {
+            let result =
+                images_to_pdf_with_options(std::slice::from_ref(&wide), output.clone(), &options);
+            assert!(
+                matches!(result, Err(PdfError::Operation(_))),
+                "{:?}",
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn test_images_to_pdf_frames() {
+        use image::codecs::gif::GifEncoder;
+        use image::ImageEncoder;
+
+        let dir = std::env::temp_dir().join("rust_pdf_test_images_frames");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        // A 40 x 20 JPEG whose EXIF data says to turn it 90 degrees clockwise
+        let photo = dir.join("photo.jpg");
+        let mut jpeg_data = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg_data);
+        encoder
+            .set_exif_metadata(vec![
+                b'M', b'M', 0, 42, 0, 0, 0, 8, // TIFF header
+                0, 1, // one entry: Orientation, SHORT, 1 value, 6
+                1, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, //
+                0, 0, 0, 0, // no next directory
+            ])
+            .unwrap();
+        encoder
+            .write_image(
+                image::RgbImage::from_pixel(40, 20, image::Rgb([200, 0, 0])).as_raw(),
+                40,
+                20,
+                image::ExtendedColorType::Rgb8,
+            )
+            .unwrap();
+        std::fs::write(&photo, &jpeg_data).unwrap();
+
+        // A two-page TIFF, gray then CMYK
+        let scan = dir.join("scan.tif");
+        let mut tiff =
+            tiff::encoder::TiffEncoder::new(std::fs::File::create(&scan).unwrap()).unwrap();
+        tiff.write_image::<tiff::encoder::colortype::Gray8>(8, 8, &[128; 64])
+            .unwrap();
+        tiff.write_image::<tiff::encoder::colortype::CMYK8>(4, 4, &[0, 255, 255, 0].repeat(16))
+            .unwrap();
+        drop(tiff);
+
+        // An animated GIF with three frames
+        let animation = dir.join("animation.gif");
+        let mut gif = GifEncoder::new(std::fs::File::create(&animation).unwrap());
+        for shade in [0, 100, 200] {
+            let frame = image::RgbaImage::from_pixel(6, 6, image::Rgba([shade, shade, shade, 255]));
+            gif.encode_frame(image::Frame::new(frame)).unwrap();
+        }
+        drop(gif);
+
+        let output = dir.join("frames.pdf");
+        let options = ImagePdfOptions {
+            page_size: PageSize::Image,
+            dpi: 72.0,
+            ..Default::default()
+        };
+        images_to_pdf_with_options(&[photo, scan, animation], output.clone(), &options).unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 6);
+        let page = |number: u32| {
+            let page_id = pages[&number];
+            let media_box: Vec<f32> = doc
+                .get_dictionary(page_id)
+                .unwrap()
+                .get(b"MediaBox")
+                .unwrap()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|o| o.as_float().unwrap())
+                .collect();
+            let resources = doc
+                .get_dictionary(page_id)
+                .unwrap()
+                .get(b"Resources")
+                .unwrap();
+            let (_, image) =
+                crate::xobject::lookup(&doc, resources.as_dict().unwrap(), b"Im1").unwrap();
+            let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+            let cm = content
+                .operations
+                .iter()
+                .find(|op| op.operator == "cm")
+                .unwrap()
+                .operands
+                .iter()
+                .map(|o| o.as_float().unwrap())
+                .collect::<Vec<f32>>();
+            (media_box, image.clone(), cm)
+        };
+
+        // The photo stands upright on a portrait page, its JPEG data untouched
+        let (media_box, image, cm) = page(1);
+        assert_eq!(media_box, vec![0.0, 0.0, 20.0, 40.0]);
+        assert_eq!(image.content, jpeg_data);
+        assert_eq!(cm, vec![0.0, -40.0, 20.0, 0.0, 0.0, 40.0]);
+
+        let (_, image, _) = page(3);
+        assert_eq!(
+            image
+                .dict
+                .get(b"ColorSpace")
+                .unwrap()
+                .as_name_str()
+                .unwrap(),
+            "DeviceCMYK"
+        );
+        let (media_box, _, _) = page(6);
+        assert_eq!(media_box, vec![0.0, 0.0, 6.0, 6.0]);
+
+        // HEIC files are refused by name
+        let heic = dir.join("photo.heic");
+        std::fs::write(&heic, b"\0\0\0\x18ftypheic\0\0\0\0mif1heic").unwrap();
+        let result = images_to_pdf(&[heic], dir.join("heic.pdf"));
+        assert!(matches!(result, Err(PdfError::Operation(e)) if e.contains("HEIC")));
+    }
+
+    #[test]
+    fn test_images_to_pdf_grid() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_images_grid");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let mut paths = Vec::new();
+        for name in ["receipt 1", "receipt 2", "收据", "receipt 4", "receipt 5"] {
+            let path = dir.join(format!("{}.png", name));
+            image::GrayImage::from_pixel(100, 100, image::Luma([90]))
+                .save(&path)
+                .unwrap();
+            paths.push(path);
+        }
+        let output = dir.join("grid.pdf");
+        let options = ImagePdfOptions {
+            margin: 20.0,
+            grid: Some(ImageGrid {
+                rows: 2,
+                columns: 2,
+                gutter: 10.0,
+            }),
+            captions: true,
+            ..Default::default()
+        };
+        images_to_pdf_with_options(&paths, output.clone(), &options).unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+        let placements = |number: u32| -> Vec<Vec<f32>> {
+            let content = Content::decode(&doc.get_page_content(pages[&number]).unwrap()).unwrap();
+            content
+                .operations
+                .iter()
+                .filter(|op| op.operator == "cm")
+                .map(|op| {
+                    op.operands
+                        .iter()
+                        .map(|o| o.as_float().unwrap().round())
+                        .collect()
+                })
+                .collect()
+        };
+
+        // Square images fill the width of their cells in a portrait A4 grid
+        let first = placements(1);
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[0][..5], [273.0, 0.0, 0.0, 273.0, 20.0]);
+        assert_eq!(first[1][4], 303.0);
+        assert_eq!(first[2][4], 20.0);
+        assert!(first[2][5] < first[0][5]);
+        assert_eq!(placements(2).len(), 1);
+
+        let text = extract_text(&output, None, None).unwrap().join("\n");
+        for name in ["receipt 1", "收据", "receipt 5"] {
+            assert!(text.contains(name), "{:?} not in {:?}", name, text);
+        }
+
+        // Each script gets a font that has it; Arabic is in none and turns into "?"
+        for (name, font, shown) in [
+            ("квитанция", "STSong-Light", "квитанция"),
+            ("영수증", "HYSMyeongJo-Medium", "영수증"),
+            ("レシート", "KozMinPro-Regular", "レシート"),
+            ("إيصال 2", "Helvetica", "????? 2"),
+        ] {
+            let path = dir.join(format!("{}.png", name));
+            std::fs::copy(&paths[0], &path).unwrap();
+            images_to_pdf_with_options(&[path], output.clone(), &options).unwrap();
+
+            let doc = Document::load(&output).unwrap();
+            let fonts = doc.get_page_fonts(doc.get_pages()[&1]);
+            let base_fonts: Vec<&[u8]> = fonts
+                .values()
+                .map(|f| f.get(b"BaseFont").and_then(Object::as_name).unwrap())
+                .collect();
+            assert!(
+                base_fonts.iter().all(|f| f.starts_with(font.as_bytes())),
+                "{}",
+                name
+            );
+            let text = extract_text(&output, None, None).unwrap().join("\n");
+            assert_eq!(text.trim(), shown);
+        }
+    }
+
+    #[test]
+    fn test_images_to_pdf_scan_cleanup() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_images_scan");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let output = dir.join("scan.pdf");
+        // The decoded image on the first page, its filter and bits per sample
+        let convert = |path: &std::path::PathBuf, scan: ScanCleanup| {
+            let options = ImagePdfOptions {
+                scan: Some(scan),
+                ..Default::default()
+            };
+            images_to_pdf_with_options(std::slice::from_ref(path), output.clone(), &options)
+                .unwrap();
+            let doc = Document::load(&output).unwrap();
+            let page_id = *doc.get_pages().get(&1).unwrap();
+            let resources = doc
+                .get_dictionary(page_id)
+                .unwrap()
+                .get(b"Resources")
+                .unwrap();
+            let (id, stream) =
+                crate::xobject::lookup(&doc, resources.as_dict().unwrap(), b"Im1").unwrap();
+            let filter = stream
+                .dict
+                .get(b"Filter")
+                .unwrap()
+                .as_name_str()
+                .unwrap()
+                .to_string();
+            let bits = stream
+                .dict
+                .get(b"BitsPerComponent")
+                .unwrap()
+                .as_i64()
+                .unwrap();
+            let image = crate::xobject::decode_image(&doc, id.unwrap()).unwrap();
+            (image.into_luma8(), filter, bits)
+        };
+
+        // A light sheet photographed at an angle on a dark table
+        let corners = [(90.0, 60.0), (520.0, 80.0), (500.0, 400.0), (110.0, 390.0)];
+        let inside = |x: f32, y: f32| {
+            (0..4).all(|i| {
+                let ((x0, y0), (x1, y1)): ((f32, f32), (f32, f32)) =
+                    (corners[i], corners[(i + 1) % 4]);
+                (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0) >= 0.0
+            })
+        };
+        let photo = image::RgbImage::from_fn(600, 450, |x, y| {
+            if inside(x as f32, y as f32) {
+                image::Rgb([235, 232, 225])
+            } else {
+                image::Rgb([50, 55, 60])
+            }
+        });
+        let photo_path = dir.join("photo.png");
+        photo.save(&photo_path).unwrap();
+        let (sheet, filter, _) = convert(
+            &photo_path,
+            ScanCleanup {
+                detect_edges: true,
+                deskew: false,
+                trim_borders: false,
+                color: ScanColor::Grayscale,
+            },
+        );
+        assert_eq!(filter, "DCTDecode");
+        // Cut out as wide as its top edge and as tall as its left edge
+        let (width, height) = sheet.dimensions();
+        assert!((width as i32 - 430).abs() <= 6, "width {}", width);
+        assert!((height as i32 - 331).abs() <= 6, "height {}", height);
+        for (x, y) in [
+            (2, 2),
+            (width - 3, 2),
+            (2, height - 3),
+            (width - 3, height - 3),
+        ] {
+            assert!(
+                sheet.get_pixel(x, y).0[0] > 180,
+                "dark corner at {} {}",
+                x,
+                y
+            );
+        }
+        // Every step with a threshold that adapts to the light leaves a blank white page
+        let (sheet, _, _) = convert(
+            &photo_path,
+            ScanCleanup {
+                color: ScanColor::Bilevel { threshold: None },
+                ..Default::default()
+            },
+        );
+        let black = sheet.pixels().filter(|p| p.0[0] == 0).count();
+        assert!(black * 100 < sheet.len(), "{} black pixels", black);
 
-    let input_path = input.as_ref();
-    let output_base = output_dir.as_ref();
+        // Ten lines of round glyphs in random sizes, tilted 3 degrees clockwise on white paper
+        let (sin, cos) = 3f32.to_radians().sin_cos();
+        let text = image::RgbImage::from_fn(800, 600, |x, y| {
+            let (x, y) = (x as f32 - 400.0, y as f32 - 300.0);
+            // Position along and across the lines
+            let (along, across) = (x * cos + y * sin + 300.0, y * cos - x * sin + 200.0);
+            let (glyph, line) = ((along / 16.0).floor(), (across / 40.0).floor());
+            let radius = 3.0 + ((glyph * 7.0 + line * 13.0) % 4.0);
+            let (dx, dy) = (along % 16.0 - 8.0, across % 40.0 - 20.0);
+            let ink = (0.0..600.0).contains(&along)
+                && (0.0..400.0).contains(&across)
+                && (dx.hypot(dy) - radius).abs() < 1.5;
+            if ink {
+                image::Rgb([20, 20, 20])
+            } else {
+                image::Rgb([250, 250, 250])
+            }
+        });
+        let text_path = dir.join("text.png");
+        text.save(&text_path).unwrap();
+        let (page, filter, bits) = convert(
+            &text_path,
+            ScanCleanup {
+                detect_edges: false,
+                deskew: true,
+                trim_borders: true,
+                color: ScanColor::Bilevel {
+                    threshold: Some(128),
+                },
+            },
+        );
+        // Group 4 fax data or Flate, whichever is smaller
+        assert!(matches!(filter.as_str(), "CCITTFaxDecode" | "FlateDecode"));
+        assert_eq!(bits, 1);
+        assert!(page.pixels().all(|p| matches!(p.0[0], 0 | 255)));
+        // Trimmed to the text, with a little room around it
+        let (width, height) = page.dimensions();
+        assert!((600..650).contains(&width), "width {}", width);
+        assert!((380..430).contains(&height), "height {}", height);
+        // Leveled, with blank rows between the lines
+        let blank = page.rows().filter(|row| row.clone().all(|p| p.0[0] == 255));
+        assert!(blank.count() > 200);
+    }
 
-    // 1. Determine output sub-directory name
-    let file_stem = input_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-    let target_dir = output_base.join(file_stem);
+    #[test]
+    fn test_render_with_hayro() {
+        let _cache = THUMBNAIL_CACHE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join("rust_pdf_test_render_hayro");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        // A black square in the lower left quarter of each A4 page
+        let square = || Content {
+            operations: vec![
+                Operation::new("g", vec![0.into()]),
+                Operation::new("re", vec![0.into(), 0.into(), 297.into(), 421.into()]),
+                Operation::new("f", vec![]),
+            ],
+        };
+        let input = dir.join("square.pdf");
+        create_content_pdf(&input, vec![square(), square()]).unwrap();
+
+        let options = RenderOptions {
+            size: RenderSize::Fit {
+                width: Some(119),
+                height: None,
+            },
+            pages: Some("2".to_string()),
+            ..Default::default()
+        };
+        let paths = pdf_to_images(&input, &dir, &HayroRenderer, &options, None).unwrap();
+        assert_eq!(paths, vec![dir.join("square/square_page_002.png")]);
+        let page = image::open(&paths[0]).unwrap().to_rgb8();
+        assert_eq!(page.dimensions(), (119, 168));
+        assert_eq!(page.get_pixel(20, 150).0, [0, 0, 0]);
+        assert_eq!(page.get_pixel(100, 20).0, [255, 255, 255]);
+
+        // Sizes that give no pixels are refused
+        for size in [
+            RenderSize::Dpi(f32::NAN),
+            RenderSize::Dpi(-72.0),
+            RenderSize::Fit {
+                width: Some(0),
+                height: None,
+            },
+        ] {
+            let options = RenderOptions {
+                size,
+                ..Default::default()
+            };
+            let result = pdf_to_images(&input, &dir, &HayroRenderer, &options, None);
+            assert!(matches!(result, Err(PdfError::Operation(_))), "{:?}", size);
+        }
+
+        // Contact sheet tiles are rendered at tile size, not at the page size
+        let options = RenderOptions {
+            output: RenderOutput::ContactSheet {
+                columns: 2,
+                spacing: 2,
+                tile: 60,
+            },
+            ..Default::default()
+        };
+        let paths = pdf_to_images(&input, &dir, &HayroRenderer, &options, None).unwrap();
+        let sheet = image::open(&paths[0]).unwrap();
+        assert_eq!(
+            (sheet.width(), sheet.height()),
+            (2 * 42 + 3 * 2, 60 + 2 * 2)
+        );
 
-    if !target_dir.exists() {
-        std::fs::create_dir_all(&target_dir)
-            .map_err(|e| PdfError::Operation(format!("Failed to create output dir: {}", e)))?;
+        let mut thumbnails = Vec::new();
+        render_page_thumbnails(&input, &HayroRenderer, None, 64, None, |page, png| {
+            thumbnails.push((
+                page,
+                image::load_from_memory(&png)
+                    .unwrap()
+                    .to_rgb8()
+                    .dimensions(),
+            ));
+            true
+        })
+        .unwrap();
+        assert_eq!(thumbnails, vec![(1, (45, 64)), (2, (45, 64))]);
     }
 
-    // 2. Initialize Pdfium
-    let pdfium = Pdfium::new(
-        Pdfium::bind_to_library(pdfium_path.as_ref().to_str().ok_or_else(|| PdfError::Operation("Invalid PDFium path".to_string()))?)
-            .or_else(|_| Pdfium::bind_to_system_library())
-            .map_err(|e| PdfError::Operation(format!("Failed to bind Pdfium at {:?}: {}. Please ensure pdfium.dll is correct and has required runtime dependencies.", pdfium_path.as_ref(), e)))?,
-    );
+    #[test]
+    fn test_encrypt_pdf() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_encrypt");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input = dir.join("plain.pdf");
+        create_dummy_pdf(&input, 3).unwrap();
 
-    // 3. Load Document
-    let document = pdfium
-        .load_pdf_from_file(input_path, None)
-        .map_err(|e| PdfError::Operation(format!("Failed to load PDF: {}", e)))?;
+        for algorithm in [EncryptionAlgorithm::Aes128, EncryptionAlgorithm::Aes256] {
+            let options = EncryptionOptions {
+                user_password: "user".to_string(),
+                owner_password: "owner".to_string(),
+                algorithm,
+                permissions: Permissions {
+                    print: false,
+                    ..Default::default()
+                },
+            };
+            let output = dir.join(format!("{:?}.pdf", algorithm));
+            encrypt_pdf(&input, &output, &options, None).unwrap();
 
-    // 4. Render Pages
-    let render_config = PdfRenderConfig::new()
-        .set_target_width(2000) // Set a reasonable high width for "HD" quality, aspect ratio preserved
-        .set_maximum_height(2000)
-        .rotate_if_landscape(PdfPageRenderRotation::None, true); // Auto rotate if needed? Maybe no, let's keep original
+            let raw = Document::load(&output).unwrap();
+            let p = raw.get_encrypted().unwrap().get(b"P").unwrap();
+            assert_eq!(p.as_i64().unwrap() & 0b100, 0, "print must be denied");
 
-    for (i, page) in document.pages().iter().enumerate() {
-        let bitmap = page
-            .render_with_config(&render_config)
-            .map_err(|e| PdfError::Operation(format!("Failed to render page {}: {}", i, e)))?;
+            assert!(crate::security::needs_password(&output).unwrap());
+            assert!(matches!(
+                get_page_count(&output, Some("nope")),
+                Err(PdfError::WrongPassword)
+            ));
+            for password in ["user", "owner"] {
+                let doc = load_document(&output, Some(password)).unwrap();
+                assert_eq!(page_texts(&doc), vec!["Page 1", "Page 2", "Page 3"]);
+            }
+        }
 
-        let image = bitmap.as_image(); // RgbaImage
+        // As an output option of merge and split
+        let options = EncryptionOptions {
+            user_password: "user".to_string(),
+            ..Default::default()
+        };
+        let merged = dir.join("merged.pdf");
+        let merge_options = MergeOptions {
+            encryption: Some(options.clone()),
+            ..Default::default()
+        };
+        merge_pdf_with_options(&[&input, &input], &merged, &merge_options).unwrap();
+        assert_eq!(get_page_count(&merged, Some("user")).unwrap(), 6);
 
-        // 5. Save Image
-        let image_filename = format!("{}_page_{:03}.png", file_stem, i + 1);
-        let image_path = target_dir.join(image_filename);
+        let parts = split_pdf_by_ranges(
+            &merged,
+            &dir,
+            "part",
+            "1-2,3-",
+            Some("user"),
+            Some(&options),
+        )
+        .unwrap();
+        assert!(matches!(
+            get_page_count(&parts[0], None),
+            Err(PdfError::PasswordRequired)
+        ));
+        assert_eq!(get_page_count(&parts[1], Some("user")).unwrap(), 4);
 
-        image
-            .save_with_format(&image_path, image::ImageFormat::Png)
-            .map_err(|e| PdfError::Operation(format!("Failed to save image: {}", e)))?;
+        // Page edits only keep the output protected when asked to
+        let edited = dir.join("edited.pdf");
+        reorder_pages(&merged, &[2, 1], &edited, Some("user"), Some(&options)).unwrap();
+        assert!(matches!(
+            get_page_count(&edited, None),
+            Err(PdfError::PasswordRequired)
+        ));
+        rotate_pages(&edited, &[(1, 90)], &edited, Some("user"), Some(&options)).unwrap();
+        let rotated = rotate_pages_to_orientation(
+            &edited,
+            Orientation::Portrait,
+            &edited,
+            Some("user"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(rotated, vec![1]);
+        assert!(!crate::security::needs_password(&edited).unwrap());
+        assert_eq!(get_page_count(&edited, None).unwrap(), 2);
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_encrypt_pdf_keeps_version_and_extensions() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_encrypt_extensions");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let options = EncryptionOptions {
+            user_password: "new".to_string(),
+            algorithm: EncryptionAlgorithm::Aes256,
+            ..Default::default()
+        };
 
-fn extract_text_from_object(obj: &Object) -> Result<String, ()> {
-    match obj {
-        Object::String(bytes, _) => Ok(String::from_utf8_lossy(bytes).to_string()),
-        Object::Array(arr) => {
-            let mut s = String::new();
-            for item in arr {
-                if let Ok(text) = extract_text_from_object(item) {
-                    s.push_str(&text);
-                }
+        // Encrypted by tests/fixtures/make_encrypted.py, with its own /Extensions entries
+        let input = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/encrypted_objstm_aes256.pdf");
+        let output = dir.join("reencrypted.pdf");
+        encrypt_pdf(&input, &output, &options, Some("owner")).unwrap();
+        let doc = load_document(&output, Some("new")).unwrap();
+        assert_eq!(page_texts(&doc), vec!["Page 1", "Page 2"]);
+        let extensions = doc
+            .catalog()
+            .unwrap()
+            .get(b"Extensions")
+            .and_then(Object::as_dict)
+            .unwrap();
+        let level = |prefix: &[u8]| {
+            extensions
+                .get(prefix)
+                .and_then(Object::as_dict)
+                .and_then(|entry| entry.get(b"ExtensionLevel"))
+                .and_then(Object::as_i64)
+                .unwrap()
+        };
+        assert_eq!(level(b"ADBE"), 8);
+        assert_eq!(level(b"EXMP"), 3);
+
+        // Versions compare as numbers, so 1.10 is not lowered to 1.6
+        let input = dir.join("plain.pdf");
+        create_dummy_pdf(&input, 1).unwrap();
+        let mut doc = Document::load(&input).unwrap();
+        doc.version = "1.10".to_string();
+        doc.save(&input).unwrap();
+        let output = dir.join("aes128.pdf");
+        let options = EncryptionOptions {
+            algorithm: EncryptionAlgorithm::Aes128,
+            ..options
+        };
+        encrypt_pdf(&input, &output, &options, None).unwrap();
+        assert_eq!(Document::load(&output).unwrap().version, "1.10");
+    }
+
+    #[test]
+    fn test_split_pdf() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 5).unwrap();
+
+        let result = split_pdf(&input_path, &dir, "split", 1, 2).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result[0].exists());
+
+        // Verify page count of split result
+        let doc = Document::load(&result[0]).unwrap();
+        assert_eq!(doc.get_pages().len(), 2);
+    }
+
+    #[test]
+    fn test_split_pdf_by_ranges() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_ranges");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 7).unwrap();
+
+        let result =
+            split_pdf_by_ranges(&input_path, &dir, "part", "1-3,5,6-", None, None).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result[1].ends_with("part_5.pdf"));
+
+        let counts: Vec<usize> = result
+            .iter()
+            .map(|p| Document::load(p).unwrap().get_pages().len())
+            .collect();
+        assert_eq!(counts, vec![3, 1, 2]);
+
+        let result =
+            split_pdf_by_ranges(&input_path, &dir, "chunk", "every 3 pages", None, None).unwrap();
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn test_split_pdf_by_ranges_drops_links_to_other_pages() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_ranges_links");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 4).unwrap();
+        add_page_link(&input_path, 1, 4).unwrap();
+
+        let result = split_pdf_by_ranges(&input_path, &dir, "single", "1", None, None).unwrap();
+        let doc = Document::load(&result[0]).unwrap();
+        let page_objects = doc
+            .objects
+            .values()
+            .filter_map(|o| o.as_dict().ok())
+            .filter(|dict| dict.get(b"Type").and_then(Object::as_name).ok() == Some(b"Page"))
+            .count();
+        assert_eq!(page_objects, 1);
+        assert_eq!(page_texts(&doc), vec!["Page 1"]);
+        // Catalog, page tree, page, contents, resources, font, the now inert link and the xref stream
+        assert_eq!(doc.objects.len(), 8);
+    }
+
+    #[test]
+    fn test_split_pdf_by_size() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_size");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 12).unwrap();
+        let max_bytes = std::fs::metadata(&input_path).unwrap().len() / 3;
+
+        let parts = split_pdf_by_size(&input_path, &dir, "sized", max_bytes, None, None).unwrap();
+        assert!(parts.len() > 1);
+
+        let mut next_page = 1;
+        for part in &parts {
+            assert_eq!(part.first_page, next_page);
+            assert!(part.size <= max_bytes);
+            assert_eq!(std::fs::metadata(&part.path).unwrap().len(), part.size);
+            let doc = Document::load(&part.path).unwrap();
+            assert_eq!(
+                doc.get_pages().len() as u32,
+                part.last_page - part.first_page + 1
+            );
+            next_page = part.last_page + 1;
+        }
+        assert_eq!(next_page, 13);
+    }
+
+    #[test]
+    fn test_split_pdf_by_size_drops_links_to_other_parts() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_size_links");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 10).unwrap();
+        pad_pages(&input_path, 100_000).unwrap();
+        add_page_link(&input_path, 1, 10).unwrap();
+
+        let max_bytes = 350_000;
+        let parts = split_pdf_by_size(&input_path, &dir, "linked", max_bytes, None, None).unwrap();
+        assert_eq!(parts.len(), 4);
+        for part in &parts {
+            assert!(part.size <= max_bytes, "{} bytes", part.size);
+        }
+        let first = Document::load(&parts[0].path).unwrap();
+        assert_eq!(first.get_pages().len(), 3);
+    }
+
+    /// Appends an uncompressed content stream of about `bytes` bytes to every page.
+    fn pad_pages(path: &Path, bytes: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::load(path)?;
+        for page_id in doc.get_pages().into_values() {
+            let content = doc.get_dictionary(page_id)?.get(b"Contents")?.clone();
+            let padding = format!("%{}\n", "x".repeat(bytes)).into_bytes();
+            let padding_id = doc.add_object(Stream::new(dictionary! {}, padding));
+            doc.get_dictionary_mut(page_id)?
+                .set("Contents", vec![content, padding_id.into()]);
+        }
+        doc.save(path)?;
+        Ok(())
+    }
+
+    /// Adds a link annotation on page `from` whose `/Dest` is page `to`.
+    fn add_page_link(path: &Path, from: u32, to: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::load(path)?;
+        let pages = doc.get_pages();
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Dest" => vec![pages[&to].into(), "Fit".into()],
+        });
+        doc.get_dictionary_mut(pages[&from])?
+            .set("Annots", vec![Object::Reference(link_id)]);
+        doc.save(path)?;
+        Ok(())
+    }
+
+    /// Adds a flat outline to an existing PDF, one top-level bookmark per `(title, page)`.
+    fn add_outline(
+        path: &Path,
+        bookmarks: &[(&str, u32)],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::load(path)?;
+        let pages = doc.get_pages();
+        let outlines_id = doc.new_object_id();
+        let item_ids: Vec<ObjectId> = bookmarks.iter().map(|_| doc.new_object_id()).collect();
+
+        for (i, (title, page)) in bookmarks.iter().enumerate() {
+            let mut item = dictionary! {
+                "Title" => Object::string_literal(*title),
+                "Parent" => outlines_id,
+                "Dest" => vec![pages[page].into(), "Fit".into()],
+            };
+            if i > 0 {
+                item.set("Prev", item_ids[i - 1]);
             }
-            Ok(s)
+            if let Some(&next) = item_ids.get(i + 1) {
+                item.set("Next", next);
+            }
+            doc.objects.insert(item_ids[i], Object::Dictionary(item));
         }
-        _ => Err(()),
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => item_ids[0],
+                "Last" => item_ids[item_ids.len() - 1],
+                "Count" => item_ids.len() as i32,
+            }),
+        );
+        doc.catalog_mut()?.set("Outlines", outlines_id);
+        doc.save(path)?;
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use lopdf::content::{Content, Operation};
-    use lopdf::{dictionary, Stream};
+    #[test]
+    fn test_split_pdf_by_bookmarks() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_bookmarks");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 8).unwrap();
+        add_outline(
+            &input_path,
+            &[("Intro", 1), ("Part 1/2", 3), ("Part 1/2", 5), ("Con", 7)],
+        )
+        .unwrap();
 
-    fn create_dummy_pdf(path: &Path, pages: u32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut doc = Document::with_version("1.5");
-        let pages_id = doc.new_object_id();
+        let result = split_pdf_by_bookmarks(&input_path, &dir, 1, None, None).unwrap();
+        assert_eq!(result.len(), 4);
+        assert!(result[0].ends_with("Intro.pdf"));
+        assert!(result[1].ends_with("Part 1_2.pdf"));
+        assert!(result[2].ends_with("Part 1_2 (2).pdf"));
+        assert!(result[3].ends_with("Con_.pdf"));
+
+        // A second run leaves the first run's files alone
+        let again = split_pdf_by_bookmarks(&input_path, &dir, 1, None, None).unwrap();
+        assert!(again[0].ends_with("Intro (2).pdf"));
+        assert!(again[1].ends_with("Part 1_2 (3).pdf"));
+        assert!(again[2].ends_with("Part 1_2 (4).pdf"));
+
+        let counts: Vec<usize> = result
+            .iter()
+            .map(|p| Document::load(p).unwrap().get_pages().len())
+            .collect();
+        assert_eq!(counts, vec![2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_split_pdf_by_bookmarks_drops_toc_links() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_split_bookmarks_toc");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let input_path = dir.join("input.pdf");
+        create_dummy_pdf(&input_path, 6).unwrap();
+        // A table of contents on page 1 linking to the second chapter
+        add_page_link(&input_path, 1, 4).unwrap();
+        add_outline(&input_path, &[("One", 1), ("Two", 4)]).unwrap();
+
+        let result = split_pdf_by_bookmarks(&input_path, &dir, 1, None, None).unwrap();
+        assert_eq!(result.len(), 2);
+        let first = Document::load(&result[0]).unwrap();
+        assert_eq!(page_texts(&first), vec!["Page 1", "Page 2", "Page 3"]);
+        let page_objects = first
+            .objects
+            .values()
+            .filter_map(|o| o.as_dict().ok())
+            .filter(|dict| dict.get(b"Type").and_then(Object::as_name).ok() == Some(b"Page"))
+            .count();
+        assert_eq!(page_objects, 3);
+    }
+
+    /// Adds a `/Dests` entry `name` for page 1 and a link to it on the last page.
+    fn add_named_dest_link(path: &Path, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::load(path)?;
+        let pages = doc.get_pages();
+        let first = pages[&1];
+        let last = *pages.values().last().unwrap();
+        let dests_id = doc.add_object(dictionary! {
+            name => vec![first.into(), "Fit".into()],
+        });
+        doc.catalog_mut()?.set("Dests", dests_id);
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Dest" => Object::Name(name.as_bytes().to_vec()),
+        });
+        doc.get_dictionary_mut(last)?
+            .set("Annots", vec![Object::Reference(link_id)]);
+        doc.save(path)?;
+        Ok(())
+    }
+
+    /// Adds a form with a text field `field` on page 1, using `base_font` as `/Helv`.
+    fn add_form(
+        path: &Path,
+        field: &str,
+        base_font: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = Document::load(path)?;
+        let page = doc.get_pages()[&1];
         let font_id = doc.add_object(dictionary! {
             "Type" => "Font",
             "Subtype" => "Type1",
-            "BaseFont" => "Courier",
+            "BaseFont" => base_font,
         });
-        let resources_id = doc.add_object(dictionary! {
-            "Font" => dictionary! {
-                "F1" => Object::Reference(font_id),
-            },
+        let field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal(field),
+            "DA" => Object::string_literal("/Helv 12 Tf 0 g"),
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "P" => page,
         });
+        doc.get_dictionary_mut(page)?
+            .set("Annots", vec![Object::Reference(field_id)]);
+        let form_id = doc.add_object(dictionary! {
+            "Fields" => vec![Object::Reference(field_id)],
+            "DA" => Object::string_literal("/Helv 0 Tf 0 g"),
+            "DR" => dictionary! { "Font" => dictionary! { "Helv" => font_id } },
+        });
+        doc.catalog_mut()?.set("AcroForm", form_id);
+        doc.save(path)?;
+        Ok(())
+    }
 
-        let mut page_ids = Vec::new();
+    #[test]
+    fn test_merge_pdf_forms() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_merge_forms");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let p1 = dir.join("first.pdf");
+        let p2 = dir.join("second.pdf");
+        let p3 = dir.join("third.pdf");
+        create_dummy_pdf(&p1, 1).unwrap();
+        create_dummy_pdf(&p2, 1).unwrap();
+        create_dummy_pdf(&p3, 1).unwrap();
+        add_form(&p1, "name", "Helvetica").unwrap();
+        add_form(&p2, "name", "Times-Roman").unwrap();
+        add_form(&p3, "date", "Helvetica").unwrap();
+        let output = dir.join("merged.pdf");
+        merge_pdf(&[p1, p2, p3], output.clone()).unwrap();
 
-        for i in 1..=pages {
-            let content = Content {
-                operations: vec![
-                    Operation::new("BT", vec![]),
-                    Operation::new("Tf", vec!["F1".into(), 48.into()]),
-                    Operation::new("Td", vec![100.into(), 600.into()]),
-                    Operation::new(
-                        "Tj",
-                        vec![Object::String(
-                            format!("Page {}", i).into_bytes(),
-                            lopdf::StringFormat::Literal,
-                        )],
-                    ),
-                    Operation::new("ET", vec![]),
-                ],
-            };
-            let stream = Stream::new(dictionary! {}, content.encode().unwrap());
-            let stream_id = doc.add_object(stream);
+        let doc = Document::load(&output).unwrap();
+        let form = doc
+            .catalog()
+            .unwrap()
+            .get(b"AcroForm")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .unwrap();
+        // Same-named fields of different inputs stay separate fields
+        let fields: Vec<(String, String)> = form
+            .get(b"Fields")
+            .and_then(Object::as_array)
+            .unwrap()
+            .iter()
+            .map(|field| {
+                let field = doc.get_dictionary(field.as_reference().unwrap()).unwrap();
+                let text = |key: &[u8]| {
+                    String::from_utf8_lossy(field.get(key).unwrap().as_str().unwrap()).to_string()
+                };
+                (text(b"T"), text(b"DA"))
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("name".to_string(), "/Helv 12 Tf 0 g".to_string()),
+                ("name_2".to_string(), "/Helv_2 12 Tf 0 g".to_string()),
+                ("date".to_string(), "/Helv 12 Tf 0 g".to_string()),
+            ]
+        );
+        // Every input's fonts are in the default resources; identical ones only once
+        let fonts = form
+            .get(b"DR")
+            .and_then(Object::as_dict)
+            .unwrap()
+            .get(b"Font")
+            .and_then(Object::as_dict)
+            .unwrap();
+        let base_font = |name: &[u8]| {
+            let font = doc
+                .get_dictionary(fonts.get(name).unwrap().as_reference().unwrap())
+                .unwrap();
+            font.get(b"BaseFont")
+                .unwrap()
+                .as_name_str()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(fonts.len(), 2);
+        assert_eq!(base_font(b"Helv"), "Helvetica");
+        assert_eq!(base_font(b"Helv_2"), "Times-Roman");
+    }
 
-            let page_id = doc.add_object(dictionary! {
-                "Type" => "Page",
-                "Parent" => Object::Reference(pages_id),
-                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
-                "Contents" => Object::Reference(stream_id),
-                "Resources" => Object::Reference(resources_id),
-            });
-            page_ids.push(Object::Reference(page_id));
+    #[test]
+    fn test_merge_pdf_keeps_navigation() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_merge_navigation");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
         }
+        let p1 = dir.join("first.pdf");
+        let p2 = dir.join("second.pdf");
+        create_dummy_pdf(&p1, 1).unwrap();
+        create_dummy_pdf(&p2, 2).unwrap();
+        add_outline(&p1, &[("A", 1)]).unwrap();
+        add_outline(&p2, &[("B", 1), ("C", 2)]).unwrap();
+        add_named_dest_link(&p1, "intro").unwrap();
+        add_named_dest_link(&p2, "intro").unwrap();
 
-        let pages_dict = dictionary! {
-            "Type" => "Pages",
-            "Kids" => page_ids,
-            "Count" => pages as i32,
+        let output = dir.join("merged.pdf");
+        merge_pdf(&[p1.clone(), p2.clone()], output.clone()).unwrap();
+        let doc = Document::load(&output).unwrap();
+        let titles: Vec<_> = read_outline(&doc)
+            .into_iter()
+            .map(|e| (e.title, e.depth, e.page.is_some()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("A".to_string(), 1, true),
+                ("B".to_string(), 1, true),
+                ("C".to_string(), 1, true),
+            ]
+        );
+
+        // The second input's destination was renamed, and its link follows it
+        let pages = doc.get_pages();
+        let dests = doc
+            .get_dict_in_dict(doc.catalog().unwrap(), b"Dests")
+            .unwrap();
+        assert!(dests.has(b"intro") && dests.has(b"intro_2"));
+        let link_dest = |page: u32| {
+            let annots = doc
+                .get_dictionary(pages[&page])
+                .unwrap()
+                .get(b"Annots")
+                .unwrap()
+                .as_array()
+                .unwrap();
+            let link = doc
+                .get_dictionary(annots[0].as_reference().unwrap())
+                .unwrap();
+            link.get(b"Dest").unwrap().as_name().unwrap().to_vec()
         };
-        doc.objects.insert(pages_id, Object::Dictionary(pages_dict));
+        assert_eq!(link_dest(1), b"intro".to_vec());
+        assert_eq!(link_dest(3), b"intro_2".to_vec());
 
-        let catalog_id = doc.add_object(dictionary! {
-            "Type" => "Catalog",
-            "Pages" => Object::Reference(pages_id),
-        });
-        doc.trailer
-            .set(b"Root".to_vec(), Object::Reference(catalog_id));
+        let options = MergeOptions {
+            outline_per_file: true,
+            ..Default::default()
+        };
+        merge_pdf_with_options(&[p1, p2], output.clone(), &options).unwrap();
+        let doc = Document::load(&output).unwrap();
+        let titles: Vec<_> = read_outline(&doc)
+            .into_iter()
+            .map(|e| (e.title, e.depth))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                ("first".to_string(), 1),
+                ("A".to_string(), 2),
+                ("second".to_string(), 1),
+                ("B".to_string(), 2),
+                ("C".to_string(), 2),
+            ]
+        );
+    }
 
-        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
-        doc.save_to(&mut file)?;
-        Ok(())
+    /// The text drawn on each page, in page order.
+    fn page_texts(doc: &Document) -> Vec<String> {
+        doc.get_pages()
+            .into_values()
+            .map(|id| {
+                let content = Content::decode(&doc.get_page_content(id).unwrap()).unwrap();
+                content
+                    .operations
+                    .iter()
+                    .filter(|op| op.operator == "Tj")
+                    .map(|op| String::from_utf8_lossy(op.operands[0].as_str().unwrap()).to_string())
+                    .collect()
+            })
+            .collect()
     }
 
     #[test]
-    fn test_split_pdf() {
-        let dir = std::env::temp_dir().join("rust_pdf_test_split");
+    fn test_merge_pdf_pages_interleave() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_merge_pages");
         if !dir.exists() {
             std::fs::create_dir(&dir).unwrap();
         }
-        let input_path = dir.join("input.pdf");
-        create_dummy_pdf(&input_path, 5).unwrap();
+        let fronts = dir.join("fronts.pdf");
+        let backs = dir.join("backs.pdf");
+        create_dummy_pdf(&fronts, 3).unwrap();
+        create_dummy_pdf(&backs, 3).unwrap();
+        add_outline(&fronts, &[("Start", 1), ("Dropped", 3)]).unwrap();
 
-        let result = split_pdf(&input_path, &dir, "split", 1, 2).unwrap();
-        assert_eq!(result.len(), 1);
-        assert!(result[0].exists());
+        let inputs = vec![
+            MergeInput {
+                pages: Some("1-2".to_string()),
+                ..MergeInput::new(&fronts)
+            },
+            MergeInput {
+                reverse: true,
+                ..MergeInput::new(&backs)
+            },
+        ];
+        let options = MergeOptions {
+            interleave: InterleaveMode::Alternate,
+            ..Default::default()
+        };
+        let output = dir.join("merged.pdf");
+        merge_pdf_pages(&inputs, &output, &options).unwrap();
 
-        // Verify page count of split result
-        let doc = Document::load(&result[0]).unwrap();
-        assert_eq!(doc.get_pages().len(), 2);
+        let doc = Document::load(&output).unwrap();
+        assert_eq!(
+            page_texts(&doc),
+            vec!["Page 1", "Page 3", "Page 2", "Page 2", "Page 1"]
+        );
+
+        // The bookmark to the dropped page stays, but the page itself is gone
+        let outline: Vec<_> = read_outline(&doc)
+            .into_iter()
+            .map(|e| (e.title, e.page.is_some()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![("Start".to_string(), true), ("Dropped".to_string(), false)]
+        );
+        let page_objects = doc
+            .objects
+            .values()
+            .filter(|o| o.type_name().ok() == Some("Page"))
+            .count();
+        assert_eq!(page_objects, 5);
+    }
+
+    #[test]
+    fn test_merge_pdf_pages_repeat_copies_annotations() {
+        let dir = std::env::temp_dir().join("rust_pdf_test_merge_repeat");
+        if !dir.exists() {
+            std::fs::create_dir(&dir).unwrap();
+        }
+        let input = dir.join("form.pdf");
+        create_dummy_pdf(&input, 1).unwrap();
+        add_form(&input, "name", "Helvetica").unwrap();
+        let inputs = vec![MergeInput {
+            pages: Some("1,1,1".to_string()),
+            ..MergeInput::new(&input)
+        }];
+        let output = dir.join("merged.pdf");
+        merge_pdf_pages(&inputs, &output, &MergeOptions::default()).unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        assert_eq!(pages.len(), 3);
+        // Every page has its own widget, and all of them are kids of the one field
+        let widgets: Vec<ObjectId> = pages
+            .iter()
+            .map(|&page| {
+                let annots = doc
+                    .get_dictionary(page)
+                    .unwrap()
+                    .get(b"Annots")
+                    .and_then(Object::as_array)
+                    .unwrap();
+                assert_eq!(annots.len(), 1);
+                let widget = annots[0].as_reference().unwrap();
+                let dict = doc.get_dictionary(widget).unwrap();
+                assert_eq!(dict.get(b"P").unwrap().as_reference().unwrap(), page);
+                assert!(!dict.has(b"T"));
+                widget
+            })
+            .collect();
+        assert_eq!(widgets.iter().collect::<HashSet<_>>().len(), 3);
+
+        let form = doc
+            .catalog()
+            .unwrap()
+            .get(b"AcroForm")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .unwrap();
+        let fields = form.get(b"Fields").and_then(Object::as_array).unwrap();
+        assert_eq!(fields.len(), 1);
+        let field_id = fields[0].as_reference().unwrap();
+        let field = doc.get_dictionary(field_id).unwrap();
+        assert_eq!(field.get(b"T").unwrap().as_str().unwrap(), b"name");
+        assert!(!field.has(b"Rect"));
+        let kids: Vec<ObjectId> = field
+            .get(b"Kids")
+            .and_then(Object::as_array)
+            .unwrap()
+            .iter()
+            .map(|kid| kid.as_reference().unwrap())
+            .collect();
+        assert_eq!(kids, widgets);
+        for widget in widgets {
+            let dict = doc.get_dictionary(widget).unwrap();
+            assert_eq!(
+                dict.get(b"Parent").unwrap().as_reference().unwrap(),
+                field_id
+            );
+        }
     }
 
     #[test]
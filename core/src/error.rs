@@ -11,6 +11,15 @@ pub enum PdfError {
     #[error("Invalid Page Number: {0}")]
     InvalidPage(u32),
 
+    #[error("Invalid Page Range: {0}")]
+    InvalidRange(String),
+
+    #[error("Password Required")]
+    PasswordRequired,
+
+    #[error("Wrong Password")]
+    WrongPassword,
+
     #[error("Operation Error: {0}")]
     Operation(String),
 }
@@ -0,0 +1,307 @@
+use crate::layout::{list_marker, Block, Headings, ListMarker, PageLayout, Paragraph};
+use crate::table::Table;
+use std::fmt::Write;
+
+/// Plain text in reading order: one line per paragraph with blank lines between them, table
+/// rows as tab-separated cells, and a form feed between pages.
+pub(crate) fn to_text(pages: &[PageLayout]) -> String {
+    let mut out = String::new();
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            out.push('\u{c}');
+        }
+        let mut first = true;
+        for block in page.blocks() {
+            let text = match block {
+                Block::Paragraph(p) => p.text(),
+                Block::Table(table) => table
+                    .cells
+                    .iter()
+                    .map(|row| {
+                        let cells: Vec<String> = row
+                            .iter()
+                            .map(|cell| cell_text(&cell.paragraphs) + &"\t".repeat(cell.span - 1))
+                            .collect();
+                        cells.join("\t")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Block::Image(_) => continue,
+            };
+            if !first {
+                out.push('\n');
+            }
+            out.push_str(text.trim());
+            out.push('\n');
+            first = false;
+        }
+    }
+    out
+}
+
+/// Markdown: `#` headings by relative font size, `-`/`1.` list items, pipe tables and
+/// bold/italic emphasis. Each page starts with a `<!-- Page N -->` comment and pages are
+/// separated by a `---` rule.
+pub(crate) fn to_markdown(pages: &[PageLayout]) -> String {
+    let headings = Headings::detect(pages.iter().flat_map(|page| &page.paragraphs));
+    let mut out = String::new();
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            out.push_str("---\n\n");
+        }
+        let _ = writeln!(out, "<!-- Page {} -->\n", index + 1);
+
+        let mut in_list = false;
+        for block in page.blocks() {
+            let item = match block {
+                Block::Paragraph(p) if headings.level(p).is_none() => {
+                    let text = p.text();
+                    list_marker(&text).map(|(marker, rest)| (marker, escape_markdown(rest)))
+                }
+                _ => None,
+            };
+            // List items are written without blank lines between them
+            if in_list && item.is_none() {
+                out.push('\n');
+            }
+            in_list = item.is_some();
+            if let Some((marker, rest)) = item {
+                let _ = match marker {
+                    ListMarker::Bullet => writeln!(out, "- {}", rest),
+                    ListMarker::Number(n) => writeln!(out, "{}. {}", n, rest),
+                    ListMarker::Letter(c) => writeln!(out, "- {}) {}", c, rest),
+                };
+                continue;
+            }
+
+            match block {
+                Block::Paragraph(p) => match headings.level(p) {
+                    Some(level) => {
+                        let _ = writeln!(
+                            out,
+                            "{} {}\n",
+                            "#".repeat(level),
+                            escape_markdown(p.text().trim())
+                        );
+                    }
+                    None => {
+                        let text = markdown_inline(p);
+                        // A leading `#` would make the paragraph a heading
+                        let escape = if text.starts_with('#') { "\\" } else { "" };
+                        let _ = writeln!(out, "{}{}\n", escape, text);
+                    }
+                },
+                Block::Table(table) => {
+                    out.push_str(&markdown_table(table));
+                    out.push('\n');
+                }
+                Block::Image(_) => {}
+            }
+        }
+        if in_list {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// An HTML document with one `<section>` per page, headings by relative font size, lists,
+/// tables and bold/italic text.
+pub(crate) fn to_html(pages: &[PageLayout], title: &str) -> String {
+    let headings = Headings::detect(pages.iter().flat_map(|page| &page.paragraphs));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>",
+        escape_html(title)
+    );
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            out.push_str("<hr>\n");
+        }
+        let _ = writeln!(out, "<section class=\"page\" id=\"page-{}\">", index + 1);
+
+        // The closing tag of the list being written
+        let mut open_list: Option<&str> = None;
+        for block in page.blocks() {
+            let item = match block {
+                Block::Paragraph(p) if headings.level(p).is_none() => {
+                    let text = p.text();
+                    list_marker(&text).map(|(marker, rest)| (marker, escape_html(rest)))
+                }
+                _ => None,
+            };
+            let tag = item.as_ref().map(|(marker, _)| match marker {
+                ListMarker::Bullet => "</ul>",
+                _ => "</ol>",
+            });
+            if open_list.is_some() && open_list != tag {
+                out.push_str(open_list.take().unwrap_or_default());
+                out.push('\n');
+            }
+            if let Some((marker, rest)) = item {
+                if open_list.is_none() {
+                    let _ = match marker {
+                        ListMarker::Bullet => writeln!(out, "<ul>"),
+                        ListMarker::Number(1) => writeln!(out, "<ol>"),
+                        ListMarker::Number(n) => writeln!(out, "<ol start=\"{}\">", n),
+                        ListMarker::Letter(_) => writeln!(out, "<ol type=\"a\">"),
+                    };
+                    open_list = tag;
+                }
+                let _ = writeln!(out, "<li>{}</li>", rest);
+                continue;
+            }
+
+            match block {
+                Block::Paragraph(p) => match headings.level(p) {
+                    Some(level) => {
+                        let _ =
+                            writeln!(out, "<h{level}>{}</h{level}>", escape_html(p.text().trim()));
+                    }
+                    None => {
+                        let _ = writeln!(out, "<p>{}</p>", html_inline(p));
+                    }
+                },
+                Block::Table(table) => out.push_str(&html_table(table)),
+                Block::Image(_) => {}
+            }
+        }
+        if let Some(tag) = open_list {
+            let _ = writeln!(out, "{}", tag);
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// The paragraph's text in stretches of the same (bold, italic) style.
+fn styled_spans(paragraph: &Paragraph) -> Vec<(String, bool, bool)> {
+    let mut spans: Vec<(String, bool, bool)> = Vec::new();
+    for (text, run) in paragraph.pieces() {
+        let (bold, italic) = (run.style.bold, run.style.italic);
+        match spans.last_mut() {
+            Some((span, b, i)) if *b == bold && *i == italic => span.push_str(&text),
+            _ => spans.push((text, bold, italic)),
+        }
+    }
+    spans
+}
+
+fn markdown_inline(paragraph: &Paragraph) -> String {
+    let mut out = String::new();
+    for (text, bold, italic) in styled_spans(paragraph) {
+        let marker = match (bold, italic) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        };
+        // Emphasis markers must touch the text, so keep surrounding spaces outside
+        let trimmed = text.trim();
+        if trimmed.is_empty() || marker.is_empty() {
+            out.push_str(&escape_markdown(&text));
+            continue;
+        }
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        let _ = write!(
+            out,
+            "{leading}{marker}{}{marker}{trailing}",
+            escape_markdown(trimmed)
+        );
+    }
+    out.trim().to_string()
+}
+
+fn html_inline(paragraph: &Paragraph) -> String {
+    let mut out = String::new();
+    for (text, bold, italic) in styled_spans(paragraph) {
+        let text = escape_html(&text);
+        let _ = match (bold, italic) {
+            (true, true) => write!(out, "<b><i>{}</i></b>", text),
+            (true, false) => write!(out, "<b>{}</b>", text),
+            (false, true) => write!(out, "<i>{}</i>", text),
+            (false, false) => write!(out, "{}", text),
+        };
+    }
+    out.trim().to_string()
+}
+
+/// A pipe table. Markdown has no spanning cells, so spanned columns are left empty; the first
+/// row becomes the header.
+fn markdown_table(table: &Table) -> String {
+    let columns = table.columns.len() - 1;
+    let mut out = String::new();
+    for (index, row) in table.cells.iter().enumerate() {
+        let mut cells: Vec<String> = Vec::with_capacity(columns);
+        for cell in row {
+            let text = escape_markdown(&cell_text(&cell.paragraphs)).replace('|', "\\|");
+            cells.push(text);
+            cells.extend(std::iter::repeat_n(String::new(), cell.span - 1));
+        }
+        cells.resize(columns, String::new());
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+        if index == 0 {
+            let _ = writeln!(out, "|{}", " --- |".repeat(columns));
+        }
+    }
+    out
+}
+
+fn html_table(table: &Table) -> String {
+    let mut out = String::from("<table>\n");
+    for row in &table.cells {
+        out.push_str("<tr>");
+        for cell in row {
+            let text = cell
+                .paragraphs
+                .iter()
+                .map(html_inline)
+                .collect::<Vec<_>>()
+                .join("<br>");
+            if cell.span > 1 {
+                let _ = write!(out, "<td colspan=\"{}\">{}</td>", cell.span, text);
+            } else {
+                let _ = write!(out, "<td>{}</td>", text);
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+fn cell_text(paragraphs: &[Paragraph]) -> String {
+    paragraphs
+        .iter()
+        .map(|p| p.text().trim().to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,1299 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// One character code of a shown string, decoded through its font.
+#[derive(Debug, Clone)]
+pub(crate) struct Glyph {
+    /// Unicode text of the code; empty if the font gives no way to map it.
+    pub text: String,
+    /// Horizontal advance in text space units (multiply by the font size).
+    pub width: f32,
+    /// Single-byte code 32, which word spacing (`Tw`) applies to.
+    pub is_space: bool,
+}
+
+/// How a font looks, as far as its name and font descriptor tell.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FontStyle {
+    /// Family name, without subset tag and style suffix (`ABCDEF+Arial-BoldMT` is `Arial`).
+    pub family: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// A font resource, reduced to what text extraction needs: how to split a string into codes,
+/// how to map codes to Unicode and how wide each code is.
+pub(crate) struct Font {
+    pub style: Rc<FontStyle>,
+    codespace: Vec<CodespaceRange>,
+    to_unicode: HashMap<u32, String>,
+    encoding: FontEncoding,
+    widths: Widths,
+}
+
+enum FontEncoding {
+    /// Simple font: the text of each single-byte code, from the base encoding and `/Differences`.
+    Simple(Vec<String>),
+    /// Composite font with an `Identity` or embedded CMap. The codes select CIDs, which only
+    /// map to Unicode through `/ToUnicode`.
+    Cid(Option<HashMap<u32, u32>>),
+    /// Composite font whose codes are UTF-16BE (the `Uni*-UCS2-*` / `Uni*-UTF16-*` CMaps).
+    Utf16,
+    /// Composite font whose codes are a legacy charset (GBK, Big5, Shift-JIS, EUC-KR, ...).
+    Charset(&'static encoding_rs::Encoding),
+}
+
+enum Widths {
+    Simple {
+        first_char: u32,
+        widths: Vec<f32>,
+        missing: f32,
+        /// Widths of the ASCII range for standard fonts that come without `/Widths`.
+        standard: Option<&'static [u16; 95]>,
+    },
+    Cid {
+        widths: HashMap<u32, f32>,
+        default: f32,
+    },
+}
+
+/// A range of valid codes of a given byte length; each byte is checked against its own bounds.
+#[derive(Debug, Clone)]
+struct CodespaceRange {
+    low: Vec<u8>,
+    high: Vec<u8>,
+}
+
+impl CodespaceRange {
+    fn new(low: &[u8], high: &[u8]) -> Self {
+        CodespaceRange {
+            low: low.to_vec(),
+            high: high.to_vec(),
+        }
+    }
+
+    fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() == self.low.len()
+            && bytes
+                .iter()
+                .zip(self.low.iter().zip(&self.high))
+                .all(|(b, (lo, hi))| lo <= b && b <= hi)
+    }
+}
+
+impl Font {
+    /// Splits `bytes` into character codes and decodes each one.
+    pub fn decode(&self, bytes: &[u8]) -> Vec<Glyph> {
+        let mut glyphs = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let len = self.code_length(rest);
+            let (code_bytes, tail) = rest.split_at(len);
+            rest = tail;
+            let code = code_bytes.iter().fold(0u32, |acc, &b| acc << 8 | b as u32);
+            glyphs.push(Glyph {
+                text: self.unicode(code, code_bytes),
+                width: self.width(code),
+                is_space: len == 1 && code == 32,
+            });
+        }
+        glyphs
+    }
+
+    fn code_length(&self, bytes: &[u8]) -> usize {
+        for len in 1..=bytes.len().min(4) {
+            if self.codespace.iter().any(|r| r.matches(&bytes[..len])) {
+                return len;
+            }
+        }
+        // Not a valid code: skip as many bytes as the shortest code takes
+        self.codespace
+            .iter()
+            .map(|r| r.low.len())
+            .min()
+            .unwrap_or(1)
+            .clamp(1, bytes.len())
+    }
+
+    fn unicode(&self, code: u32, bytes: &[u8]) -> String {
+        if let Some(text) = self.to_unicode.get(&code) {
+            return text.clone();
+        }
+        match &self.encoding {
+            FontEncoding::Simple(table) => table.get(code as usize).cloned().unwrap_or_default(),
+            FontEncoding::Cid(_) => String::new(),
+            FontEncoding::Utf16 => {
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|c| c.iter().fold(0u16, |acc, &b| acc << 8 | b as u16))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            FontEncoding::Charset(encoding) => {
+                encoding.decode_without_bom_handling(bytes).0.into_owned()
+            }
+        }
+    }
+
+    fn width(&self, code: u32) -> f32 {
+        match &self.widths {
+            Widths::Simple {
+                first_char,
+                widths,
+                missing,
+                standard,
+            } => {
+                if let Some(&w) = code
+                    .checked_sub(*first_char)
+                    .and_then(|i| widths.get(i as usize))
+                {
+                    return w;
+                }
+                match standard {
+                    Some(table) if (32..127).contains(&code) => {
+                        table[code as usize - 32] as f32 / 1000.0
+                    }
+                    _ => *missing,
+                }
+            }
+            Widths::Cid { widths, default } => {
+                let cid = match &self.encoding {
+                    FontEncoding::Cid(None) => Some(code),
+                    FontEncoding::Cid(Some(cids)) => cids.get(&code).copied(),
+                    _ => None,
+                };
+                cid.and_then(|cid| widths.get(&cid))
+                    .copied()
+                    .unwrap_or(*default)
+            }
+        }
+    }
+}
+
+/// Loads fonts on first use and keeps them for the other pages that share them.
+#[derive(Default)]
+pub(crate) struct FontCache {
+    fonts: HashMap<ObjectId, Rc<Font>>,
+}
+
+impl FontCache {
+    /// The font named `name` in the `/Font` entry of `resources`.
+    pub fn get(&mut self, doc: &Document, resources: &Dictionary, name: &[u8]) -> Option<Rc<Font>> {
+        let fonts = resources
+            .get(b"Font")
+            .ok()
+            .and_then(|o| resolve_dict(doc, o))?;
+        match fonts.get(name).ok()? {
+            Object::Reference(id) => {
+                if let Some(font) = self.fonts.get(id) {
+                    return Some(font.clone());
+                }
+                let font = Rc::new(load_font(doc, doc.get_dictionary(*id).ok()?));
+                self.fonts.insert(*id, font.clone());
+                Some(font)
+            }
+            Object::Dictionary(dict) => Some(Rc::new(load_font(doc, dict))),
+            _ => None,
+        }
+    }
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object)
+        .ok()
+        .and_then(|(_, o)| o.as_dict().ok())
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(i) => Some(*i as f32),
+        Object::Real(r) => Some(*r),
+        _ => None,
+    }
+}
+
+fn load_font(doc: &Document, dict: &Dictionary) -> Font {
+    let to_unicode = dict
+        .get(b"ToUnicode")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_stream().ok())
+        .map(|s| {
+            s.decompressed_content()
+                .unwrap_or_else(|_| s.content.clone())
+        })
+        .map(|data| parse_cmap(&data));
+
+    let font = if dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0".as_slice()) {
+        load_composite_font(doc, dict, to_unicode.as_ref())
+    } else {
+        load_simple_font(doc, dict)
+    };
+
+    Font {
+        style: Rc::new(font_style(doc, dict)),
+        to_unicode: to_unicode.map(|cmap| cmap.unicode).unwrap_or_default(),
+        ..font
+    }
+}
+
+/// Font descriptor flags (PDF 32000-1, table 123).
+const FLAG_ITALIC: i64 = 1 << 6;
+const FLAG_FORCE_BOLD: i64 = 1 << 18;
+
+fn font_style(doc: &Document, dict: &Dictionary) -> FontStyle {
+    // A composite font's metrics live in its descendant CIDFont
+    let descendant = dict
+        .get(b"DescendantFonts")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .and_then(|a| a.first())
+        .and_then(|o| resolve_dict(doc, o));
+    let descriptor = descendant
+        .unwrap_or(dict)
+        .get(b"FontDescriptor")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o));
+    let name = dict
+        .get(b"BaseFont")
+        .ok()
+        .or_else(|| descriptor?.get(b"FontName").ok())
+        .and_then(|o| o.as_name_str().ok())
+        .unwrap_or("");
+    let flags = descriptor
+        .and_then(|d| d.get(b"Flags").ok())
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(0);
+    let weight = descriptor
+        .and_then(|d| d.get(b"FontWeight").ok())
+        .and_then(number)
+        .unwrap_or(0.0);
+    let italic_angle = descriptor
+        .and_then(|d| d.get(b"ItalicAngle").ok())
+        .and_then(number)
+        .unwrap_or(0.0);
+
+    // Drop the subset tag, e.g. `ABCDEF+`
+    let name = match name.split_once('+') {
+        Some((tag, rest)) if tag.len() == 6 && tag.bytes().all(|b| b.is_ascii_uppercase()) => rest,
+        _ => name,
+    };
+    let lower = name.to_ascii_lowercase();
+    let bold = ["bold", "black", "heavy", "demi", "semibold"]
+        .iter()
+        .any(|s| lower.contains(s))
+        || flags & FLAG_FORCE_BOLD != 0
+        || weight >= 600.0;
+    let italic = lower.contains("italic")
+        || lower.contains("oblique")
+        || flags & FLAG_ITALIC != 0
+        || italic_angle != 0.0;
+
+    let family = name.split([',', '-']).next().unwrap_or(name);
+    let family = family
+        .strip_suffix("PSMT")
+        .or_else(|| family.strip_suffix("MT"))
+        .unwrap_or(family);
+    // The standard 14 fonts, by the names word processors know them as
+    let family = match family {
+        "Helvetica" => "Arial",
+        "Times" => "Times New Roman",
+        "Courier" => "Courier New",
+        other => other,
+    };
+
+    FontStyle {
+        family: family.to_string(),
+        bold,
+        italic,
+    }
+}
+
+fn load_simple_font(doc: &Document, dict: &Dictionary) -> Font {
+    let encoding = dict
+        .get(b"Encoding")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .map(|(_, o)| o);
+    let base = match encoding {
+        Some(Object::Name(name)) => name.as_slice(),
+        Some(Object::Dictionary(enc)) => enc
+            .get(b"BaseEncoding")
+            .and_then(Object::as_name)
+            .unwrap_or(b"StandardEncoding"),
+        _ => b"StandardEncoding",
+    };
+    let base = match base {
+        b"WinAnsiEncoding" => "WinAnsiEncoding",
+        b"MacRomanEncoding" => "MacRomanEncoding",
+        b"MacExpertEncoding" => "MacExpertEncoding",
+        _ => "StandardEncoding",
+    };
+    let mut table: Vec<String> = (0..=255u8)
+        .map(|code| Document::decode_text(Some(base), &[code]))
+        .collect();
+
+    if let Some(Object::Dictionary(enc)) = encoding {
+        if let Ok(differences) = enc.get(b"Differences").and_then(Object::as_array) {
+            // `None` after a negative code: its run of names is skipped
+            let mut code = Some(0usize);
+            for item in differences {
+                match item {
+                    Object::Integer(i) => code = usize::try_from(*i).ok(),
+                    Object::Name(name) => {
+                        if let Some(slot) = code.and_then(|code| table.get_mut(code)) {
+                            *slot = glyph_name_to_unicode(&String::from_utf8_lossy(name))
+                                .unwrap_or_default();
+                        }
+                        code = code.and_then(|code| code.checked_add(1));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let descriptor = dict
+        .get(b"FontDescriptor")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o));
+    let missing = descriptor
+        .and_then(|d| d.get(b"MissingWidth").ok())
+        .and_then(number);
+    let base_font = dict
+        .get(b"BaseFont")
+        .and_then(Object::as_name_str)
+        .unwrap_or("");
+    let widths: Vec<f32> = dict
+        .get(b"Widths")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .map(|a| a.iter().map(|w| number(w).unwrap_or(0.0)).collect())
+        .unwrap_or_default();
+    let standard = if widths.is_empty() {
+        Some(standard_widths(base_font))
+    } else {
+        None
+    };
+
+    // Type 3 glyph widths are in glyph space, mapped to text space by the font matrix
+    let scale = dict
+        .get(b"FontMatrix")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|m| m.first())
+        .and_then(number)
+        .unwrap_or(0.001);
+    let widths = widths.iter().map(|w| w * scale).collect();
+    let missing = missing.map(|w| w * scale).unwrap_or_else(|| {
+        if base_font.contains("Courier") {
+            0.6
+        } else {
+            0.5
+        }
+    });
+
+    Font {
+        style: Rc::default(),
+        codespace: vec![CodespaceRange::new(&[0x00], &[0xFF])],
+        to_unicode: HashMap::new(),
+        encoding: FontEncoding::Simple(table),
+        widths: Widths::Simple {
+            first_char: dict
+                .get(b"FirstChar")
+                .and_then(Object::as_i64)
+                .unwrap_or(0)
+                .max(0) as u32,
+            widths,
+            missing,
+            standard,
+        },
+    }
+}
+
+fn load_composite_font(doc: &Document, dict: &Dictionary, to_unicode: Option<&CMap>) -> Font {
+    let (encoding, mut codespace) = match dict
+        .get(b"Encoding")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .map(|(_, o)| o)
+    {
+        Some(Object::Name(name)) => predefined_cmap(&String::from_utf8_lossy(name)),
+        Some(Object::Stream(stream)) => {
+            let cmap = parse_cmap(
+                &stream
+                    .decompressed_content()
+                    .unwrap_or_else(|_| stream.content.clone()),
+            );
+            let base = stream
+                .dict
+                .get(b"UseCMap")
+                .and_then(Object::as_name_str)
+                .ok()
+                .or(cmap.use_cmap.as_deref())
+                .map(predefined_cmap);
+            let cids = (!cmap.cids.is_empty()).then_some(cmap.cids);
+            let codespace = if cmap.codespace.is_empty() {
+                base.map(|(_, codespace)| codespace).unwrap_or_default()
+            } else {
+                cmap.codespace
+            };
+            (FontEncoding::Cid(cids), codespace)
+        }
+        _ => predefined_cmap("Identity-H"),
+    };
+    if codespace.is_empty() {
+        codespace = to_unicode
+            .map(|cmap| cmap.codespace.clone())
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| vec![CodespaceRange::new(&[0x00, 0x00], &[0xFF, 0xFF])]);
+    }
+
+    let descendant = dict
+        .get(b"DescendantFonts")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .and_then(|a| a.first())
+        .and_then(|o| resolve_dict(doc, o));
+    let default = descendant
+        .and_then(|d| d.get(b"DW").ok())
+        .and_then(number)
+        .unwrap_or(1000.0)
+        / 1000.0;
+    let mut widths = HashMap::new();
+    if let Some(w) = descendant
+        .and_then(|d| d.get(b"W").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+    {
+        // Either `c [w1 w2 ...]` or `c_first c_last w`
+        let mut i = 0;
+        while i + 1 < w.len() {
+            let Some(first) = number(&w[i]) else {
+                break;
+            };
+            let first = first as u32;
+            match doc.dereference(&w[i + 1]).map(|(_, o)| o) {
+                Ok(Object::Array(list)) => {
+                    for (n, width) in list.iter().enumerate() {
+                        let Some(cid) = u32::try_from(n).ok().and_then(|n| first.checked_add(n))
+                        else {
+                            break;
+                        };
+                        widths.insert(cid, number(width).unwrap_or(0.0) / 1000.0);
+                    }
+                    i += 2;
+                }
+                Ok(last) => {
+                    let (Some(last), Some(width)) = (number(last), w.get(i + 2).and_then(number))
+                    else {
+                        break;
+                    };
+                    i += 3;
+                    let Some(end) = first.checked_add(MAX_RANGE_CODES - 1) else {
+                        continue;
+                    };
+                    for cid in first..=(last as u32).min(end) {
+                        widths.insert(cid, width / 1000.0);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    Font {
+        style: Rc::default(),
+        codespace,
+        to_unicode: HashMap::new(),
+        encoding,
+        widths: Widths::Cid { widths, default },
+    }
+}
+
+/// Encoding and codespace of a predefined CMap, by name.
+fn predefined_cmap(name: &str) -> (FontEncoding, Vec<CodespaceRange>) {
+    let one_byte = || CodespaceRange::new(&[0x00], &[0x80]);
+    let double = |low: [u8; 2], high: [u8; 2]| CodespaceRange::new(&low, &high);
+
+    if name.starts_with("Uni") && (name.contains("UCS2") || name.contains("UTF16")) {
+        let codespace = vec![
+            double([0x00, 0x00], [0xD7, 0xFF]),
+            CodespaceRange::new(&[0xD8, 0x00, 0xDC, 0x00], &[0xDB, 0xFF, 0xDF, 0xFF]),
+            double([0xE0, 0x00], [0xFF, 0xFF]),
+        ];
+        return (FontEncoding::Utf16, codespace);
+    }
+    if name.starts_with("Uni") && name.contains("UTF8") {
+        let codespace = vec![
+            CodespaceRange::new(&[0x00], &[0x7F]),
+            double([0xC2, 0x80], [0xDF, 0xBF]),
+            CodespaceRange::new(&[0xE0, 0x80, 0x80], &[0xEF, 0xBF, 0xBF]),
+            CodespaceRange::new(&[0xF0, 0x80, 0x80, 0x80], &[0xF4, 0xBF, 0xBF, 0xBF]),
+        ];
+        return (FontEncoding::Charset(encoding_rs::UTF_8), codespace);
+    }
+    if name.starts_with("GB") {
+        let mut codespace = vec![one_byte(), double([0x81, 0x40], [0xFE, 0xFE])];
+        if name.starts_with("GBK2K") {
+            codespace.push(CodespaceRange::new(
+                &[0x81, 0x30, 0x81, 0x30],
+                &[0xFE, 0x39, 0xFE, 0x39],
+            ));
+        }
+        return (FontEncoding::Charset(encoding_rs::GB18030), codespace);
+    }
+    if name.contains("B5") || name.starts_with("ETen") {
+        let codespace = vec![one_byte(), double([0x81, 0x40], [0xFE, 0xFE])];
+        return (FontEncoding::Charset(encoding_rs::BIG5), codespace);
+    }
+    if name.contains("RKSJ") {
+        let codespace = vec![
+            one_byte(),
+            double([0x81, 0x40], [0x9F, 0xFC]),
+            CodespaceRange::new(&[0xA0], &[0xDF]),
+            double([0xE0, 0x40], [0xFC, 0xFC]),
+        ];
+        return (FontEncoding::Charset(encoding_rs::SHIFT_JIS), codespace);
+    }
+    if name.starts_with("KSC") {
+        let codespace = vec![one_byte(), double([0x81, 0x41], [0xFE, 0xFE])];
+        return (FontEncoding::Charset(encoding_rs::EUC_KR), codespace);
+    }
+    if name == "EUC-H" || name == "EUC-V" {
+        let codespace = vec![
+            one_byte(),
+            double([0x8E, 0xA0], [0x8E, 0xDF]),
+            double([0xA1, 0xA1], [0xFE, 0xFE]),
+        ];
+        return (FontEncoding::Charset(encoding_rs::EUC_JP), codespace);
+    }
+    // Identity-H / Identity-V and anything unknown: two-byte codes that are CIDs
+    (
+        FontEncoding::Cid(None),
+        vec![double([0x00, 0x00], [0xFF, 0xFF])],
+    )
+}
+
+/// The parts of a CMap stream that text extraction uses.
+#[derive(Default)]
+struct CMap {
+    codespace: Vec<CodespaceRange>,
+    /// Code to Unicode text (`bfchar` / `bfrange`, in `/ToUnicode` CMaps).
+    unicode: HashMap<u32, String>,
+    /// Code to CID (`cidchar` / `cidrange`, in `/Encoding` CMaps).
+    cids: HashMap<u32, u32>,
+    use_cmap: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    String(Vec<u8>),
+    Name(String),
+    Number(f64),
+    Array(Vec<Token>),
+    Keyword(String),
+}
+
+/// Largest number of codes a single range may expand to, to bound malformed input.
+const MAX_RANGE_CODES: u32 = 0x10000;
+
+fn parse_cmap(data: &[u8]) -> CMap {
+    let mut cmap = CMap::default();
+    let mut operands: Vec<Token> = Vec::new();
+
+    for token in tokenize_cmap(data) {
+        let Token::Keyword(keyword) = &token else {
+            operands.push(token);
+            continue;
+        };
+        match keyword.as_str() {
+            "endcodespacerange" => {
+                for pair in operands.chunks_exact(2) {
+                    if let [Token::String(low), Token::String(high)] = pair {
+                        if low.len() == high.len() && !low.is_empty() {
+                            cmap.codespace.push(CodespaceRange::new(low, high));
+                        }
+                    }
+                }
+            }
+            "endbfchar" => {
+                for pair in operands.chunks_exact(2) {
+                    if let [Token::String(code), dest] = pair {
+                        let text = match dest {
+                            Token::String(bytes) => utf16_text(bytes),
+                            Token::Name(name) => glyph_name_to_unicode(name).unwrap_or_default(),
+                            _ => continue,
+                        };
+                        cmap.unicode.insert(code_value(code), text);
+                    }
+                }
+            }
+            "endbfrange" => {
+                for triple in operands.chunks_exact(3) {
+                    let [Token::String(low), Token::String(high), dest] = triple else {
+                        continue;
+                    };
+                    let (low, high) = (code_value(low), code_value(high));
+                    if high < low {
+                        continue;
+                    }
+                    let high = high.min(low.saturating_add(MAX_RANGE_CODES - 1));
+                    match dest {
+                        Token::String(bytes) => {
+                            for (offset, code) in (low..=high).enumerate() {
+                                cmap.unicode
+                                    .insert(code, incremented_text(bytes, offset as u32));
+                            }
+                        }
+                        Token::Array(items) => {
+                            for (code, item) in (low..=high).zip(items) {
+                                if let Token::String(bytes) = item {
+                                    cmap.unicode.insert(code, utf16_text(bytes));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "endcidchar" => {
+                for pair in operands.chunks_exact(2) {
+                    if let [Token::String(code), Token::Number(cid)] = pair {
+                        cmap.cids.insert(code_value(code), *cid as u32);
+                    }
+                }
+            }
+            "endcidrange" => {
+                for triple in operands.chunks_exact(3) {
+                    if let [Token::String(low), Token::String(high), Token::Number(cid)] = triple {
+                        let (low, high) = (code_value(low), code_value(high));
+                        let high = high.min(low.saturating_add(MAX_RANGE_CODES - 1));
+                        for code in low..=high {
+                            let Some(cid) = (*cid as u32).checked_add(code - low) else {
+                                break;
+                            };
+                            cmap.cids.insert(code, cid);
+                        }
+                    }
+                }
+            }
+            "usecmap" => {
+                if let Some(Token::Name(name)) = operands.last() {
+                    cmap.use_cmap = Some(name.clone());
+                }
+            }
+            _ => {}
+        }
+        operands.clear();
+    }
+    cmap
+}
+
+fn code_value(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |acc, &b| acc << 8 | b as u32)
+}
+
+/// `bfchar` / `bfrange` destinations are UTF-16BE; a few producers write single bytes.
+fn utf16_text(bytes: &[u8]) -> String {
+    if bytes.len() % 2 == 1 {
+        return bytes.iter().map(|&b| b as char).collect();
+    }
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|c| (c[0] as u16) << 8 | c[1] as u16)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// The destination of the `offset`-th code of a `bfrange`: the last code unit is incremented.
+fn incremented_text(bytes: &[u8], offset: u32) -> String {
+    let mut bytes = bytes.to_vec();
+    if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+        let n = bytes.len();
+        let last = ((bytes[n - 2] as u32) << 8 | bytes[n - 1] as u32) + offset;
+        bytes[n - 2] = (last >> 8) as u8;
+        bytes[n - 1] = last as u8;
+    } else if let Some(last) = bytes.last_mut() {
+        *last = last.wrapping_add(offset as u8);
+    }
+    utf16_text(&bytes)
+}
+
+fn tokenize_cmap(data: &[u8]) -> Vec<Token> {
+    let is_delimiter = |b: u8| b"()<>[]{}/%".contains(&b) || b.is_ascii_whitespace();
+    let mut stack: Vec<Vec<Token>> = vec![Vec::new()];
+    let mut i = 0;
+
+    while i < data.len() {
+        let b = data[i];
+        let token = match b {
+            _ if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'%' => {
+                while i < data.len() && data[i] != b'\n' && data[i] != b'\r' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'<' if data.get(i + 1) == Some(&b'<') => {
+                i += 2;
+                continue;
+            }
+            b'>' if data.get(i + 1) == Some(&b'>') => {
+                i += 2;
+                continue;
+            }
+            b'<' => {
+                let end = data[i..]
+                    .iter()
+                    .position(|&c| c == b'>')
+                    .map_or(data.len(), |p| i + p);
+                let digits: Vec<u8> = data[i + 1..end]
+                    .iter()
+                    .filter(|c| c.is_ascii_hexdigit())
+                    .copied()
+                    .collect();
+                let bytes = digits
+                    .chunks(2)
+                    .map(|pair| {
+                        let hex = |c: u8| (c as char).to_digit(16).unwrap_or(0) as u8;
+                        hex(pair[0]) << 4 | pair.get(1).map_or(0, |&c| hex(c))
+                    })
+                    .collect();
+                i = end + 1;
+                Token::String(bytes)
+            }
+            b'(' => {
+                let mut bytes = Vec::new();
+                let mut depth = 1;
+                i += 1;
+                while i < data.len() {
+                    match data[i] {
+                        b'\\' if i + 1 < data.len() => {
+                            i += 1;
+                            bytes.push(data[i]);
+                        }
+                        b'(' => {
+                            depth += 1;
+                            bytes.push(b'(');
+                        }
+                        b')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                            bytes.push(b')');
+                        }
+                        c => bytes.push(c),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Token::String(bytes)
+            }
+            b'[' => {
+                stack.push(Vec::new());
+                i += 1;
+                continue;
+            }
+            b']' => {
+                i += 1;
+                if stack.len() < 2 {
+                    continue;
+                }
+                Token::Array(stack.pop().unwrap_or_default())
+            }
+            _ => {
+                let start = if b == b'/' { i + 1 } else { i };
+                i = start;
+                while i < data.len() && !is_delimiter(data[i]) {
+                    i += 1;
+                }
+                if i == start && b != b'/' {
+                    // A stray delimiter such as `{` or `)`
+                    i += 1;
+                    continue;
+                }
+                let word = String::from_utf8_lossy(&data[start..i]).to_string();
+                if b == b'/' {
+                    Token::Name(word)
+                } else if let Ok(n) = word.parse::<f64>() {
+                    Token::Number(n)
+                } else {
+                    Token::Keyword(word)
+                }
+            }
+        };
+        if let Some(top) = stack.last_mut() {
+            top.push(token);
+        }
+    }
+
+    while stack.len() > 1 {
+        let inner = stack.pop().unwrap_or_default();
+        if let Some(top) = stack.last_mut() {
+            top.push(Token::Array(inner));
+        }
+    }
+    stack.pop().unwrap_or_default()
+}
+
+/// Maps a glyph name from a `/Differences` array to Unicode, following the Adobe Glyph List
+/// conventions: `uniXXXX`, `uXXXX[XX]`, ligatures joined with `_` and suffixes after `.`.
+fn glyph_name_to_unicode(name: &str) -> Option<String> {
+    let base = name.split('.').next().unwrap_or(name);
+    if base.is_empty() {
+        return None;
+    }
+    if base.contains('_') {
+        let parts: Option<String> = base.split('_').map(glyph_name_to_unicode).collect();
+        return parts;
+    }
+    if let Some(&(_, c)) = GLYPH_NAMES.iter().find(|(n, _)| *n == base) {
+        return Some(c.to_string());
+    }
+    if base.len() == 1 && base.as_bytes()[0].is_ascii_alphabetic() {
+        return Some(base.to_string());
+    }
+    if let Some(hex) = base.strip_prefix("uni") {
+        if hex.len() >= 4
+            && hex.len().is_multiple_of(4)
+            && hex.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            let units: Vec<u16> = (0..hex.len())
+                .step_by(4)
+                .filter_map(|i| u16::from_str_radix(&hex[i..i + 4], 16).ok())
+                .collect();
+            return Some(String::from_utf16_lossy(&units));
+        }
+    }
+    if let Some(hex) = base.strip_prefix('u') {
+        if (4..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(String::from);
+        }
+    }
+    None
+}
+
+/// Glyph widths (1/1000 em) of the printable ASCII range for the standard fonts, used when
+/// a font dictionary comes without `/Widths`.
+fn standard_widths(base_font: &str) -> &'static [u16; 95] {
+    if base_font.contains("Courier") {
+        &COURIER_WIDTHS
+    } else if base_font.contains("Times") {
+        &TIMES_WIDTHS
+    } else {
+        &HELVETICA_WIDTHS
+    }
+}
+
+/// Width in em of `text` set in Helvetica, counting characters outside ASCII as wide as a
+/// digit.
+pub(crate) fn helvetica_width(text: &str) -> f32 {
+    let width: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => HELVETICA_WIDTHS[c as usize - 0x20] as u32,
+            _ => 556,
+        })
+        .sum();
+    width as f32 / 1000.0
+}
+
+const COURIER_WIDTHS: [u16; 95] = [600; 95];
+
+#[rustfmt::skip]
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 222, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    222, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[rustfmt::skip]
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 333, 333, 333, 500, 564, 250, 333, 250, 278,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444,
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722,
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500,
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500,
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541,
+];
+
+/// Glyph names of the Latin text encodings (Standard, WinAnsi, MacRoman) and common
+/// symbols; single letters and `uniXXXX` style names are handled in code.
+const GLYPH_NAMES: &[(&str, char)] = &[
+    ("space", ' '),
+    ("exclam", '!'),
+    ("quotedbl", '"'),
+    ("numbersign", '#'),
+    ("dollar", '$'),
+    ("percent", '%'),
+    ("ampersand", '&'),
+    ("quotesingle", '\''),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("asterisk", '*'),
+    ("plus", '+'),
+    ("comma", ','),
+    ("hyphen", '-'),
+    ("period", '.'),
+    ("slash", '/'),
+    ("zero", '0'),
+    ("one", '1'),
+    ("two", '2'),
+    ("three", '3'),
+    ("four", '4'),
+    ("five", '5'),
+    ("six", '6'),
+    ("seven", '7'),
+    ("eight", '8'),
+    ("nine", '9'),
+    ("colon", ':'),
+    ("semicolon", ';'),
+    ("less", '<'),
+    ("equal", '='),
+    ("greater", '>'),
+    ("question", '?'),
+    ("at", '@'),
+    ("bracketleft", '['),
+    ("backslash", '\\'),
+    ("bracketright", ']'),
+    ("asciicircum", '^'),
+    ("underscore", '_'),
+    ("grave", '`'),
+    ("braceleft", '{'),
+    ("bar", '|'),
+    ("braceright", '}'),
+    ("asciitilde", '~'),
+    ("nbspace", '\u{A0}'),
+    ("nonbreakingspace", '\u{A0}'),
+    ("exclamdown", '¡'),
+    ("cent", '¢'),
+    ("sterling", '£'),
+    ("currency", '¤'),
+    ("yen", '¥'),
+    ("brokenbar", '¦'),
+    ("section", '§'),
+    ("dieresis", '¨'),
+    ("copyright", '©'),
+    ("ordfeminine", 'ª'),
+    ("guillemotleft", '«'),
+    ("logicalnot", '¬'),
+    ("sfthyphen", '\u{AD}'),
+    ("registered", '®'),
+    ("macron", '¯'),
+    ("degree", '°'),
+    ("plusminus", '±'),
+    ("twosuperior", '²'),
+    ("threesuperior", '³'),
+    ("acute", '´'),
+    ("mu", 'µ'),
+    ("paragraph", '¶'),
+    ("periodcentered", '·'),
+    ("cedilla", '¸'),
+    ("onesuperior", '¹'),
+    ("ordmasculine", 'º'),
+    ("guillemotright", '»'),
+    ("onequarter", '¼'),
+    ("onehalf", '½'),
+    ("threequarters", '¾'),
+    ("questiondown", '¿'),
+    ("Agrave", 'À'),
+    ("Aacute", 'Á'),
+    ("Acircumflex", 'Â'),
+    ("Atilde", 'Ã'),
+    ("Adieresis", 'Ä'),
+    ("Aring", 'Å'),
+    ("AE", 'Æ'),
+    ("Ccedilla", 'Ç'),
+    ("Egrave", 'È'),
+    ("Eacute", 'É'),
+    ("Ecircumflex", 'Ê'),
+    ("Edieresis", 'Ë'),
+    ("Igrave", 'Ì'),
+    ("Iacute", 'Í'),
+    ("Icircumflex", 'Î'),
+    ("Idieresis", 'Ï'),
+    ("Eth", 'Ð'),
+    ("Ntilde", 'Ñ'),
+    ("Ograve", 'Ò'),
+    ("Oacute", 'Ó'),
+    ("Ocircumflex", 'Ô'),
+    ("Otilde", 'Õ'),
+    ("Odieresis", 'Ö'),
+    ("multiply", '×'),
+    ("Oslash", 'Ø'),
+    ("Ugrave", 'Ù'),
+    ("Uacute", 'Ú'),
+    ("Ucircumflex", 'Û'),
+    ("Udieresis", 'Ü'),
+    ("Yacute", 'Ý'),
+    ("Thorn", 'Þ'),
+    ("germandbls", 'ß'),
+    ("agrave", 'à'),
+    ("aacute", 'á'),
+    ("acircumflex", 'â'),
+    ("atilde", 'ã'),
+    ("adieresis", 'ä'),
+    ("aring", 'å'),
+    ("ae", 'æ'),
+    ("ccedilla", 'ç'),
+    ("egrave", 'è'),
+    ("eacute", 'é'),
+    ("ecircumflex", 'ê'),
+    ("edieresis", 'ë'),
+    ("igrave", 'ì'),
+    ("iacute", 'í'),
+    ("icircumflex", 'î'),
+    ("idieresis", 'ï'),
+    ("eth", 'ð'),
+    ("ntilde", 'ñ'),
+    ("ograve", 'ò'),
+    ("oacute", 'ó'),
+    ("ocircumflex", 'ô'),
+    ("otilde", 'õ'),
+    ("odieresis", 'ö'),
+    ("divide", '÷'),
+    ("oslash", 'ø'),
+    ("ugrave", 'ù'),
+    ("uacute", 'ú'),
+    ("ucircumflex", 'û'),
+    ("udieresis", 'ü'),
+    ("yacute", 'ý'),
+    ("thorn", 'þ'),
+    ("ydieresis", 'ÿ'),
+    ("Euro", '€'),
+    ("quotesinglbase", '‚'),
+    ("florin", 'ƒ'),
+    ("quotedblbase", '„'),
+    ("ellipsis", '…'),
+    ("dagger", '†'),
+    ("daggerdbl", '‡'),
+    ("circumflex", 'ˆ'),
+    ("perthousand", '‰'),
+    ("Scaron", 'Š'),
+    ("guilsinglleft", '‹'),
+    ("OE", 'Œ'),
+    ("Zcaron", 'Ž'),
+    ("quoteleft", '‘'),
+    ("quoteright", '’'),
+    ("quotedblleft", '“'),
+    ("quotedblright", '”'),
+    ("bullet", '•'),
+    ("endash", '–'),
+    ("emdash", '—'),
+    ("tilde", '˜'),
+    ("trademark", '™'),
+    ("scaron", 'š'),
+    ("guilsinglright", '›'),
+    ("oe", 'œ'),
+    ("zcaron", 'ž'),
+    ("Ydieresis", 'Ÿ'),
+    ("dotlessi", 'ı'),
+    ("Lslash", 'Ł'),
+    ("lslash", 'ł'),
+    ("breve", '˘'),
+    ("dotaccent", '˙'),
+    ("ring", '˚'),
+    ("hungarumlaut", '˝'),
+    ("ogonek", '˛'),
+    ("caron", 'ˇ'),
+    ("fraction", '⁄'),
+    ("ff", 'ﬀ'),
+    ("fi", 'ﬁ'),
+    ("fl", 'ﬂ'),
+    ("ffi", 'ﬃ'),
+    ("ffl", 'ﬄ'),
+    ("minus", '−'),
+    ("notequal", '≠'),
+    ("infinity", '∞'),
+    ("lessequal", '≤'),
+    ("greaterequal", '≥'),
+    ("partialdiff", '∂'),
+    ("summation", '∑'),
+    ("product", '∏'),
+    ("integral", '∫'),
+    ("radical", '√'),
+    ("approxequal", '≈'),
+    ("Delta", '∆'),
+    ("Omega", 'Ω'),
+    ("lozenge", '◊'),
+    ("arrowleft", '←'),
+    ("arrowup", '↑'),
+    ("arrowright", '→'),
+    ("arrowdown", '↓'),
+    ("checkmark", '✓'),
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("omicron", 'ο'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+    ("Gamma", 'Γ'),
+    ("Theta", 'Θ'),
+    ("Lambda", 'Λ'),
+    ("Xi", 'Ξ'),
+    ("Pi", 'Π'),
+    ("Sigma", 'Σ'),
+    ("Phi", 'Φ'),
+    ("Psi", 'Ψ'),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    fn text(font: &Font, bytes: &[u8]) -> String {
+        font.decode(bytes).into_iter().map(|g| g.text).collect()
+    }
+
+    fn widths(font: &Font, bytes: &[u8]) -> Vec<f32> {
+        font.decode(bytes).into_iter().map(|g| g.width).collect()
+    }
+
+    #[test]
+    fn test_cid_widths() {
+        let doc = Document::new();
+        let w: Vec<Object> = vec![
+            1.into(),
+            vec![500.into(), 600.into()].into(),
+            10.into(),
+            12.into(),
+            700.into(),
+            // Out of range entries are skipped instead of overflowing
+            Object::Integer(u32::MAX as i64),
+            vec![100.into(), 200.into()].into(),
+            Object::Integer(u32::MAX as i64 - 10),
+            Object::Integer(u32::MAX as i64),
+            300.into(),
+        ];
+        let font = load_font(
+            &doc,
+            &dictionary! {
+                "Subtype" => "Type0",
+                "Encoding" => "Identity-H",
+                "DescendantFonts" => vec![dictionary! { "DW" => 900, "W" => w }.into()],
+            },
+        );
+        assert_eq!(
+            widths(&font, &[0, 1, 0, 2, 0, 11, 0, 12, 0, 13]),
+            vec![0.5, 0.6, 0.7, 0.7, 0.9]
+        );
+    }
+
+    #[test]
+    fn test_to_unicode_ranges() {
+        let cmap = parse_cmap(
+            b"1 begincodespacerange <0000> <FFFF> endcodespacerange
+              2 beginbfrange <0001> <0003> <0041> <0010> <0011> [<0048> <00690069>] endbfrange
+              1 beginbfchar <0020> <00E9> endbfchar
+              2 begincidrange <0100> <0102> 50 <0200> <02FF> 4294967294 endcidrange
+              1 begincidchar <0300> 7 endcidchar",
+        );
+        let unicode = |code: u32| cmap.unicode.get(&code).map(String::as_str);
+        assert_eq!(unicode(0x0001), Some("A"));
+        assert_eq!(unicode(0x0003), Some("C"));
+        assert_eq!(unicode(0x0004), None);
+        assert_eq!(unicode(0x0010), Some("H"));
+        assert_eq!(unicode(0x0011), Some("ii"));
+        assert_eq!(unicode(0x0020), Some("é"));
+
+        assert_eq!(cmap.cids.get(&0x0101), Some(&51));
+        assert_eq!(cmap.cids.get(&0x0102), Some(&52));
+        assert_eq!(cmap.cids.get(&0x0300), Some(&7));
+        // The range stops where the CIDs would overflow
+        assert_eq!(cmap.cids.get(&0x0201), Some(&u32::MAX));
+        assert_eq!(cmap.cids.get(&0x0202), None);
+    }
+
+    #[test]
+    fn test_encoding_fallbacks() {
+        let mut doc = Document::new();
+
+        // Base encoding, then /Differences with AGL names and uniXXXX
+        let font = load_font(
+            &doc,
+            &dictionary! {
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => dictionary! {
+                    "BaseEncoding" => "WinAnsiEncoding",
+                    "Differences" => vec![65.into(), "Sigma".into(), "uni0416".into()],
+                },
+            },
+        );
+        assert_eq!(text(&font, b"AB\x80C"), "ΣЖ€C");
+        assert_eq!(widths(&font, b"C"), vec![0.722]);
+        // Codes out of range skip their names instead of overflowing
+        let font = load_font(
+            &doc,
+            &dictionary! {
+                "Subtype" => "Type1",
+                "Encoding" => dictionary! {
+                    "Differences" => vec![
+                        (-1).into(), "Sigma".into(),
+                        i64::MAX.into(), "Sigma".into(), "Sigma".into(),
+                        66.into(), "Sigma".into(),
+                    ],
+                },
+            },
+        );
+        assert_eq!(text(&font, b"AB"), "AΣ");
+        let font = load_font(&doc, &dictionary! { "Subtype" => "Type1" });
+        assert_eq!(text(&font, b"'"), "\u{2019}");
+
+        // Composite fonts without /ToUnicode: UCS-2 and legacy charset CMaps
+        let font = load_font(
+            &doc,
+            &dictionary! { "Subtype" => "Type0", "Encoding" => "UniGB-UCS2-H" },
+        );
+        assert_eq!(text(&font, &[0x4E, 0x2D, 0x65, 0x87]), "中文");
+        let font = load_font(
+            &doc,
+            &dictionary! { "Subtype" => "Type0", "Encoding" => "GBK-EUC-H" },
+        );
+        assert_eq!(text(&font, &[0xD6, 0xD0, b'a', 0xCE, 0xC4]), "中a文");
+
+        // Identity CIDs only map through /ToUnicode, which wins over the encoding
+        let identity = dictionary! { "Subtype" => "Type0", "Encoding" => "Identity-H" };
+        assert_eq!(text(&load_font(&doc, &identity), &[0, 1]), "");
+        let to_unicode = doc.add_object(Stream::new(
+            dictionary! {},
+            b"1 beginbfchar <0001> <4E2D> endbfchar".to_vec(),
+        ));
+        let mut font_dict = identity;
+        font_dict.set("ToUnicode", to_unicode);
+        assert_eq!(text(&load_font(&doc, &font_dict), &[0, 1]), "中");
+    }
+}
@@ -0,0 +1,448 @@
+use crate::table::{extract_tables, Table};
+use crate::text::{PageContent, PageImage, TextRun};
+
+/// The content of one page, laid out for conversion to a flowing document.
+pub(crate) struct PageLayout {
+    /// Paragraphs outside tables, in reading order.
+    pub paragraphs: Vec<Paragraph>,
+    pub images: Vec<PageImage>,
+    /// Tables, top first.
+    pub tables: Vec<Table>,
+}
+
+/// A paragraph, image or table of a [`PageLayout`].
+pub(crate) enum Block<'a> {
+    Paragraph(&'a Paragraph),
+    Image(&'a PageImage),
+    Table(&'a Table),
+}
+
+impl PageLayout {
+    pub fn new(content: PageContent) -> Self {
+        let (tables, runs) = extract_tables(content.runs, &content.rulings);
+        PageLayout {
+            paragraphs: layout_paragraphs(runs),
+            images: content.images,
+            tables,
+        }
+    }
+
+    /// The page's content in reading order. Images and tables go before the first paragraph
+    /// that starts below their top edge.
+    pub fn blocks(&self) -> Vec<Block<'_>> {
+        let mut floats: Vec<(f32, Block)> = self
+            .images
+            .iter()
+            .map(|i| (i.y + i.height, Block::Image(i)))
+            .chain(self.tables.iter().map(|t| (t.top(), Block::Table(t))))
+            .collect();
+        floats.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut floats = floats.into_iter().peekable();
+
+        let mut blocks = Vec::new();
+        for paragraph in &self.paragraphs {
+            let top = paragraph.lines.first().map_or(f32::MIN, |l| l.y + l.size);
+            while let Some((_, float)) = floats.next_if(|(float_top, _)| *float_top >= top) {
+                blocks.push(float);
+            }
+            blocks.push(Block::Paragraph(paragraph));
+        }
+        blocks.extend(floats.map(|(_, float)| float));
+        blocks
+    }
+
+    /// The left edge of the page's content.
+    pub fn left(&self) -> f32 {
+        self.paragraphs
+            .iter()
+            .flat_map(|p| &p.lines)
+            .map(|l| l.left)
+            .chain(self.images.iter().map(|i| i.x))
+            .chain(self.tables.iter().map(|t| t.columns[0]))
+            .fold(f32::MAX, f32::min)
+    }
+}
+
+/// Runs that share a baseline and follow each other closely, left to right.
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub runs: Vec<TextRun>,
+    pub left: f32,
+    pub right: f32,
+    /// Baseline.
+    pub y: f32,
+    /// Largest font size on the line.
+    pub size: f32,
+}
+
+impl Line {
+    fn new(run: TextRun) -> Self {
+        Line {
+            left: run.x,
+            right: run.x + run.width,
+            y: run.y,
+            size: run.size.max(1.0),
+            runs: vec![run],
+        }
+    }
+
+    fn push(&mut self, run: TextRun) {
+        self.right = self.right.max(run.x + run.width);
+        self.size = self.size.max(run.size);
+        self.runs.push(run);
+    }
+
+    fn top(&self) -> f32 {
+        self.y + self.size * 0.75
+    }
+
+    fn bottom(&self) -> f32 {
+        self.y - self.size * 0.25
+    }
+
+    /// The line's runs with the text to write for each, including any word space before it.
+    pub fn pieces(&self) -> Vec<(String, &TextRun)> {
+        let mut pieces: Vec<(String, &TextRun)> = Vec::with_capacity(self.runs.len());
+        let mut end: Option<f32> = None;
+        for run in &self.runs {
+            let mut text = String::new();
+            if let (Some(end), Some((prev, _))) = (end, pieces.last()) {
+                let gap = run.x - end;
+                let spaced = prev.ends_with(char::is_whitespace)
+                    || run.text.starts_with(char::is_whitespace);
+                if gap > run.size.max(1.0) * 0.15 && !spaced {
+                    text.push(' ');
+                }
+            }
+            text.push_str(&run.text);
+            end = Some(run.x + run.width);
+            pieces.push((text, run));
+        }
+        pieces
+    }
+
+    pub fn text(&self) -> String {
+        self.pieces().into_iter().map(|(text, _)| text).collect()
+    }
+}
+
+/// Lines that read as one paragraph.
+#[derive(Debug, Clone)]
+pub(crate) struct Paragraph {
+    pub lines: Vec<Line>,
+}
+
+impl Paragraph {
+    /// The paragraph's runs with the text to write for each; lines are joined with a space,
+    /// except after a hyphen and between CJK characters.
+    pub fn pieces(&self) -> Vec<(String, &TextRun)> {
+        let mut pieces: Vec<(String, &TextRun)> = Vec::new();
+        for line in &self.lines {
+            let mut line_pieces = line.pieces();
+            if let (Some((prev, _)), Some((next, _))) = (pieces.last(), line_pieces.first_mut()) {
+                let prev_char = prev.trim_end().chars().last();
+                let next_char = next.trim_start().chars().next();
+                let joined = prev.ends_with(char::is_whitespace)
+                    || next.starts_with(char::is_whitespace)
+                    || prev_char == Some('-')
+                    || prev_char.is_some_and(is_cjk)
+                    || next_char.is_some_and(is_cjk);
+                if !joined {
+                    next.insert(0, ' ');
+                }
+            }
+            pieces.extend(line_pieces);
+        }
+        pieces
+    }
+
+    pub fn text(&self) -> String {
+        self.pieces().into_iter().map(|(text, _)| text).collect()
+    }
+
+    /// Largest font size in the paragraph.
+    pub fn size(&self) -> f32 {
+        self.lines.iter().map(|l| l.size).fold(0.0, f32::max)
+    }
+}
+
+/// Headings recognized by font size: short paragraphs set clearly larger than the body text.
+/// Each distinct heading size is one level, the largest being level 1.
+pub(crate) struct Headings {
+    /// Size of the body text.
+    body: f32,
+    /// Heading sizes, largest first.
+    sizes: Vec<f32>,
+}
+
+/// Heading levels beyond this are folded into the last one.
+const MAX_HEADING_LEVEL: usize = 6;
+
+impl Headings {
+    pub fn detect<'a>(paragraphs: impl IntoIterator<Item = &'a Paragraph> + Clone) -> Self {
+        // The body size is the one most of the text is set in
+        let mut counts: Vec<(f32, usize)> = Vec::new();
+        for paragraph in paragraphs.clone() {
+            for line in &paragraph.lines {
+                for run in &line.runs {
+                    let size = (run.size * 2.0).round() / 2.0;
+                    let chars = run.text.chars().count();
+                    match counts.iter_mut().find(|(s, _)| *s == size) {
+                        Some((_, n)) => *n += chars,
+                        None => counts.push((size, chars)),
+                    }
+                }
+            }
+        }
+        let Some(&(body, _)) = counts.iter().max_by_key(|(_, n)| *n) else {
+            return Headings {
+                body: 0.0,
+                sizes: Vec::new(),
+            };
+        };
+
+        let mut sizes: Vec<f32> = paragraphs
+            .into_iter()
+            .filter(|p| Self::is_candidate(p, body))
+            .map(Paragraph::size)
+            .collect();
+        sizes.sort_by(|a, b| b.total_cmp(a));
+        // Sizes within 5% of each other are one level
+        sizes.dedup_by(|next, kept| *next >= *kept * 0.95);
+        Headings { body, sizes }
+    }
+
+    fn is_candidate(paragraph: &Paragraph, body: f32) -> bool {
+        paragraph.size() >= body * 1.15
+            && paragraph.lines.len() <= 3
+            && paragraph.text().chars().count() <= 200
+    }
+
+    /// The heading level of `paragraph`, starting at 1, or `None` for body text.
+    pub fn level(&self, paragraph: &Paragraph) -> Option<usize> {
+        if !Self::is_candidate(paragraph, self.body) {
+            return None;
+        }
+        let size = paragraph.size();
+        let level = self.sizes.iter().position(|&s| size >= s * 0.95)?;
+        Some((level + 1).min(MAX_HEADING_LEVEL))
+    }
+}
+
+/// How a list item is marked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ListMarker {
+    Bullet,
+    Number(u32),
+    Letter(char),
+}
+
+/// A list item marker at the start of `text`: a bullet, or a number or lowercase letter
+/// followed by `.` or `)`. Returns the marker and the text after it.
+pub(crate) fn list_marker(text: &str) -> Option<(ListMarker, &str)> {
+    let text = text.trim_start();
+    let (marker, rest) = text.split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+    if rest.is_empty() {
+        return None;
+    }
+    if matches!(
+        marker,
+        "•" | "◦" | "▪" | "‣" | "●" | "○" | "■" | "-" | "–" | "*"
+    ) {
+        return Some((ListMarker::Bullet, rest));
+    }
+    let label = marker.strip_suffix(['.', ')'])?;
+    if label.len() <= 3 {
+        if let Ok(number) = label.parse() {
+            return Some((ListMarker::Number(number), rest));
+        }
+    }
+    let mut chars = label.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() => Some((ListMarker::Letter(c), rest)),
+        _ => None,
+    }
+}
+
+/// Characters of scripts written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x30FF // CJK punctuation, Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0xFF00..=0xFFEF // Half- and full-width forms
+        | 0x20000..=0x2FA1F)
+}
+
+/// Orders the text runs of a page into paragraphs in reading order.
+///
+/// Runs are grouped into lines by baseline. The lines are then split recursively along
+/// white space ("XY cut"): vertical gutters separate columns, which are read left to right,
+/// and wide horizontal gaps separate e.g. a full-width title from the columns below it.
+/// Within the resulting blocks, lines are merged into paragraphs unless the line spacing
+/// grows, the font size changes, the next line is indented or the previous one ends a
+/// sentence short of the right margin.
+pub(crate) fn layout_paragraphs(runs: Vec<TextRun>) -> Vec<Paragraph> {
+    let lines = build_lines(runs);
+    let mut blocks = Vec::new();
+    xy_cut(lines, &mut blocks, 0);
+
+    let mut paragraphs: Vec<Paragraph> = Vec::new();
+    for block in blocks {
+        let block_right = block.iter().map(|l| l.right).fold(f32::MIN, f32::max);
+        let mut current: Vec<Line> = Vec::new();
+        for line in block {
+            if let Some(prev) = current.last() {
+                if starts_paragraph(&current, prev, &line, block_right) {
+                    paragraphs.push(Paragraph {
+                        lines: std::mem::take(&mut current),
+                    });
+                }
+            }
+            current.push(line);
+        }
+        if !current.is_empty() {
+            paragraphs.push(Paragraph { lines: current });
+        }
+    }
+    paragraphs
+}
+
+/// Groups runs into lines by baseline, splitting lines at gaps too wide for a word space.
+pub(crate) fn build_lines(mut runs: Vec<TextRun>) -> Vec<Line> {
+    runs.retain(|r| !r.text.trim().is_empty());
+    runs.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < runs.len() {
+        // Runs whose baselines are within half a font size of the first one
+        let y = runs[i].y;
+        let tolerance = runs[i].size.max(1.0) * 0.5;
+        let mut j = i + 1;
+        while j < runs.len() && (y - runs[j].y).abs() <= tolerance {
+            j += 1;
+        }
+        let mut group: Vec<TextRun> = runs[i..j].to_vec();
+        group.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        // Split where the horizontal gap is too wide for a word space
+        let mut line: Option<Line> = None;
+        for run in group {
+            match &mut line {
+                Some(l) if run.x - l.right <= l.size.max(run.size) * 2.0 => l.push(run),
+                _ => {
+                    lines.extend(line.take());
+                    line = Some(Line::new(run));
+                }
+            }
+        }
+        lines.extend(line);
+        i = j;
+    }
+    lines
+}
+
+/// Maximum recursion depth of [`xy_cut`]; deeper regions are read top to bottom.
+const MAX_CUT_DEPTH: usize = 32;
+
+/// Splits `lines` into blocks in reading order, see [`layout_paragraphs`].
+fn xy_cut(mut lines: Vec<Line>, blocks: &mut Vec<Vec<Line>>, depth: usize) {
+    if lines.len() > 1 && depth < MAX_CUT_DEPTH {
+        let size = median(lines.iter().map(|l| l.size).collect());
+
+        if let Some(cut) = vertical_cut(&lines, size) {
+            let (left, right) = lines.into_iter().partition(|l| l.right <= cut);
+            xy_cut(left, blocks, depth + 1);
+            xy_cut(right, blocks, depth + 1);
+            return;
+        }
+        if let Some(cut) = horizontal_cut(&lines, size) {
+            let (above, below) = lines.into_iter().partition(|l| l.bottom() >= cut);
+            xy_cut(above, blocks, depth + 1);
+            xy_cut(below, blocks, depth + 1);
+            return;
+        }
+    }
+    lines.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.left.total_cmp(&b.left)));
+    if !lines.is_empty() {
+        blocks.push(lines);
+    }
+}
+
+/// The middle of the widest vertical gutter that runs through all lines, if both sides
+/// are wide enough to be columns rather than e.g. list bullets.
+fn vertical_cut(lines: &[Line], size: f32) -> Option<f32> {
+    let left = lines.iter().map(|l| l.left).fold(f32::MAX, f32::min);
+    let right = lines.iter().map(|l| l.right).fold(f32::MIN, f32::max);
+    let min_side = (right - left) * 0.15;
+    let min_gutter = (size * 1.5).max(10.0);
+
+    let spans = lines.iter().map(|l| (l.left, l.right)).collect();
+    widest_gap(spans, min_gutter)
+        .filter(|&(start, end)| start - left >= min_side && right - end >= min_side)
+        .map(|(start, end)| (start + end) / 2.0)
+}
+
+/// The middle of the widest horizontal gap between lines that is clearly larger than
+/// normal line spacing.
+fn horizontal_cut(lines: &[Line], size: f32) -> Option<f32> {
+    let spans = lines.iter().map(|l| (l.bottom(), l.top())).collect();
+    widest_gap(spans, size * 0.6).map(|(start, end)| (start + end) / 2.0)
+}
+
+/// The widest gap of at least `min_width` between the merged `spans`.
+fn widest_gap(mut spans: Vec<(f32, f32)>, min_width: f32) -> Option<(f32, f32)> {
+    spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut best: Option<(f32, f32)> = None;
+    let mut covered_to = spans.first()?.1;
+    for &(start, end) in &spans[1..] {
+        if start - covered_to >= min_width && best.is_none_or(|(s, e)| start - covered_to > e - s) {
+            best = Some((covered_to, start));
+        }
+        covered_to = covered_to.max(end);
+    }
+    best
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied().unwrap_or(10.0)
+}
+
+/// Whether `line` begins a new paragraph after `prev`, the last line of `paragraph`.
+fn starts_paragraph(paragraph: &[Line], prev: &Line, line: &Line, block_right: f32) -> bool {
+    let size = prev.size.max(line.size);
+    let spacing = prev.y - line.y;
+
+    // Another piece of the same visual line, e.g. after a tab stop
+    if spacing.abs() <= size * 0.5 {
+        return false;
+    }
+    if spacing < 0.0 || line.left > prev.right || line.right < prev.left {
+        return true;
+    }
+    if (line.size - prev.size).abs() > prev.size * 0.15 || list_marker(&line.text()).is_some() {
+        return true;
+    }
+    // Compare with the paragraph's own line spacing once it has one
+    let expected = match paragraph {
+        [.., a, b] if (a.y - b.y) > size * 0.5 => (a.y - b.y) * 1.3,
+        _ => size * 1.6,
+    };
+    if spacing > expected {
+        return true;
+    }
+
+    let short = prev.right < block_right - size * 2.0;
+    if line.left > prev.left + size && short {
+        return true;
+    }
+    let ends_sentence = prev
+        .text()
+        .trim_end()
+        .ends_with(['.', '!', '?', ':', '。', '！', '？', '：']);
+    short && ends_sentence
+}
@@ -1,7 +1,28 @@
 pub mod engine;
 pub mod error;
+mod export;
+mod font;
+mod layout;
+mod outline;
+pub mod page_range;
+mod render;
+pub mod renderer;
+mod scan;
+pub mod security;
+mod table;
+mod text;
+mod word;
+mod xobject;
 
 pub use engine::{
-    get_page_count, images_to_pdf, merge, pdf_to_images, pdf_to_word, reorder_pages, split,
+    encrypt_pdf, extract_text, get_page_count, images_to_pdf, images_to_pdf_with_options, merge,
+    merge_pages, merge_with_options, pdf_to_html, pdf_to_images, pdf_to_markdown, pdf_to_text,
+    pdf_to_word, render_page_thumbnail, render_page_thumbnails, reorder_pages, rotate_pages,
+    rotate_to_orientation, split, split_bookmarks, split_ranges, split_size, ImageFit, ImageGrid,
+    ImagePdfOptions, InterleaveMode, MergeInput, MergeOptions, Orientation, PageSize, RenderFormat,
+    RenderOptions, RenderOutput, RenderSize, ScanCleanup, ScanColor, SplitPart,
 };
 pub use error::PdfError;
+pub use page_range::{parse_page_groups, parse_page_list, PageGroup};
+pub use renderer::{default_renderer, HayroRenderer, PageRenderer, PdfiumRenderer, RenderDocument};
+pub use security::{needs_password, EncryptionAlgorithm, EncryptionOptions, Permissions};
@@ -0,0 +1,462 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+
+/// A bookmark from the document outline, flattened in reading order.
+#[derive(Debug, Clone)]
+pub(crate) struct OutlineEntry {
+    pub title: String,
+    /// 1 for top-level bookmarks, 2 for their children, ...
+    pub depth: usize,
+    /// The page the bookmark points to, if its destination could be resolved.
+    pub page: Option<ObjectId>,
+}
+
+/// Reads the `/Outlines` tree of `doc` into a flat, pre-order list.
+/// Returns an empty list if the document has no outline.
+pub(crate) fn read_outline(doc: &Document) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let first = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|o| resolve_dict(doc, o))
+        .and_then(|outlines| outlines.get(b"First").ok())
+        .and_then(|o| o.as_reference().ok());
+
+    let mut visited = HashSet::new();
+    if let Some(first) = first {
+        walk_outline(doc, first, 1, &mut visited, &mut entries);
+    }
+    entries
+}
+
+fn walk_outline(
+    doc: &Document,
+    first: ObjectId,
+    depth: usize,
+    visited: &mut HashSet<ObjectId>,
+    entries: &mut Vec<OutlineEntry>,
+) {
+    let mut next = Some(first);
+    while let Some(id) = next {
+        // Guard against malformed outlines that loop back on themselves
+        if !visited.insert(id) {
+            break;
+        }
+        let Ok(item) = doc.get_dictionary(id) else {
+            break;
+        };
+
+        let title = item
+            .get(b"Title")
+            .ok()
+            .and_then(|o| resolve(doc, o))
+            .and_then(|o| o.as_str().ok())
+            .map(decode_text_string)
+            .unwrap_or_default();
+
+        entries.push(OutlineEntry {
+            title,
+            depth,
+            page: outline_item_page(doc, item),
+        });
+
+        if let Ok(child) = item.get(b"First").and_then(Object::as_reference) {
+            walk_outline(doc, child, depth + 1, visited, entries);
+        }
+
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+}
+
+/// Resolves the target page of an outline item, through `/Dest` or a `GoTo` action.
+fn outline_item_page(doc: &Document, item: &Dictionary) -> Option<ObjectId> {
+    if let Ok(dest) = item.get(b"Dest") {
+        return destination_page(doc, dest);
+    }
+    let action = item.get(b"A").ok().and_then(|o| resolve_dict(doc, o))?;
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    destination_page(doc, action.get(b"D").ok()?)
+}
+
+/// Resolves an explicit (`[page /XYZ ...]`) or named destination to its page object.
+pub(crate) fn destination_page(doc: &Document, dest: &Object) -> Option<ObjectId> {
+    match resolve(doc, dest)? {
+        Object::Array(arr) => arr.first()?.as_reference().ok(),
+        Object::Dictionary(dict) => destination_page(doc, dict.get(b"D").ok()?),
+        Object::Name(name) | Object::String(name, _) => {
+            let target = named_destination(doc, name)?;
+            // A named destination must not point at another name
+            match resolve(doc, target)? {
+                Object::Name(_) | Object::String(..) => None,
+                _ => destination_page(doc, target),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Looks `name` up in the catalog `/Dests` dictionary or the `/Names` `/Dests` name tree.
+fn named_destination<'a>(doc: &'a Document, name: &[u8]) -> Option<&'a Object> {
+    let catalog = doc.catalog().ok()?;
+    if let Some(dests) = catalog
+        .get(b"Dests")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+    {
+        if let Ok(dest) = dests.get(name) {
+            return Some(dest);
+        }
+    }
+    let tree = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+        .and_then(|names| names.get(b"Dests").ok())
+        .and_then(|o| resolve_dict(doc, o))?;
+    lookup_name_tree(doc, tree, name, 0)
+}
+
+fn lookup_name_tree<'a>(
+    doc: &'a Document,
+    node: &'a Dictionary,
+    name: &[u8],
+    level: usize,
+) -> Option<&'a Object> {
+    if level > 32 {
+        return None;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [key, value] = pair {
+                if resolve(doc, key).and_then(|k| k.as_str().ok()) == Some(name) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(kid) = resolve_dict(doc, kid) {
+                if let Some(found) = lookup_name_tree(doc, kid, name, level + 1) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn resolve<'a>(doc: &'a Document, obj: &'a Object) -> Option<&'a Object> {
+    doc.dereference(obj).ok().map(|(_, o)| o)
+}
+
+fn resolve_dict<'a>(doc: &'a Document, obj: &'a Object) -> Option<&'a Dictionary> {
+    resolve(doc, obj).and_then(|o| o.as_dict().ok())
+}
+
+/// Decodes a PDF text string: UTF-16BE with a byte order mark, otherwise PDFDocEncoding
+/// (treated as Latin-1, which matches it for printable characters).
+pub(crate) fn decode_text_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(&bytes[3..]).to_string()
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// Device names that Windows reserves, with or without an extension.
+const RESERVED_FILE_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Turns a bookmark title into something usable as a file name on every platform. Names that
+/// Windows reserves for devices, like "CON" or "com1.txt", get a trailing underscore.
+pub(crate) fn sanitize_file_name(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(100)
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.').trim();
+    if cleaned.is_empty() {
+        return "section".to_string();
+    }
+    let stem = cleaned.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_FILE_NAMES.contains(&stem.to_lowercase().as_str()) {
+        format!("{}_", cleaned)
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Encodes `text` as a PDF text string: a literal for ASCII, UTF-16BE with a byte order mark otherwise.
+pub(crate) fn encode_text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+        Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+    }
+}
+
+/// The first and last top-level items of the document outline, if it has any.
+pub(crate) fn outline_top_items(doc: &Document) -> Option<(ObjectId, ObjectId)> {
+    let outlines = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|o| resolve_dict(doc, o))?;
+    let first = outlines.get(b"First").and_then(Object::as_reference).ok()?;
+
+    // Follow the sibling chain instead of trusting `/Last`
+    let mut visited = HashSet::new();
+    let mut last = first;
+    while visited.insert(last) {
+        match doc
+            .get_dictionary(last)
+            .and_then(|item| item.get(b"Next"))
+            .and_then(Object::as_reference)
+        {
+            Ok(next) if doc.get_dictionary(next).is_ok() => last = next,
+            _ => break,
+        }
+    }
+    Some((first, last))
+}
+
+/// Named destinations of a document, split by how they are referenced.
+#[derive(Default)]
+pub(crate) struct NamedDestinations {
+    /// Entries of the catalog `/Dests` dictionary, referenced by name objects.
+    pub by_name: Vec<(Vec<u8>, Object)>,
+    /// Entries of the `/Names` `/Dests` name tree, referenced by strings.
+    pub by_string: Vec<(Vec<u8>, Object)>,
+}
+
+pub(crate) fn named_destinations(doc: &Document) -> NamedDestinations {
+    let mut result = NamedDestinations::default();
+    let Ok(catalog) = doc.catalog() else {
+        return result;
+    };
+    if let Some(dests) = catalog
+        .get(b"Dests")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+    {
+        result.by_name = dests
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+    }
+    if let Some(tree) = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(|o| resolve_dict(doc, o))
+        .and_then(|names| names.get(b"Dests").ok())
+        .and_then(|o| resolve_dict(doc, o))
+    {
+        flatten_name_tree(doc, tree, 0, &mut result.by_string);
+    }
+    result
+}
+
+fn flatten_name_tree(
+    doc: &Document,
+    node: &Dictionary,
+    level: usize,
+    out: &mut Vec<(Vec<u8>, Object)>,
+) {
+    if level > 32 {
+        return;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [key, value] = pair {
+                if let Some(key) = resolve(doc, key).and_then(|k| k.as_str().ok()) {
+                    out.push((key.to_vec(), value.clone()));
+                }
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(kid) = resolve_dict(doc, kid) {
+                flatten_name_tree(doc, kid, level + 1, out);
+            }
+        }
+    }
+}
+
+/// Rewrites every `/Dest` and `GoTo` `/D` entry that refers to a renamed destination.
+/// `by_name` renames name-object references, `by_string` renames string references.
+pub(crate) fn rename_destination_refs(
+    doc: &mut Document,
+    by_name: &HashMap<Vec<u8>, Vec<u8>>,
+    by_string: &HashMap<Vec<u8>, Vec<u8>>,
+) {
+    if by_name.is_empty() && by_string.is_empty() {
+        return;
+    }
+    let rename = |dest: &mut Object| match dest {
+        Object::Name(name) => {
+            if let Some(new_name) = by_name.get(name) {
+                *name = new_name.clone();
+            }
+        }
+        Object::String(name, _) => {
+            if let Some(new_name) = by_string.get(name) {
+                *name = new_name.clone();
+            }
+        }
+        _ => {}
+    };
+
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        if let Ok(dest) = dict.get_mut(b"Dest") {
+            rename(dest);
+        }
+        let is_goto = dict
+            .get(b"S")
+            .and_then(Object::as_name)
+            .map(|s| s == b"GoTo")
+            .unwrap_or(false);
+        if is_goto {
+            if let Ok(dest) = dict.get_mut(b"D") {
+                rename(dest);
+            }
+        }
+        // Link annotations may also carry their action inline
+        if let Ok(Object::Dictionary(action)) = dict.get_mut(b"A") {
+            if action.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo".as_slice()) {
+                if let Ok(dest) = action.get_mut(b"D") {
+                    rename(dest);
+                }
+            }
+        }
+    }
+}
+
+/// Builds a flat name tree node from `(key, value)` pairs, sorted by key as the spec requires.
+pub(crate) fn build_name_tree(mut entries: Vec<(Vec<u8>, Object)>) -> Dictionary {
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let names: Vec<Object> = entries
+        .into_iter()
+        .flat_map(|(key, value)| [Object::String(key, lopdf::StringFormat::Literal), value])
+        .collect();
+    let mut dict = Dictionary::new();
+    dict.set("Names", names);
+    dict
+}
+
+/// Reads the `/PageLabels` number tree into `(page index, label dictionary)` pairs.
+pub(crate) fn page_labels(doc: &Document) -> Vec<(i64, Object)> {
+    let mut out = Vec::new();
+    if let Some(tree) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"PageLabels").ok())
+        .and_then(|o| resolve_dict(doc, o))
+    {
+        flatten_number_tree(doc, tree, 0, &mut out);
+    }
+    out
+}
+
+fn flatten_number_tree(
+    doc: &Document,
+    node: &Dictionary,
+    level: usize,
+    out: &mut Vec<(i64, Object)>,
+) {
+    if level > 32 {
+        return;
+    }
+    if let Ok(nums) = node.get(b"Nums").and_then(Object::as_array) {
+        for pair in nums.chunks(2) {
+            if let [key, value] = pair {
+                if let Ok(key) = key.as_i64() {
+                    out.push((key, value.clone()));
+                }
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(kid) = resolve_dict(doc, kid) {
+                flatten_number_tree(doc, kid, level + 1, out);
+            }
+        }
+    }
+}
+
+/// Removes every explicit destination, `GoTo` action and `/P` back-reference that points at
+/// one of `pages`. Bookmarks keep their title but stop navigating; links lose their action.
+pub(crate) fn drop_destinations_to(doc: &mut Document, pages: &HashSet<ObjectId>) {
+    let targets_removed = |dest: &Object| match dest {
+        Object::Array(arr) => arr
+            .first()
+            .and_then(|o| o.as_reference().ok())
+            .is_some_and(|id| pages.contains(&id)),
+        _ => false,
+    };
+    let is_dead_goto = |dict: &Dictionary| {
+        dict.get(b"S").and_then(Object::as_name).ok() == Some(b"GoTo".as_slice())
+            && dict.get(b"D").is_ok_and(targets_removed)
+    };
+
+    // Indirect actions are dropped from whatever refers to them in a second pass
+    let dead_actions: HashSet<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().is_ok_and(is_dead_goto))
+        .map(|(&id, _)| id)
+        .collect();
+
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        if dict.get(b"Dest").is_ok_and(targets_removed) {
+            dict.remove(b"Dest");
+        }
+        let dead_action = match dict.get(b"A") {
+            Ok(Object::Dictionary(action)) => is_dead_goto(action),
+            Ok(Object::Reference(id)) => dead_actions.contains(id),
+            _ => false,
+        };
+        if dead_action {
+            dict.remove(b"A");
+        }
+        if dict
+            .get(b"P")
+            .and_then(Object::as_reference)
+            .is_ok_and(|id| pages.contains(&id))
+        {
+            dict.remove(b"P");
+        }
+    }
+}
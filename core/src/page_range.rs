@@ -0,0 +1,161 @@
+use crate::error::PdfError;
+
+/// A named group of 1-based page numbers, produced from a page-range expression.
+/// Each group becomes one output file when splitting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageGroup {
+    pub label: String,
+    pub pages: Vec<u32>,
+}
+
+/// Parses a page-range expression into page groups.
+///
+/// The expression is a comma separated list of items, each producing one or more groups:
+/// - `5` a single page
+/// - `1-3` an inclusive range, `8-` runs to the last page, `-4` starts at page 1
+/// - `every 10 pages` (or `every 10`) chunks the whole document
+/// - `odd` / `even` all odd or even pages
+/// - `all` every page
+pub fn parse_page_groups(expr: &str, page_count: u32) -> Result<Vec<PageGroup>, PdfError> {
+    let mut groups = Vec::new();
+
+    for item in expr.split(',') {
+        let item = item.trim().to_lowercase();
+        if item.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = item.strip_prefix("every") {
+            let n = rest
+                .trim()
+                .trim_end_matches("pages")
+                .trim_end_matches("page")
+                .trim();
+            let chunk: u32 = n
+                .parse()
+                .map_err(|_| PdfError::InvalidRange(item.clone()))?;
+            if chunk == 0 {
+                return Err(PdfError::InvalidRange(item.clone()));
+            }
+            let mut start = 1;
+            while start <= page_count {
+                let end = start.saturating_add(chunk - 1).min(page_count);
+                groups.push(range_group(start, end));
+                match end.checked_add(1) {
+                    Some(next) => start = next,
+                    None => break,
+                }
+            }
+            continue;
+        }
+
+        match item.as_str() {
+            "odd" | "even" => {
+                let first = if item == "odd" { 1 } else { 2 };
+                let pages: Vec<u32> = (first..=page_count).step_by(2).collect();
+                if pages.is_empty() {
+                    return Err(PdfError::InvalidRange(item.clone()));
+                }
+                groups.push(PageGroup { label: item, pages });
+            }
+            "all" => {
+                if page_count == 0 {
+                    return Err(PdfError::InvalidRange(item.clone()));
+                }
+                groups.push(range_group(1, page_count));
+            }
+            _ => {
+                let (start, end) = match item.split_once('-') {
+                    Some((a, b)) => {
+                        let start = if a.trim().is_empty() {
+                            1
+                        } else {
+                            parse_page(a, &item)?
+                        };
+                        let end = if b.trim().is_empty() {
+                            page_count
+                        } else {
+                            parse_page(b, &item)?
+                        };
+                        (start, end)
+                    }
+                    None => {
+                        let page = parse_page(&item, &item)?;
+                        (page, page)
+                    }
+                };
+
+                if start == 0 || start > end {
+                    return Err(PdfError::InvalidPage(start));
+                }
+                if end > page_count {
+                    return Err(PdfError::InvalidPage(end));
+                }
+                groups.push(range_group(start, end));
+            }
+        }
+    }
+
+    if groups.is_empty() {
+        return Err(PdfError::InvalidRange(expr.to_string()));
+    }
+    Ok(groups)
+}
+
+/// Parses a page-range expression into a single flat, ordered page list.
+/// Pages keep the order they appear in the expression; duplicates are allowed.
+pub fn parse_page_list(expr: &str, page_count: u32) -> Result<Vec<u32>, PdfError> {
+    Ok(parse_page_groups(expr, page_count)?
+        .into_iter()
+        .flat_map(|g| g.pages)
+        .collect())
+}
+
+fn parse_page(s: &str, item: &str) -> Result<u32, PdfError> {
+    s.trim()
+        .parse()
+        .map_err(|_| PdfError::InvalidRange(item.to_string()))
+}
+
+fn range_group(start: u32, end: u32) -> PageGroup {
+    let label = if start == end {
+        start.to_string()
+    } else {
+        format!("{}-{}", start, end)
+    };
+    PageGroup {
+        label,
+        pages: (start..=end).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ranges() {
+        let groups = parse_page_groups("1-3, 5, 8-", 10).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].pages, vec![1, 2, 3]);
+        assert_eq!(groups[1].label, "5");
+        assert_eq!(groups[2].pages, vec![8, 9, 10]);
+
+        assert!(parse_page_groups("4-2", 10).is_err());
+        assert!(parse_page_groups("11", 10).is_err());
+        assert!(parse_page_groups("abc", 10).is_err());
+    }
+
+    #[test]
+    fn test_parse_every_and_parity() {
+        let groups = parse_page_groups("every 4 pages", 10).unwrap();
+        let labels: Vec<_> = groups.iter().map(|g| g.label.as_str()).collect();
+        assert_eq!(labels, vec!["1-4", "5-8", "9-10"]);
+        let groups = parse_page_groups("every 4294967295", 3).unwrap();
+        assert_eq!(groups[0].pages, vec![1, 2, 3]);
+
+        let groups = parse_page_groups("odd,even", 5).unwrap();
+        assert_eq!(groups[0].pages, vec![1, 3, 5]);
+        assert_eq!(groups[1].pages, vec![2, 4]);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::engine::{RenderFormat, RenderOptions, RenderSize};
+use crate::error::PdfError;
+use crate::renderer::{PageRenderer, RenderDocument};
+use image::{DynamicImage, GrayImage, RgbaImage};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::SystemTime;
+use tiff::encoder::{colortype, Compression, DeflateLevel, Rational, TiffEncoder};
+use tiff::tags::{CompressionMethod, PhotometricInterpretation, ResolutionUnit, Tag};
+
+/// Identifies a loaded file; a changed file or another renderer gets a new key.
+#[derive(PartialEq)]
+struct DocumentKey {
+    renderer: &'static str,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+    /// Salted hash of the password, so the password itself is not kept around
+    password: Option<[u8; 32]>,
+}
+
+/// A loaded document that callers take turns rendering from.
+pub(crate) type SharedDocument = Arc<Mutex<Box<dyn RenderDocument>>>;
+
+/// The document thumbnails were last rendered from, so that previews requested page by page
+/// load it only once. It is read into memory rather than kept open, so the file can still be
+/// replaced while it is cached.
+static THUMBNAIL_DOCUMENT: Mutex<Option<(DocumentKey, SharedDocument)>> = Mutex::new(None);
+
+/// The PDF at `input`, shared with the previous call if that was for the same, unchanged
+/// file. Lock it only while rendering a page, so other callers are not held up.
+pub(crate) fn cached_document(
+    renderer: &dyn PageRenderer,
+    input: &Path,
+    password: Option<&str>,
+) -> Result<SharedDocument, PdfError> {
+    let metadata = std::fs::metadata(input).map_err(PdfError::Io)?;
+    let key = DocumentKey {
+        renderer: renderer.name(),
+        path: input.canonicalize().map_err(PdfError::Io)?,
+        modified: metadata.modified().ok(),
+        len: metadata.len(),
+        password: password.map(password_hash),
+    };
+
+    let mut cached = THUMBNAIL_DOCUMENT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some((cached_key, document)) = cached.as_ref() {
+        if *cached_key == key {
+            return Ok(document.clone());
+        }
+    }
+    // Let go of the previous document before opening the next
+    *cached = None;
+    let bytes = std::fs::read(input).map_err(PdfError::Io)?;
+    let document = Arc::new(Mutex::new(renderer.load(bytes, password)?));
+    *cached = Some((key, document.clone()));
+    Ok(document)
+}
+
+fn password_hash(password: &str) -> [u8; 32] {
+    static SALT: OnceLock<[u8; 16]> = OnceLock::new();
+    let mut hasher = Sha256::new();
+    hasher.update(SALT.get_or_init(rand::random));
+    hasher.update(password.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Pixels per point that bring a page of the given size in points to `size`. Sizes that come
+/// to no pixels, or to a scale that is not a finite number, are an error.
+pub(crate) fn scale(
+    size: RenderSize,
+    (page_width, page_height): (f32, f32),
+) -> Result<f32, PdfError> {
+    let scale = match size {
+        RenderSize::Dpi(dpi) => dpi / 72.0,
+        RenderSize::Fit {
+            width: Some(max_width),
+            height,
+        } => {
+            let scale = max_width as f32 / page_width;
+            match height {
+                Some(max_height) => scale.min(max_height as f32 / page_height),
+                None => scale,
+            }
+        }
+        RenderSize::Fit {
+            width: None,
+            height: Some(max_height),
+        } => max_height as f32 / page_height,
+        RenderSize::Fit {
+            width: None,
+            height: None,
+        } => 1.0,
+    };
+    if scale.is_finite() && scale > 0.0 {
+        Ok(scale)
+    } else {
+        Err(PdfError::Operation(format!(
+            "Invalid render size {:?} for a page of {} x {} pt",
+            size, page_width, page_height
+        )))
+    }
+}
+
+/// Renders a page (1-based) on white, scaled to fit in `max_px` x `max_px`, as PNG.
+pub(crate) fn thumbnail(
+    document: &dyn RenderDocument,
+    page: u32,
+    max_px: u32,
+) -> Result<Vec<u8>, PdfError> {
+    let max_px = max_px.max(1);
+    let size = RenderSize::Fit {
+        width: Some(max_px),
+        height: Some(max_px),
+    };
+    let scale = scale(size, document.page_size(page)?)?;
+    let rendered = document.render(page, scale, [255, 255, 255, 255], true)?;
+
+    let mut png = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rendered).to_rgb8())
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(save_error)?;
+    Ok(png.into_inner())
+}
+
+/// The RGBA color pages are rendered onto: the background, or transparent white.
+pub(crate) fn background(options: &RenderOptions) -> [u8; 4] {
+    match (options.background, options.format) {
+        (Some([r, g, b]), _) => [r, g, b, 255],
+        (None, RenderFormat::Jpeg { .. }) => [255, 255, 255, 255],
+        (None, _) => [255, 255, 255, 0],
+    }
+}
+
+/// Expands the `{name}` and `{page}` placeholders of a [`RenderOptions::file_name`] template.
+pub(crate) fn file_name(template: &str, name: &str, page: u32) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let placeholder = &rest[1..end];
+        let width = placeholder
+            .strip_prefix("page:0")
+            .and_then(|w| w.parse::<usize>().ok());
+        match (placeholder, width) {
+            ("name", _) => out.push_str(name),
+            ("page", _) => out.push_str(&page.to_string()),
+            (_, Some(width)) => out.push_str(&format!("{:0width$}", page, width = width)),
+            // Unknown placeholders are kept as written
+            _ => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Checks a [`RenderOptions::file_name`] template: it must stay inside the output directory,
+/// and name every page differently when several are rendered.
+pub(crate) fn check_file_name(template: &str, several_pages: bool) -> Result<(), PdfError> {
+    let path = Path::new(template);
+    let escapes = path.has_root()
+        || path
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+        // Windows separators and drive letters, wherever the check runs
+        || template.starts_with('\\')
+        || template.split(['/', '\\']).any(|part| part == "..")
+        || template.as_bytes().get(1) == Some(&b':');
+    if escapes {
+        return Err(PdfError::Operation(format!(
+            "File name template must be a relative path inside the output directory: {}",
+            template
+        )));
+    }
+    // Only `{page}` and `{page:0N}` expand to the page number
+    if several_pages && file_name(template, "", 1) == file_name(template, "", 2) {
+        return Err(PdfError::Operation(
+            "File name template must contain {page} when rendering several pages".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts a rendered page to the color type chosen by `options`. Alpha is only kept when
+/// the background is transparent.
+pub(crate) fn prepare(image: DynamicImage, options: &RenderOptions) -> DynamicImage {
+    if let Some(threshold) = options.threshold {
+        let mut gray = image.to_luma8();
+        for pixel in gray.pixels_mut() {
+            pixel.0[0] = if pixel.0[0] < threshold { 0 } else { 255 };
+        }
+        return DynamicImage::ImageLuma8(gray);
+    }
+    let opaque = background(options)[3] == 255;
+    match (options.grayscale, opaque) {
+        (true, true) => DynamicImage::ImageLuma8(image.to_luma8()),
+        (true, false) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        (false, true) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, false) => DynamicImage::ImageRgba8(image.into_rgba8()),
+    }
+}
+
+/// Writes an image in `format`, creating its directory if needed.
+pub(crate) fn save(
+    image: &DynamicImage,
+    path: &Path,
+    format: RenderFormat,
+) -> Result<(), PdfError> {
+    create_parent(path)?;
+    let result = match format {
+        RenderFormat::Jpeg { quality } => {
+            let file = std::fs::File::create(path).map_err(PdfError::Io)?;
+            image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+                std::io::BufWriter::new(file),
+                quality.clamp(1, 100),
+            ))
+        }
+        RenderFormat::Png => image.save_with_format(path, image::ImageFormat::Png),
+        RenderFormat::WebP => image.save_with_format(path, image::ImageFormat::WebP),
+        // The TIFF encoder takes no gray with alpha, so such pages are stored as RGBA
+        RenderFormat::Tiff => match image {
+            DynamicImage::ImageLumaA8(_) => DynamicImage::ImageRgba8(image.to_rgba8())
+                .save_with_format(path, image::ImageFormat::Tiff),
+            _ => image.save_with_format(path, image::ImageFormat::Tiff),
+        },
+    };
+    result.map_err(save_error)
+}
+
+pub(crate) fn create_parent(path: &Path) -> Result<(), PdfError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| PdfError::Operation(format!("Failed to create output dir: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn save_error(e: impl std::fmt::Display) -> PdfError {
+    PdfError::Operation(format!("Failed to save image: {}", e))
+}
+
+/// A TIFF file that takes one page at a time. Pages with only black and white pixels are
+/// stored at 1 bit per pixel with CCITT Group 4 compression, the others with Deflate.
+pub(crate) struct MultiPageTiff<W: Write + Seek> {
+    encoder: TiffEncoder<W>,
+    /// Pixels per inch, recorded in every page
+    dpi: Option<f32>,
+}
+
+impl<W: Write + Seek> MultiPageTiff<W> {
+    pub fn new(writer: W, dpi: Option<f32>) -> Result<Self, PdfError> {
+        let encoder = TiffEncoder::new(writer)
+            .map_err(save_error)?
+            .with_compression(Compression::Deflate(DeflateLevel::Balanced));
+        Ok(MultiPageTiff { encoder, dpi })
+    }
+
+    pub fn add_page(&mut self, image: &DynamicImage) -> Result<(), PdfError> {
+        let (width, height) = (image.width(), image.height());
+        if let Some(bilevel) = bilevel(image) {
+            return self.add_bilevel(&bilevel).map_err(save_error);
+        }
+        let resolution = self.dpi.map(|dpi| Rational {
+            n: dpi.round() as u32,
+            d: 1,
+        });
+        macro_rules! write_page {
+            ($color:ty, $data:expr) => {{
+                let mut page = self
+                    .encoder
+                    .new_image::<$color>(width, height)
+                    .map_err(save_error)?;
+                if let Some(resolution) = resolution {
+                    page.resolution(ResolutionUnit::Inch, resolution);
+                }
+                page.write_data($data).map_err(save_error)
+            }};
+        }
+        match image {
+            DynamicImage::ImageLuma8(gray) => write_page!(colortype::Gray8, gray.as_raw()),
+            DynamicImage::ImageRgb8(rgb) => write_page!(colortype::RGB8, rgb.as_raw()),
+            // Gray with alpha is stored as RGBA, as in `save`
+            _ => write_page!(colortype::RGBA8, image.to_rgba8().as_raw()),
+        }
+    }
+
+    fn add_bilevel(&mut self, image: &GrayImage) -> tiff::TiffResult<()> {
+        let (width, height) = image.dimensions();
+        let data = crate::xobject::group4(image.as_raw(), width);
+
+        let mut directory = self.encoder.image_directory()?;
+        let offset = directory.write_data(data.as_slice())?;
+        directory.write_tag(Tag::ImageWidth, width)?;
+        directory.write_tag(Tag::ImageLength, height)?;
+        directory.write_tag(Tag::BitsPerSample, 1u16)?;
+        directory.write_tag(Tag::Compression, CompressionMethod::Fax4.to_u16())?;
+        directory.write_tag(
+            Tag::PhotometricInterpretation,
+            PhotometricInterpretation::WhiteIsZero.to_u16(),
+        )?;
+        directory.write_tag(Tag::StripOffsets, offset as u32)?;
+        directory.write_tag(Tag::SamplesPerPixel, 1u16)?;
+        directory.write_tag(Tag::RowsPerStrip, height)?;
+        directory.write_tag(Tag::StripByteCounts, data.len() as u32)?;
+        if let Some(dpi) = self.dpi {
+            let resolution = || Rational {
+                n: dpi.round() as u32,
+                d: 1,
+            };
+            directory.write_tag(Tag::XResolution, resolution())?;
+            directory.write_tag(Tag::YResolution, resolution())?;
+            directory.write_tag(Tag::ResolutionUnit, ResolutionUnit::Inch.to_u16())?;
+        }
+        directory.finish()
+    }
+}
+
+/// The image as gray samples if every pixel is opaque black or white. Group 4 only encodes
+/// widths that fit in 16 bits.
+fn bilevel(image: &DynamicImage) -> Option<GrayImage> {
+    if image.width() > u16::MAX as u32 {
+        return None;
+    }
+    let pure = match image {
+        DynamicImage::ImageLuma8(gray) => gray.pixels().all(|p| matches!(p.0[0], 0 | 255)),
+        DynamicImage::ImageRgb8(rgb) => rgb
+            .pixels()
+            .all(|p| matches!(p.0, [0, 0, 0] | [255, 255, 255])),
+        _ => false,
+    };
+    pure.then(|| image.to_luma8())
+}
+
+/// Tiles the pages left to right, top to bottom, `columns` per row, with `spacing` pixels
+/// between and around them. Every cell is the size of the largest page, which is centered in
+/// it.
+pub(crate) fn contact_sheet(
+    pages: &[DynamicImage],
+    columns: u32,
+    spacing: u32,
+    background: [u8; 4],
+) -> Result<RgbaImage, PdfError> {
+    let columns = columns.clamp(1, pages.len().max(1) as u32);
+    let rows = (pages.len() as u32).div_ceil(columns);
+    let cell_width = pages.iter().map(DynamicImage::width).max().unwrap_or(0);
+    let cell_height = pages.iter().map(DynamicImage::height).max().unwrap_or(0);
+
+    // `cells` cells of `cell` pixels with spacing between and around them
+    let extent = |cells: u32, cell: u32| {
+        cells
+            .checked_mul(cell)?
+            .checked_add(cells.checked_add(1)?.checked_mul(spacing)?)
+    };
+    let (Some(width), Some(height)) = (extent(columns, cell_width), extent(rows, cell_height))
+    else {
+        return Err(PdfError::Operation(
+            "Contact sheet is too large".to_string(),
+        ));
+    };
+    let mut sheet = RgbaImage::from_pixel(width, height, image::Rgba(background));
+    for (index, page) in pages.iter().enumerate() {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let x = spacing + column * (cell_width + spacing) + (cell_width - page.width()) / 2;
+        let y = spacing + row * (cell_height + spacing) + (cell_height - page.height()) / 2;
+        image::imageops::overlay(&mut sheet, &page.to_rgba8(), x as i64, y as i64);
+    }
+    Ok(sheet)
+}
@@ -0,0 +1,275 @@
+//! Page rasterizers used by [`crate::pdf_to_images`] and the thumbnail functions.
+//!
+//! [`PdfiumRenderer`] draws with the PDFium shared library. [`HayroRenderer`] is written in
+//! Rust and built in, so pages can be rendered where PDFium is not installed.
+//! [`default_renderer`] picks PDFium when it can be loaded and the built-in renderer otherwise.
+
+use crate::error::PdfError;
+use hayro::hayro_interpret::InterpreterSettings;
+use hayro::hayro_syntax::{DecryptionError, LoadPdfError, Pdf};
+use hayro::vello_cpu::color::AlphaColor;
+use hayro::vello_cpu::peniko::ImageAlphaType;
+use hayro::{PixmapSettings, RenderCache, RenderSettings};
+use image::RgbaImage;
+use pdfium_render::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Turns PDF pages into pixels.
+pub trait PageRenderer: Send + Sync {
+    /// Short name of the backend, e.g. "pdfium".
+    fn name(&self) -> &'static str;
+
+    /// Loads a PDF from its bytes.
+    fn load(
+        &self,
+        data: Vec<u8>,
+        password: Option<&str>,
+    ) -> Result<Box<dyn RenderDocument>, PdfError>;
+}
+
+/// A PDF loaded by a [`PageRenderer`]. Pages are numbered from 1.
+pub trait RenderDocument: Send {
+    fn page_count(&self) -> u32;
+
+    /// Width and height of a page in points, as displayed after its rotation.
+    fn page_size(&self, page: u32) -> Result<(f32, f32), PdfError>;
+
+    /// Renders a page at `scale` pixels per point onto `background` (RGBA). With `smooth`
+    /// off, edges are drawn without anti-aliasing where the backend supports it.
+    fn render(
+        &self,
+        page: u32,
+        scale: f32,
+        background: [u8; 4],
+        smooth: bool,
+    ) -> Result<RgbaImage, PdfError>;
+}
+
+/// PDFium, bound from `pdfium_path` or else from the system, or [`HayroRenderer`] if PDFium
+/// cannot be loaded.
+pub fn default_renderer(pdfium_path: Option<&Path>) -> Box<dyn PageRenderer> {
+    match PdfiumRenderer::bind(pdfium_path) {
+        Ok(pdfium) => Box::new(pdfium),
+        Err(_) => Box::new(HayroRenderer),
+    }
+}
+
+/// The PDFium library, bound on first use. A failed binding is kept too, with the path it
+/// was tried from, so that it is not retried on every call.
+static PDFIUM: Mutex<Option<Result<&'static Pdfium, BindFailure>>> = Mutex::new(None);
+
+struct BindFailure {
+    path: Option<PathBuf>,
+    message: String,
+}
+
+/// Renders with the PDFium shared library.
+#[derive(Clone, Copy)]
+pub struct PdfiumRenderer {
+    pdfium: &'static Pdfium,
+}
+
+impl PdfiumRenderer {
+    /// Binds PDFium from `path` or else from the system. PDFium is initialized once per
+    /// process and shuts down when its binding is dropped, so the first binding is kept for
+    /// the rest of the process and later calls reuse it. A failure is remembered as well and
+    /// only retried for another `path`.
+    pub fn bind(path: Option<&Path>) -> Result<Self, PdfError> {
+        let mut pdfium = PDFIUM.lock().unwrap_or_else(PoisonError::into_inner);
+        match &*pdfium {
+            Some(Ok(pdfium)) => return Ok(PdfiumRenderer { pdfium }),
+            Some(Err(failure)) if failure.path.as_deref() == path => {
+                return Err(PdfError::Operation(failure.message.clone()));
+            }
+            _ => {}
+        }
+        let bindings = match path {
+            Some(path) => {
+                Pdfium::bind_to_library(path).or_else(|_| Pdfium::bind_to_system_library())
+            }
+            None => Pdfium::bind_to_system_library(),
+        };
+        let bindings = match bindings {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                let location = match path {
+                    Some(path) => format!("at {}", path.display()),
+                    None => "from the system".to_string(),
+                };
+                let message = format!(
+                    "Failed to bind Pdfium {}: {}. Please ensure pdfium.dll is correct and has required runtime dependencies.",
+                    location, e
+                );
+                *pdfium = Some(Err(BindFailure {
+                    path: path.map(Path::to_path_buf),
+                    message: message.clone(),
+                }));
+                return Err(PdfError::Operation(message));
+            }
+        };
+        let bound: &'static Pdfium = Box::leak(Box::new(Pdfium::new(bindings)));
+        *pdfium = Some(Ok(bound));
+        Ok(PdfiumRenderer { pdfium: bound })
+    }
+}
+
+impl PageRenderer for PdfiumRenderer {
+    fn name(&self) -> &'static str {
+        "pdfium"
+    }
+
+    fn load(
+        &self,
+        data: Vec<u8>,
+        password: Option<&str>,
+    ) -> Result<Box<dyn RenderDocument>, PdfError> {
+        let document = self
+            .pdfium
+            .load_pdf_from_byte_vec(data, password)
+            .map_err(|e| match e {
+                PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+                    password_error(password)
+                }
+                e => PdfError::Operation(format!("Failed to load PDF: {}", e)),
+            })?;
+        Ok(Box::new(PdfiumDocument(document)))
+    }
+}
+
+struct PdfiumDocument(PdfDocument<'static>);
+
+impl PdfiumDocument {
+    fn page(&self, page: u32) -> Result<PdfPage<'_>, PdfError> {
+        if page == 0 {
+            return Err(PdfError::InvalidPage(page));
+        }
+        self.0
+            .pages()
+            .get((page - 1) as PdfPageIndex)
+            .map_err(|_| PdfError::InvalidPage(page))
+    }
+}
+
+impl RenderDocument for PdfiumDocument {
+    fn page_count(&self) -> u32 {
+        self.0.pages().len() as u32
+    }
+
+    fn page_size(&self, page: u32) -> Result<(f32, f32), PdfError> {
+        let page = self.page(page)?;
+        Ok((page.width().value, page.height().value))
+    }
+
+    fn render(
+        &self,
+        page: u32,
+        scale: f32,
+        background: [u8; 4],
+        smooth: bool,
+    ) -> Result<RgbaImage, PdfError> {
+        let [r, g, b, a] = background;
+        let config = PdfRenderConfig::new()
+            .scale_page_by_factor(scale)
+            .set_clear_color(PdfColor::new(r, g, b, a))
+            .set_text_smoothing(smooth)
+            .set_path_smoothing(smooth)
+            .set_image_smoothing(smooth);
+        let pdf_page = self.page(page)?;
+        let bitmap = pdf_page
+            .render_with_config(&config)
+            .map_err(|e| PdfError::Operation(format!("Failed to render page {}: {}", page, e)))?;
+        Ok(bitmap.as_image().into_rgba8())
+    }
+}
+
+/// Renders with [hayro](https://crates.io/crates/hayro), a rasterizer written in Rust with
+/// the standard PDF fonts built in. It needs no shared library but is slower than PDFium and
+/// always anti-aliases.
+#[derive(Clone, Copy, Default)]
+pub struct HayroRenderer;
+
+impl PageRenderer for HayroRenderer {
+    fn name(&self) -> &'static str {
+        "hayro"
+    }
+
+    fn load(
+        &self,
+        data: Vec<u8>,
+        password: Option<&str>,
+    ) -> Result<Box<dyn RenderDocument>, PdfError> {
+        let pdf = Pdf::new_with_password(data, password.unwrap_or("")).map_err(|e| match e {
+            LoadPdfError::Decryption(DecryptionError::PasswordProtected) => {
+                password_error(password)
+            }
+            LoadPdfError::Decryption(e) => {
+                PdfError::Operation(format!("Failed to decrypt PDF: {:?}", e))
+            }
+            LoadPdfError::Invalid => PdfError::Operation("Failed to load PDF".to_string()),
+        })?;
+        Ok(Box::new(HayroDocument(pdf)))
+    }
+}
+
+struct HayroDocument(Pdf);
+
+impl HayroDocument {
+    fn page(&self, page: u32) -> Result<&hayro::hayro_syntax::page::Page<'_>, PdfError> {
+        let index = (page as usize)
+            .checked_sub(1)
+            .ok_or(PdfError::InvalidPage(page))?;
+        self.0.pages().get(index).ok_or(PdfError::InvalidPage(page))
+    }
+}
+
+impl RenderDocument for HayroDocument {
+    fn page_count(&self) -> u32 {
+        self.0.pages().len() as u32
+    }
+
+    fn page_size(&self, page: u32) -> Result<(f32, f32), PdfError> {
+        Ok(self.page(page)?.render_dimensions())
+    }
+
+    fn render(
+        &self,
+        page: u32,
+        scale: f32,
+        background: [u8; 4],
+        _smooth: bool,
+    ) -> Result<RgbaImage, PdfError> {
+        let pdf_page = self.page(page)?;
+        let (width, height) = pdf_page.render_dimensions();
+        // The pixmap is at most 65535 pixels on either side
+        if width * scale >= u16::MAX as f32 || height * scale >= u16::MAX as f32 {
+            return Err(PdfError::Operation(format!(
+                "Page {} is too large to render at this size",
+                page
+            )));
+        }
+        let [r, g, b, a] = background;
+        let pixmap = hayro::render(
+            pdf_page,
+            &RenderCache::new(),
+            &InterpreterSettings::default(),
+            &RenderSettings::default(),
+            &PixmapSettings {
+                x_scale: scale,
+                y_scale: scale,
+                bg_color: AlphaColor::from_rgba8(r, g, b, a),
+            },
+        );
+        let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+        RgbaImage::from_raw(width, height, pixmap.take_rgba8(ImageAlphaType::Alpha))
+            .ok_or_else(|| PdfError::Operation(format!("Failed to render page {}", page)))
+    }
+}
+
+fn password_error(password: Option<&str>) -> PdfError {
+    if password.is_some_and(|p| !p.is_empty()) {
+        PdfError::WrongPassword
+    } else {
+        PdfError::PasswordRequired
+    }
+}
@@ -0,0 +1,469 @@
+//! Cleanup of photographed paper documents before [`crate::images_to_pdf_with_options`] places
+//! them: the sheet is cut out of its surroundings with its perspective straightened, tilted
+//! text is leveled, uniform borders are trimmed and colors are reduced.
+
+use crate::engine::{ScanCleanup, ScanColor};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+
+/// Longest side of the reduced copy that the sheet is looked for in, in pixels
+const FIND_SIZE: u32 = 600;
+/// Longest side of the reduced copy that lines of text are measured in, in pixels
+const SKEW_SIZE: u32 = 1200;
+/// Largest tilt that is corrected, in degrees
+const MAX_SKEW: f32 = 10.0;
+
+/// Runs the steps of `cleanup` on an image that is already upright.
+pub(crate) fn clean(image: DynamicImage, cleanup: &ScanCleanup) -> DynamicImage {
+    let mut image = image.into_rgb8();
+    if cleanup.detect_edges {
+        if let Some(corners) = find_sheet(&imageops::grayscale(&image)) {
+            image = warp(&image, corners);
+        }
+    }
+    if cleanup.deskew {
+        let angle = skew_angle(&imageops::grayscale(&image));
+        if angle.abs() >= 0.1 {
+            image = rotate(&image, angle);
+        }
+    }
+    if cleanup.trim_borders {
+        let (x, y, width, height) = trim(&imageops::grayscale(&image));
+        image = imageops::crop_imm(&image, x, y, width, height).to_image();
+    }
+    match cleanup.color {
+        ScanColor::Color => DynamicImage::ImageRgb8(image),
+        ScanColor::Grayscale => DynamicImage::ImageLuma8(imageops::grayscale(&image)),
+        ScanColor::Bilevel { threshold } => {
+            let mut gray = imageops::grayscale(&image);
+            match threshold {
+                Some(threshold) => {
+                    for p in gray.pixels_mut() {
+                        p.0[0] = if p.0[0] < threshold { 0 } else { 255 };
+                    }
+                }
+                None => gray = sauvola(&gray),
+            }
+            DynamicImage::ImageLuma8(gray)
+        }
+    }
+}
+
+/// A copy of `gray` at most `size` pixels on its longer side, and the factor it was reduced by.
+fn reduced(gray: &GrayImage, size: u32) -> (GrayImage, f32) {
+    let (width, height) = gray.dimensions();
+    let scale = size as f32 / width.max(height) as f32;
+    if scale >= 1.0 {
+        return (gray.clone(), 1.0);
+    }
+    let small = imageops::resize(
+        gray,
+        ((width as f32 * scale).round() as u32).max(1),
+        ((height as f32 * scale).round() as u32).max(1),
+        FilterType::Triangle,
+    );
+    (small, scale)
+}
+
+/// The gray level that best splits the image into a dark and a light class, by Otsu's method.
+/// Pixels above it are light.
+fn otsu(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for p in gray.pixels() {
+        histogram[p.0[0] as usize] += 1;
+    }
+    let total = histogram.iter().sum::<u64>() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &n)| value as f64 * n as f64)
+        .sum();
+    let (mut dark, mut dark_sum) = (0.0, 0.0);
+    let (mut best, mut threshold) = (-1.0, 0);
+    for (value, &n) in histogram.iter().enumerate() {
+        dark += n as f64;
+        dark_sum += value as f64 * n as f64;
+        let light = total - dark;
+        if dark == 0.0 || light == 0.0 {
+            continue;
+        }
+        let spread = dark * light * (dark_sum / dark - (sum - dark_sum) / light).powi(2);
+        if spread > best {
+            best = spread;
+            threshold = value as u8;
+        }
+    }
+    threshold
+}
+
+/// Corners of the sheet of paper in a photo, clockwise from the top left, in pixels. The
+/// sheet is the largest light area; `None` unless it covers a fifth of the photo and fills
+/// most of the quadrilateral between its corners. Sheets turned by close to 45 degrees are
+/// not recognized.
+fn find_sheet(gray: &GrayImage) -> Option<[(f32, f32); 4]> {
+    let (small, scale) = reduced(gray, FIND_SIZE);
+    // Blurring merges the text into the paper around it
+    let small = imageops::blur(&small, 2.0);
+    let threshold = otsu(&small);
+    let (width, height) = small.dimensions();
+    let light = |i: usize| small.as_raw()[i] > threshold;
+
+    // Pixel count and corners of the largest light area, found by flood fill. The corners
+    // are the points with the smallest x + y, largest x - y, largest x + y and smallest x - y.
+    let mut best: Option<(usize, [(u32, u32); 4])> = None;
+    let mut seen = vec![false; small.as_raw().len()];
+    let mut stack = Vec::new();
+    for start in 0..seen.len() {
+        if seen[start] || !light(start) {
+            continue;
+        }
+        seen[start] = true;
+        stack.push(start);
+        let mut count = 0;
+        let first = (start as u32 % width, start as u32 / width);
+        let mut corners = [first; 4];
+        while let Some(i) = stack.pop() {
+            count += 1;
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let sum = |(x, y): (u32, u32)| x + y;
+            let difference = |(x, y): (u32, u32)| x as i64 - y as i64;
+            if sum((x, y)) < sum(corners[0]) {
+                corners[0] = (x, y);
+            }
+            if difference((x, y)) > difference(corners[1]) {
+                corners[1] = (x, y);
+            }
+            if sum((x, y)) > sum(corners[2]) {
+                corners[2] = (x, y);
+            }
+            if difference((x, y)) < difference(corners[3]) {
+                corners[3] = (x, y);
+            }
+            let row = width as usize;
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - row),
+                (y + 1 < height).then(|| i + row),
+            ];
+            for j in neighbors.into_iter().flatten() {
+                if !seen[j] && light(j) {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        if best.is_none_or(|(most, _)| count > most) {
+            best = Some((count, corners));
+        }
+    }
+
+    let (count, corners) = best?;
+    let corners = corners.map(|(x, y)| ((x as f32 + 0.5) / scale, (y as f32 + 0.5) / scale));
+    let outline = (0..4)
+        .map(|i| {
+            let ((x0, y0), (x1, y1)) = (corners[i], corners[(i + 1) % 4]);
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f32>()
+        .abs()
+        / 2.0;
+    let area = count as f32 / (scale * scale);
+    let photo = gray.width() as f32 * gray.height() as f32;
+    (outline >= photo / 5.0 && area >= outline * 0.85).then_some(corners)
+}
+
+/// A projective map of the plane: `[a, b, c, d, e, f, g, h]` takes (x, y) to
+/// ((a x + b y + c) / w, (d x + e y + f) / w) with w = g x + h y + 1.
+struct Projection([f64; 8]);
+
+impl Projection {
+    /// The map that takes each of `from` to the point at the same index in `to`, or `None` if
+    /// three of the points are on a line.
+    fn between(from: [(f32, f32); 4], to: [(f32, f32); 4]) -> Option<Self> {
+        // Two linear equations in the eight unknowns for every pair of points
+        let mut rows = [[0.0f64; 9]; 8];
+        for (i, ((x, y), (u, v))) in from.into_iter().zip(to).enumerate() {
+            let (x, y, u, v) = (x as f64, y as f64, u as f64, v as f64);
+            rows[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            rows[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        // Gaussian elimination with partial pivoting
+        for column in 0..8 {
+            let pivot = (column..8)
+                .max_by(|&a, &b| rows[a][column].abs().total_cmp(&rows[b][column].abs()))?;
+            if rows[pivot][column].abs() < 1e-9 {
+                return None;
+            }
+            rows.swap(column, pivot);
+            let pivot_row = rows[column];
+            for (i, row) in rows.iter_mut().enumerate() {
+                if i != column {
+                    let factor = row[column] / pivot_row[column];
+                    for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(column) {
+                        *value -= factor * pivot_value;
+                    }
+                }
+            }
+        }
+        Some(Projection(std::array::from_fn(|i| rows[i][8] / rows[i][i])))
+    }
+
+    fn map(&self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f, g, h] = self.0;
+        let (x, y) = (x as f64, y as f64);
+        let w = g * x + h * y + 1.0;
+        (
+            ((a * x + b * y + c) / w) as f32,
+            ((d * x + e * y + f) / w) as f32,
+        )
+    }
+}
+
+/// A `width` x `height` image whose pixels are taken from where `projection` maps their
+/// centers in `image`, with `background` outside of it.
+fn resample(
+    image: &RgbImage,
+    width: u32,
+    height: u32,
+    projection: &Projection,
+    background: Rgb<u8>,
+) -> RgbImage {
+    let (max_x, max_y) = (image.width() as f32 - 1.0, image.height() as f32 - 1.0);
+    RgbImage::from_fn(width, height, |x, y| {
+        let (u, v) = projection.map(x as f32 + 0.5, y as f32 + 0.5);
+        let (u, v) = (u - 0.5, v - 0.5);
+        // Half a pixel past the edge still takes the edge's color
+        if !(-0.5..=max_x + 0.5).contains(&u) || !(-0.5..=max_y + 0.5).contains(&v) {
+            return background;
+        }
+        imageops::interpolate_bilinear(image, u.clamp(0.0, max_x), v.clamp(0.0, max_y))
+            .unwrap_or(background)
+    })
+}
+
+/// The quadrilateral at `corners` stretched into an upright rectangle as long as its longer
+/// sides.
+fn warp(image: &RgbImage, corners: [(f32, f32); 4]) -> RgbImage {
+    let distance = |(x0, y0): (f32, f32), (x1, y1): (f32, f32)| (x1 - x0).hypot(y1 - y0);
+    let [top_left, top_right, bottom_right, bottom_left] = corners;
+    let width = distance(top_left, top_right).max(distance(bottom_left, bottom_right));
+    let height = distance(top_left, bottom_left).max(distance(top_right, bottom_right));
+    let (width, height) = (width.round().max(1.0), height.round().max(1.0));
+    let rectangle = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    match Projection::between(rectangle, corners) {
+        Some(projection) => resample(
+            image,
+            width as u32,
+            height as u32,
+            &projection,
+            Rgb([255; 3]),
+        ),
+        None => image.clone(),
+    }
+}
+
+/// Tilt of the lines of text in degrees, clockwise, found as the angle along which the dark
+/// pixels line up in the sharpest rows. 0 if the image does not look like a page of text.
+fn skew_angle(gray: &GrayImage) -> f32 {
+    let (small, _) = reduced(gray, SKEW_SIZE);
+    let threshold = otsu(&small);
+    let (width, height) = small.dimensions();
+    let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    let dark: Vec<(f32, f32)> = small
+        .enumerate_pixels()
+        .filter(|(_, _, p)| p.0[0] <= threshold)
+        .map(|(x, y, _)| (x as f32 - center_x, y as f32 - center_y))
+        .collect();
+    // Text covers some but not most of a page
+    let pixels = small.as_raw().len();
+    if dark.len() < pixels / 1000 || dark.len() > pixels / 3 {
+        return 0.0;
+    }
+
+    let rows = (width as f32).hypot(height as f32).ceil() as usize + 2;
+    let mut histogram = vec![0u32; rows];
+    // Sum of the squared pixel counts of the rows the dark pixels fall in, after leveling
+    // lines at `angle`; highest when the rows alternate between lines and gaps
+    let mut sharpness = |angle: f32| -> f64 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        histogram.fill(0);
+        for &(x, y) in &dark {
+            let row = (y * cos - x * sin + rows as f32 / 2.0).max(0.0) as usize;
+            histogram[row.min(rows - 1)] += 1;
+        }
+        histogram.iter().map(|&n| (n as f64).powi(2)).sum()
+    };
+    let mut sharpest = |angles: &mut dyn Iterator<Item = f32>| -> f32 {
+        let mut best = (f64::MIN, 0.0);
+        for angle in angles {
+            let score = sharpness(angle);
+            if score > best.0 {
+                best = (score, angle);
+            }
+        }
+        best.1
+    };
+    // Half degree steps, then twentieths around the best of them
+    let steps = (MAX_SKEW * 2.0) as i32;
+    let coarse = sharpest(&mut (-steps..=steps).map(|i| i as f32 * 0.5));
+    sharpest(&mut (-10..=10).map(|i| coarse + i as f32 * 0.05))
+}
+
+/// The image turned counterclockwise by `angle` degrees about its center, keeping its size,
+/// with the corners that come in filled with the color of the paper.
+fn rotate(image: &RgbImage, angle: f32) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let (sin, cos) = (angle as f64).to_radians().sin_cos();
+    let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+    // Every output pixel comes from its position turned back clockwise
+    let projection = Projection([
+        cos,
+        -sin,
+        center_x - cos * center_x + sin * center_y,
+        sin,
+        cos,
+        center_y - sin * center_x - cos * center_y,
+        0.0,
+        0.0,
+    ]);
+    resample(image, width, height, &projection, paper_color(image))
+}
+
+/// Average color of the light pixels of an image.
+fn paper_color(image: &RgbImage) -> Rgb<u8> {
+    let threshold = otsu(&reduced(&imageops::grayscale(image), FIND_SIZE).0);
+    let (mut sum, mut count) = ([0u64; 3], 0u64);
+    // Every seventh pixel is plenty for an average
+    for p in image.pixels().step_by(7) {
+        let [r, g, b] = p.0;
+        let luma = (r as u32 * 2126 + g as u32 * 7152 + b as u32 * 722) / 10000;
+        if luma > threshold as u32 {
+            for (total, value) in sum.iter_mut().zip(p.0) {
+                *total += value as u64;
+            }
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return Rgb([255; 3]);
+    }
+    Rgb(sum.map(|total| (total / count) as u8))
+}
+
+/// Whether nearly all of a line of pixels is within a few shades of its median.
+fn uniform(pixels: impl Iterator<Item = u8>) -> bool {
+    let mut histogram = [0u32; 256];
+    let mut count = 0;
+    for p in pixels {
+        histogram[p as usize] += 1;
+        count += 1;
+    }
+    let mut below = 0;
+    let median = histogram
+        .iter()
+        .position(|&n| {
+            below += n;
+            below * 2 >= count
+        })
+        .unwrap_or(0) as i32;
+    let outliers: u32 = histogram
+        .iter()
+        .enumerate()
+        .filter(|&(value, _)| (value as i32 - median).abs() > 48)
+        .map(|(_, &n)| n)
+        .sum();
+    outliers * 200 <= count
+}
+
+/// The part of the image inside its uniform borders, such as the surface around a sheet or
+/// blank margins, as x, y, width and height. A little of the border is kept around the
+/// content; images that are uniform throughout are kept whole.
+fn trim(gray: &GrayImage) -> (u32, u32, u32, u32) {
+    let (width, height) = gray.dimensions();
+    let row = |y: u32| uniform((0..width).map(|x| gray.get_pixel(x, y).0[0]));
+    let (mut top, mut bottom) = (0, height);
+    while top < bottom && row(top) {
+        top += 1;
+    }
+    while bottom > top && row(bottom - 1) {
+        bottom -= 1;
+    }
+    let column = |x: u32| uniform((top..bottom).map(|y| gray.get_pixel(x, y).0[0]));
+    let (mut left, mut right) = (0, width);
+    while left < right && column(left) {
+        left += 1;
+    }
+    while right > left && column(right - 1) {
+        right -= 1;
+    }
+    if top >= bottom || left >= right {
+        return (0, 0, width, height);
+    }
+    let padding = width.min(height) / 50;
+    let (x0, y0) = (left.saturating_sub(padding), top.saturating_sub(padding));
+    let (x1, y1) = ((right + padding).min(width), (bottom + padding).min(height));
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+/// Black and white by Sauvola's method: each pixel is compared with a threshold from the mean
+/// and spread of the gray levels around it, which follows uneven lighting across a photo.
+fn sauvola(gray: &GrayImage) -> GrayImage {
+    const K: f64 = 0.2;
+    let (width, height) = gray.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    let radius = (width.min(height) as usize / 64).max(8);
+    let samples = gray.as_raw();
+
+    // Sums of the samples and their squares down each column over the rows in the window
+    let mut sums = vec![0u64; w];
+    let mut squares = vec![0u64; w];
+    let add_row = |sums: &mut [u64], squares: &mut [u64], y: usize, sign: i64| {
+        for (x, &p) in samples[y * w..(y + 1) * w].iter().enumerate() {
+            let p = p as i64;
+            sums[x] = (sums[x] as i64 + sign * p) as u64;
+            squares[x] = (squares[x] as i64 + sign * p * p) as u64;
+        }
+    };
+    for y in 0..radius.min(h) {
+        add_row(&mut sums, &mut squares, y, 1);
+    }
+
+    let mut output = GrayImage::new(width, height);
+    for y in 0..h {
+        if y + radius < h {
+            add_row(&mut sums, &mut squares, y + radius, 1);
+        }
+        if y > radius {
+            add_row(&mut sums, &mut squares, y - radius - 1, -1);
+        }
+        let rows = ((y + radius).min(h - 1) + 1 - y.saturating_sub(radius)) as u64;
+        let (mut sum, mut square) = (0u64, 0u64);
+        for x in 0..radius.min(w) {
+            sum += sums[x];
+            square += squares[x];
+        }
+        for x in 0..w {
+            if x + radius < w {
+                sum += sums[x + radius];
+                square += squares[x + radius];
+            }
+            if x > radius {
+                sum -= sums[x - radius - 1];
+                square -= squares[x - radius - 1];
+            }
+            let columns = ((x + radius).min(w - 1) + 1 - x.saturating_sub(radius)) as u64;
+            let n = (rows * columns) as f64;
+            let mean = sum as f64 / n;
+            let deviation = (square as f64 / n - mean * mean).max(0.0).sqrt();
+            let threshold = mean * (1.0 + K * (deviation / 128.0 - 1.0));
+            let value = if (samples[y * w + x] as f64) <= threshold {
+                0
+            } else {
+                255
+            };
+            output.put_pixel(x as u32, y as u32, image::Luma([value]));
+        }
+    }
+    output
+}
@@ -0,0 +1,811 @@
+use crate::error::PdfError;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, ObjectStream};
+use md5::{Digest, Md5};
+use sha2::{Sha256, Sha384, Sha512};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Cipher used by [`EncryptionOptions`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncryptionAlgorithm {
+    /// AES-128 (PDF 1.6, security handler revision 4).
+    Aes128,
+    /// AES-256 (PDF 2.0, security handler revision 6).
+    #[default]
+    Aes256,
+}
+
+/// What a reader may do with a document opened with the user password.
+/// The owner password always grants every permission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    /// Print, including high-quality printing.
+    pub print: bool,
+    /// Copy or extract text and graphics.
+    pub copy: bool,
+    /// Change the contents and insert, delete or rotate pages.
+    pub modify: bool,
+    /// Add or change annotations and fill in form fields.
+    pub annotate: bool,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions {
+            print: true,
+            copy: true,
+            modify: true,
+            annotate: true,
+        }
+    }
+}
+
+impl Permissions {
+    /// The `/P` value of the encryption dictionary.
+    fn flags(&self) -> i32 {
+        // Bits 7-8 and 13-32 are reserved and must be set; bit 10 (extraction for
+        // accessibility) is always granted.
+        let mut p: u32 = 0xFFFF_F0C0 | 1 << 9;
+        if self.print {
+            p |= 1 << 2 | 1 << 11;
+        }
+        if self.modify {
+            p |= 1 << 3 | 1 << 10;
+        }
+        if self.copy {
+            p |= 1 << 4;
+        }
+        if self.annotate {
+            p |= 1 << 5 | 1 << 8;
+        }
+        p as i32
+    }
+}
+
+/// Password protection applied to an output PDF (standard security handler).
+#[derive(Debug, Clone, Default)]
+pub struct EncryptionOptions {
+    /// Password needed to open the document. May be empty, in which case the document
+    /// opens without a password but the permissions still apply.
+    pub user_password: String,
+    /// Password that lifts the permission restrictions. When empty a random one is used,
+    /// so the restrictions cannot be lifted at all.
+    pub owner_password: String,
+    pub algorithm: EncryptionAlgorithm,
+    pub permissions: Permissions,
+}
+
+/// Loads a PDF, decrypting it if it is password protected.
+///
+/// Documents that only carry an owner password (an empty user password) open without one.
+/// Otherwise `password` is tried as the user and then as the owner password; a missing one
+/// yields [`PdfError::PasswordRequired`] and a wrong one [`PdfError::WrongPassword`].
+pub(crate) fn load_document<P: AsRef<Path>>(
+    path: P,
+    password: Option<&str>,
+) -> Result<Document, PdfError> {
+    // lopdf expands object streams while parsing, before anything could decrypt them, and
+    // drops the ones it cannot read. Keep them packed until the document is decrypted.
+    let mut doc = Document::load_filtered(path, defer_object_stream)?;
+    if doc.is_encrypted() {
+        decrypt_document(&mut doc, password.unwrap_or(""))?;
+    }
+    expand_object_streams(&mut doc);
+    Ok(doc)
+}
+
+/// `/Type` given to object streams while loading, so lopdf leaves them packed.
+const DEFERRED_OBJECT_STREAM: &[u8] = b"DeferredObjStm";
+
+fn defer_object_stream(id: ObjectId, object: &mut Object) -> Option<(ObjectId, Object)> {
+    if let Object::Stream(stream) = object {
+        if stream.dict.type_is(b"ObjStm") {
+            stream
+                .dict
+                .set("Type", Object::Name(DEFERRED_OBJECT_STREAM.to_vec()));
+        }
+    }
+    Some((id, object.clone()))
+}
+
+/// Adds the objects of every deferred object stream to `doc`, like lopdf does on load.
+/// Objects stored directly in the file take precedence over packed ones of the same number.
+fn expand_object_streams(doc: &mut Document) {
+    let mut packed = BTreeMap::new();
+    for object in doc.objects.values_mut() {
+        let Object::Stream(stream) = object else {
+            continue;
+        };
+        if !stream.dict.type_is(DEFERRED_OBJECT_STREAM) {
+            continue;
+        }
+        stream.dict.set("Type", Object::Name(b"ObjStm".to_vec()));
+        if let Ok(object_stream) = ObjectStream::new(stream) {
+            packed.extend(object_stream.objects);
+        }
+    }
+    for (id, object) in packed {
+        doc.objects.entry(id).or_insert(object);
+    }
+}
+
+/// Returns true if the PDF is encrypted and cannot be opened without a password.
+/// Documents protected only by an owner password return false.
+pub fn needs_password<P: AsRef<Path>>(input: P) -> Result<bool, PdfError> {
+    match load_document(input, None) {
+        Ok(_) => Ok(false),
+        Err(PdfError::PasswordRequired) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Padding string of the standard security handler (revisions 2-4).
+const PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08,
+    0x2E, 0x2E, 0x00, 0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+/// How strings or streams are encrypted, as selected by a crypt filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CryptMethod {
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+/// File key and crypt filters of an opened standard security handler.
+struct SecurityHandler {
+    key: Vec<u8>,
+    strings: CryptMethod,
+    streams: CryptMethod,
+    encrypt_metadata: bool,
+}
+
+impl SecurityHandler {
+    /// Key for one object: the file key itself for AES-256, otherwise derived from the
+    /// object number (Algorithm 1 of the PDF specification).
+    fn object_key(&self, id: ObjectId, method: CryptMethod) -> Vec<u8> {
+        if method == CryptMethod::Aes256 {
+            return self.key.clone();
+        }
+        let mut hasher = Md5::new();
+        hasher.update(&self.key);
+        hasher.update(&id.0.to_le_bytes()[..3]);
+        hasher.update(&id.1.to_le_bytes()[..2]);
+        if method == CryptMethod::Aes128 {
+            hasher.update(b"sAlT");
+        }
+        let len = (self.key.len() + 5).min(16);
+        hasher.finalize()[..len].to_vec()
+    }
+
+    fn decrypt(&self, id: ObjectId, method: CryptMethod, data: &[u8]) -> Vec<u8> {
+        match method {
+            CryptMethod::Identity => data.to_vec(),
+            CryptMethod::Rc4 => rc4(&self.object_key(id, method), data),
+            CryptMethod::Aes128 | CryptMethod::Aes256 => {
+                if data.len() < 16 || !data.len().is_multiple_of(16) {
+                    // Not AES output, leave it alone
+                    return data.to_vec();
+                }
+                let key = self.object_key(id, method);
+                let (iv, data) = data.split_at(16);
+                let mut plain = aes_cbc_decrypt(&key, iv, data);
+                let pad = plain.last().copied().unwrap_or(0) as usize;
+                if (1..=16).contains(&pad) && plain.len() >= pad {
+                    plain.truncate(plain.len() - pad);
+                }
+                plain
+            }
+        }
+    }
+
+    fn encrypt(&self, id: ObjectId, method: CryptMethod, data: &[u8]) -> Vec<u8> {
+        match method {
+            CryptMethod::Identity => data.to_vec(),
+            CryptMethod::Rc4 => rc4(&self.object_key(id, method), data),
+            CryptMethod::Aes128 | CryptMethod::Aes256 => {
+                let key = self.object_key(id, method);
+                let iv: [u8; 16] = rand::random();
+                let pad = 16 - data.len() % 16;
+                let mut padded = data.to_vec();
+                padded.resize(data.len() + pad, pad as u8);
+                let mut out = iv.to_vec();
+                out.extend(aes_cbc_encrypt(&key, &iv, &padded));
+                out
+            }
+        }
+    }
+
+    /// Applies `crypt` to every string and stream of the document, except those of the
+    /// encryption dictionary itself and, if requested, of the metadata stream.
+    fn apply(
+        &self,
+        doc: &mut Document,
+        skip: Option<ObjectId>,
+        crypt: fn(&Self, ObjectId, CryptMethod, &[u8]) -> Vec<u8>,
+    ) {
+        for (&id, object) in doc.objects.iter_mut() {
+            if Some(id) == skip {
+                continue;
+            }
+            if let Object::Stream(stream) = object {
+                let kind = stream.dict.get(b"Type").and_then(Object::as_name).ok();
+                let skip_content = kind == Some(b"XRef".as_slice())
+                    || (kind == Some(b"Metadata".as_slice()) && !self.encrypt_metadata);
+                if !skip_content {
+                    let content = crypt(self, id, self.streams, &stream.content);
+                    stream.set_content(content);
+                }
+            }
+            crypt_strings(object, &mut |data| crypt(self, id, self.strings, data));
+        }
+    }
+}
+
+fn crypt_strings(object: &mut Object, crypt: &mut dyn FnMut(&[u8]) -> Vec<u8>) {
+    match object {
+        Object::String(data, _) => *data = crypt(data),
+        Object::Array(items) => items.iter_mut().for_each(|o| crypt_strings(o, crypt)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, o)| crypt_strings(o, crypt)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, o)| crypt_strings(o, crypt)),
+        _ => {}
+    }
+}
+
+/// Opens the document's standard security handler with `password` and replaces every
+/// encrypted string and stream with its plain content.
+fn decrypt_document(doc: &mut Document, password: &str) -> Result<(), PdfError> {
+    let unsupported = || PdfError::Operation("Unsupported PDF encryption method".to_string());
+
+    let (encrypt_id, encrypt) = match doc.trailer.get(b"Encrypt")? {
+        Object::Reference(id) => (Some(*id), doc.get_dictionary(*id)?.clone()),
+        Object::Dictionary(dict) => (None, dict.clone()),
+        _ => return Err(unsupported()),
+    };
+    if encrypt.get(b"Filter").and_then(Object::as_name).ok() != Some(b"Standard".as_slice()) {
+        return Err(unsupported());
+    }
+    let file_id = first_file_id(doc);
+
+    let version = encrypt.get(b"V").and_then(Object::as_i64).unwrap_or(0);
+    let revision = encrypt.get(b"R").and_then(Object::as_i64).unwrap_or(0);
+    let owner_hash = encrypt.get(b"O").and_then(Object::as_str)?.to_vec();
+    let user_hash = encrypt.get(b"U").and_then(Object::as_str)?.to_vec();
+    let p = encrypt.get(b"P").and_then(Object::as_i64).unwrap_or(0) as i32;
+    let encrypt_metadata = encrypt
+        .get(b"EncryptMetadata")
+        .and_then(Object::as_bool)
+        .unwrap_or(true);
+
+    let (strings, streams) = match version {
+        1 | 2 => (CryptMethod::Rc4, CryptMethod::Rc4),
+        4 | 5 => (
+            crypt_filter(&encrypt, b"StrF").ok_or_else(unsupported)?,
+            crypt_filter(&encrypt, b"StmF").ok_or_else(unsupported)?,
+        ),
+        _ => return Err(unsupported()),
+    };
+    // The key length follows the revision: only revisions 5 and 6 derive a 256-bit key
+    let key_len = match (version, revision) {
+        (1 | 2, 2..=4) => {
+            let bits = encrypt
+                .get(b"Length")
+                .and_then(Object::as_i64)
+                .unwrap_or(40);
+            (bits / 8).clamp(5, 16) as usize
+        }
+        (4, 4) => 16,
+        (5, 5 | 6) => 32,
+        _ => {
+            return Err(PdfError::Operation(format!(
+                "Invalid PDF encryption: /V {} does not match /R {}",
+                version, revision
+            )))
+        }
+    };
+
+    let key = match revision {
+        2..=4 if owner_hash.len() >= 32 && user_hash.len() >= 32 => {
+            let params = LegacyParams {
+                revision,
+                key_len,
+                owner_hash: &owner_hash[..32],
+                p,
+                file_id: &file_id,
+                encrypt_metadata,
+            };
+            let password = legacy_password(password);
+            params
+                .authenticate_user(&password, &user_hash)
+                .or_else(|| params.authenticate_owner(&password, &user_hash))
+        }
+        5 | 6 if owner_hash.len() >= 48 && user_hash.len() >= 48 => {
+            let owner_key = encrypt.get(b"OE").and_then(Object::as_str)?;
+            let user_key = encrypt.get(b"UE").and_then(Object::as_str)?;
+            if owner_key.len() != 32 || user_key.len() != 32 {
+                return Err(PdfError::Operation(
+                    "Invalid PDF encryption: /OE and /UE must be 32 bytes".to_string(),
+                ));
+            }
+            let password = utf8_password(password);
+            let user = &user_hash[..48];
+            let owner = &owner_hash[..48];
+            if hash_r6(revision, &password, &user[32..40], &[]) == user[..32] {
+                let key = hash_r6(revision, &password, &user[40..48], &[]);
+                Some(aes_cbc_decrypt(&key, &[0; 16], user_key))
+            } else if hash_r6(revision, &password, &owner[32..40], user) == owner[..32] {
+                let key = hash_r6(revision, &password, &owner[40..48], user);
+                Some(aes_cbc_decrypt(&key, &[0; 16], owner_key))
+            } else {
+                None
+            }
+        }
+        _ => return Err(unsupported()),
+    };
+    let Some(key) = key else {
+        return Err(if password.is_empty() {
+            PdfError::PasswordRequired
+        } else {
+            PdfError::WrongPassword
+        });
+    };
+
+    let handler = SecurityHandler {
+        key,
+        strings,
+        streams,
+        encrypt_metadata,
+    };
+    handler.apply(doc, encrypt_id, SecurityHandler::decrypt);
+
+    doc.trailer.remove(b"Encrypt");
+    if let Some(id) = encrypt_id {
+        doc.objects.remove(&id);
+    }
+    Ok(())
+}
+
+/// Encrypts every string and stream of `doc` and adds the matching encryption dictionary,
+/// so the document is written protected by the next save.
+pub(crate) fn encrypt_document(
+    doc: &mut Document,
+    options: &EncryptionOptions,
+) -> Result<(), PdfError> {
+    if doc.is_encrypted() {
+        return Err(PdfError::Operation("PDF is already encrypted".to_string()));
+    }
+
+    let file_id = match doc.trailer.get(b"ID").and_then(Object::as_array) {
+        Ok(ids) if !ids.is_empty() => first_file_id(doc),
+        _ => {
+            let id: [u8; 16] = rand::random();
+            let id = Object::String(id.to_vec(), lopdf::StringFormat::Hexadecimal);
+            doc.trailer.set("ID", vec![id.clone(), id]);
+            first_file_id(doc)
+        }
+    };
+
+    let owner_password = if options.owner_password.is_empty() {
+        let random: [u8; 16] = rand::random();
+        random.iter().map(|b| format!("{:02x}", b)).collect()
+    } else {
+        options.owner_password.clone()
+    };
+    let p = options.permissions.flags();
+
+    let (key, encrypt) = match options.algorithm {
+        EncryptionAlgorithm::Aes128 => {
+            let user = legacy_password(&options.user_password);
+            let owner = legacy_password(&owner_password);
+            let owner_hash = legacy_owner_hash(&owner, &user, 4, 16);
+            let params = LegacyParams {
+                revision: 4,
+                key_len: 16,
+                owner_hash: &owner_hash,
+                p,
+                file_id: &file_id,
+                encrypt_metadata: true,
+            };
+            let key = params.file_key(&user);
+            let user_hash = params.user_hash(&key);
+            let encrypt = dictionary! {
+                "Filter" => "Standard",
+                "V" => 4,
+                "R" => 4,
+                "Length" => 128,
+                "CF" => dictionary! {
+                    "StdCF" => dictionary! {
+                        "CFM" => "AESV2",
+                        "AuthEvent" => "DocOpen",
+                        "Length" => 16,
+                    },
+                },
+                "StmF" => "StdCF",
+                "StrF" => "StdCF",
+                "O" => Object::String(owner_hash, lopdf::StringFormat::Hexadecimal),
+                "U" => Object::String(user_hash, lopdf::StringFormat::Hexadecimal),
+                "P" => p,
+            };
+            (key, encrypt)
+        }
+        EncryptionAlgorithm::Aes256 => {
+            let user = utf8_password(&options.user_password);
+            let owner = utf8_password(&owner_password);
+            let key: [u8; 32] = rand::random();
+
+            let salts: [u8; 16] = rand::random();
+            let mut user_hash = hash_r6(6, &user, &salts[..8], &[]).to_vec();
+            user_hash.extend_from_slice(&salts);
+            let user_key = hash_r6(6, &user, &salts[8..], &[]);
+            let user_key = aes_cbc_encrypt(&user_key, &[0; 16], &key);
+
+            let salts: [u8; 16] = rand::random();
+            let mut owner_hash = hash_r6(6, &owner, &salts[..8], &user_hash).to_vec();
+            owner_hash.extend_from_slice(&salts);
+            let owner_key = hash_r6(6, &owner, &salts[8..], &user_hash);
+            let owner_key = aes_cbc_encrypt(&owner_key, &[0; 16], &key);
+
+            let mut perms = [0u8; 16];
+            perms[..4].copy_from_slice(&p.to_le_bytes());
+            perms[4..8].copy_from_slice(&[0xFF; 4]);
+            perms[8..12].copy_from_slice(b"Tadb");
+            perms[12..].copy_from_slice(&rand::random::<[u8; 4]>());
+            let perms = aes_cbc_encrypt(&key, &[0; 16], &perms);
+
+            let encrypt = dictionary! {
+                "Filter" => "Standard",
+                "V" => 5,
+                "R" => 6,
+                "Length" => 256,
+                "CF" => dictionary! {
+                    "StdCF" => dictionary! {
+                        "CFM" => "AESV3",
+                        "AuthEvent" => "DocOpen",
+                        "Length" => 32,
+                    },
+                },
+                "StmF" => "StdCF",
+                "StrF" => "StdCF",
+                "O" => Object::String(owner_hash, lopdf::StringFormat::Hexadecimal),
+                "U" => Object::String(user_hash, lopdf::StringFormat::Hexadecimal),
+                "OE" => Object::String(owner_key, lopdf::StringFormat::Hexadecimal),
+                "UE" => Object::String(user_key, lopdf::StringFormat::Hexadecimal),
+                "Perms" => Object::String(perms, lopdf::StringFormat::Hexadecimal),
+                "P" => p,
+            };
+            (key.to_vec(), encrypt)
+        }
+    };
+
+    // AES-128 needs PDF 1.6, AES-256 PDF 2.0 (or 1.7 with Adobe extension level 8)
+    let min_version = match options.algorithm {
+        EncryptionAlgorithm::Aes128 => (1, 6),
+        EncryptionAlgorithm::Aes256 => (1, 7),
+    };
+    let version = parse_version(&doc.version);
+    if version < min_version {
+        doc.version = format!("{}.{}", min_version.0, min_version.1);
+    }
+    if options.algorithm == EncryptionAlgorithm::Aes256 && version < (2, 0) {
+        add_adobe_extension_level_8(doc)?;
+    }
+
+    let method = match options.algorithm {
+        EncryptionAlgorithm::Aes128 => CryptMethod::Aes128,
+        EncryptionAlgorithm::Aes256 => CryptMethod::Aes256,
+    };
+    let handler = SecurityHandler {
+        key,
+        strings: method,
+        streams: method,
+        encrypt_metadata: true,
+    };
+    handler.apply(doc, None, SecurityHandler::encrypt);
+
+    let encrypt_id = doc.add_object(encrypt);
+    doc.trailer.set("Encrypt", encrypt_id);
+    Ok(())
+}
+
+/// Parses a `major.minor` PDF version such as "1.7"; unreadable parts count as 0.
+fn parse_version(version: &str) -> (u32, u32) {
+    let (major, minor) = version.trim().split_once('.').unwrap_or((version, ""));
+    (major.parse().unwrap_or(0), minor.parse().unwrap_or(0))
+}
+
+/// Declares Adobe extension level 8 (AES-256) in the catalog's `/Extensions`, keeping the
+/// entries of other developers and any `ADBE` entry of a level at least as high.
+fn add_adobe_extension_level_8(doc: &mut Document) -> Result<(), PdfError> {
+    let mut extensions = match doc.catalog()?.get(b"Extensions") {
+        Ok(Object::Dictionary(dict)) => dict.clone(),
+        Ok(Object::Reference(id)) => doc.get_dictionary(*id).cloned().unwrap_or_default(),
+        _ => Dictionary::new(),
+    };
+    let level = |object: &Object| {
+        let dict = match object {
+            Object::Reference(id) => doc.get_dictionary(*id).ok(),
+            object => object.as_dict().ok(),
+        };
+        dict.and_then(|dict| dict.get(b"ExtensionLevel").ok())
+            .and_then(|level| level.as_i64().ok())
+            .unwrap_or(0)
+    };
+    // Since PDF 2.0 a developer prefix may hold an array of extension dictionaries
+    let existing = match extensions.get(b"ADBE") {
+        Ok(Object::Array(entries)) => entries.iter().map(level).max().unwrap_or(0),
+        Ok(entry) => level(entry),
+        Err(_) => 0,
+    };
+    if existing < 8 {
+        extensions.set(
+            "ADBE",
+            dictionary! {
+                "BaseVersion" => Object::Name(b"1.7".to_vec()),
+                "ExtensionLevel" => 8,
+            },
+        );
+    }
+    doc.catalog_mut()?.set("Extensions", extensions);
+    Ok(())
+}
+
+/// Crypt method of the crypt filter named by `entry` (`StrF` or `StmF`).
+fn crypt_filter(encrypt: &Dictionary, entry: &[u8]) -> Option<CryptMethod> {
+    let name = match encrypt.get(entry) {
+        Ok(Object::Name(name)) => name.as_slice(),
+        _ => b"Identity",
+    };
+    if name == b"Identity" {
+        return Some(CryptMethod::Identity);
+    }
+    let filter = encrypt
+        .get(b"CF")
+        .and_then(Object::as_dict)
+        .and_then(|cf| cf.get(name))
+        .and_then(Object::as_dict)
+        .ok()?;
+    match filter.get(b"CFM").and_then(Object::as_name).ok()? {
+        b"None" => Some(CryptMethod::Identity),
+        b"V2" => Some(CryptMethod::Rc4),
+        b"AESV2" => Some(CryptMethod::Aes128),
+        b"AESV3" => Some(CryptMethod::Aes256),
+        _ => None,
+    }
+}
+
+fn first_file_id(doc: &Document) -> Vec<u8> {
+    doc.trailer
+        .get(b"ID")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|ids| ids.first())
+        .and_then(|id| id.as_str().ok())
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default()
+}
+
+/// Revision 2-4 passwords are PDFDocEncoding, padded or truncated to 32 bytes.
+fn legacy_password(password: &str) -> [u8; 32] {
+    let bytes: Vec<u8> = password
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect();
+    let mut padded = PASSWORD_PADDING;
+    let len = bytes.len().min(32);
+    padded[..len].copy_from_slice(&bytes[..len]);
+    padded[len..].copy_from_slice(&PASSWORD_PADDING[..32 - len]);
+    padded
+}
+
+/// Revision 5-6 passwords are UTF-8, truncated to 127 bytes.
+fn utf8_password(password: &str) -> Vec<u8> {
+    let mut end = password.len().min(127);
+    while !password.is_char_boundary(end) {
+        end -= 1;
+    }
+    password.as_bytes()[..end].to_vec()
+}
+
+/// Parameters of a revision 2-4 standard security handler.
+struct LegacyParams<'a> {
+    revision: i64,
+    key_len: usize,
+    owner_hash: &'a [u8],
+    p: i32,
+    file_id: &'a [u8],
+    encrypt_metadata: bool,
+}
+
+impl LegacyParams<'_> {
+    /// Algorithm 2: the file key for a padded user password.
+    fn file_key(&self, password: &[u8; 32]) -> Vec<u8> {
+        let mut hasher = Md5::new();
+        hasher.update(password);
+        hasher.update(self.owner_hash);
+        hasher.update(self.p.to_le_bytes());
+        hasher.update(self.file_id);
+        if self.revision >= 4 && !self.encrypt_metadata {
+            hasher.update([0xFF; 4]);
+        }
+        let mut hash = hasher.finalize();
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                hash = Md5::digest(&hash[..self.key_len]);
+            }
+        }
+        hash[..self.key_len].to_vec()
+    }
+
+    /// Algorithms 4 and 5: the `/U` value for a file key.
+    fn user_hash(&self, key: &[u8]) -> Vec<u8> {
+        if self.revision == 2 {
+            return rc4(key, &PASSWORD_PADDING);
+        }
+        let mut hasher = Md5::new();
+        hasher.update(PASSWORD_PADDING);
+        hasher.update(self.file_id);
+        let mut hash = hasher.finalize().to_vec();
+        for i in 0..20u8 {
+            let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+            hash = rc4(&round_key, &hash);
+        }
+        hash.extend_from_slice(&PASSWORD_PADDING[..16]);
+        hash
+    }
+
+    /// Algorithm 6: the file key if `password` is the user password.
+    fn authenticate_user(&self, password: &[u8; 32], user_hash: &[u8]) -> Option<Vec<u8>> {
+        let key = self.file_key(password);
+        // Revision 3+ only defines the first 16 bytes of /U
+        let len = if self.revision == 2 { 32 } else { 16 };
+        (self.user_hash(&key)[..len] == user_hash[..len]).then_some(key)
+    }
+
+    /// Algorithm 7: the file key if `password` is the owner password.
+    fn authenticate_owner(&self, password: &[u8; 32], user_hash: &[u8]) -> Option<Vec<u8>> {
+        let key = legacy_owner_key(password, self.revision, self.key_len);
+        let mut user_password = self.owner_hash.to_vec();
+        if self.revision == 2 {
+            user_password = rc4(&key, &user_password);
+        } else {
+            for i in (0..20u8).rev() {
+                let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+                user_password = rc4(&round_key, &user_password);
+            }
+        }
+        let user_password: [u8; 32] = user_password.try_into().ok()?;
+        self.authenticate_user(&user_password, user_hash)
+    }
+}
+
+/// Algorithm 3, steps a-d: the RC4 key derived from the padded owner password.
+fn legacy_owner_key(owner: &[u8; 32], revision: i64, key_len: usize) -> Vec<u8> {
+    let mut hash = Md5::digest(owner);
+    if revision >= 3 {
+        for _ in 0..50 {
+            hash = Md5::digest(hash);
+        }
+    }
+    hash[..key_len].to_vec()
+}
+
+/// Algorithm 3: the `/O` value.
+fn legacy_owner_hash(owner: &[u8; 32], user: &[u8; 32], revision: i64, key_len: usize) -> Vec<u8> {
+    let key = legacy_owner_key(owner, revision, key_len);
+    let mut hash = rc4(&key, user);
+    if revision >= 3 {
+        for i in 1..20u8 {
+            let round_key: Vec<u8> = key.iter().map(|b| b ^ i).collect();
+            hash = rc4(&round_key, &hash);
+        }
+    }
+    hash
+}
+
+/// Algorithm 2.B (revision 6), or plain SHA-256 for the deprecated revision 5.
+fn hash_r6(revision: i64, password: &[u8], salt: &[u8], user_data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(password);
+    hasher.update(salt);
+    hasher.update(user_data);
+    let mut k = hasher.finalize().to_vec();
+    if revision == 5 {
+        return k[..32].try_into().unwrap();
+    }
+
+    let mut round = 0u32;
+    loop {
+        let mut k1 = Vec::with_capacity(64 * (password.len() + k.len() + user_data.len()));
+        for _ in 0..64 {
+            k1.extend_from_slice(password);
+            k1.extend_from_slice(&k);
+            k1.extend_from_slice(user_data);
+        }
+        let e = aes_cbc_encrypt(&k[..16], &k[16..32], &k1);
+        let remainder = e[..16].iter().map(|&b| b as u32).sum::<u32>() % 3;
+        k = match remainder {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+        round += 1;
+        if round >= 64 && u32::from(*e.last().unwrap()) <= round - 32 {
+            break;
+        }
+    }
+    k[..32].try_into().unwrap()
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut s: Vec<u8> = (0..=255).collect();
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+        s.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|&byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(s[i as usize]);
+            s.swap(i as usize, j as usize);
+            byte ^ s[s[i as usize].wrapping_add(s[j as usize]) as usize]
+        })
+        .collect()
+}
+
+/// AES-CBC without padding; `data` must be a multiple of the block size.
+/// The key length (16 or 32 bytes) selects AES-128 or AES-256.
+fn aes_cbc_encrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    fn run<C: BlockEncrypt>(cipher: C, iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut previous = GenericArray::clone_from_slice(iv);
+        let mut out = Vec::with_capacity(data.len());
+        for chunk in data.chunks_exact(16) {
+            let mut block = GenericArray::clone_from_slice(chunk);
+            block.iter_mut().zip(&previous).for_each(|(b, p)| *b ^= p);
+            cipher.encrypt_block(&mut block);
+            out.extend_from_slice(&block);
+            previous = block;
+        }
+        out
+    }
+    match key.len() {
+        32 => run(aes::Aes256::new(GenericArray::from_slice(key)), iv, data),
+        _ => run(
+            aes::Aes128::new(GenericArray::from_slice(&key[..16])),
+            iv,
+            data,
+        ),
+    }
+}
+
+/// Inverse of [`aes_cbc_encrypt`].
+fn aes_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    fn run<C: BlockDecrypt>(cipher: C, iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut previous = iv;
+        let mut out = Vec::with_capacity(data.len());
+        for chunk in data.chunks_exact(16) {
+            let mut block = GenericArray::clone_from_slice(chunk);
+            cipher.decrypt_block(&mut block);
+            block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+            out.extend_from_slice(&block);
+            previous = chunk;
+        }
+        out
+    }
+    match key.len() {
+        32 => run(aes::Aes256::new(GenericArray::from_slice(key)), iv, data),
+        _ => run(
+            aes::Aes128::new(GenericArray::from_slice(&key[..16])),
+            iv,
+            data,
+        ),
+    }
+}
@@ -0,0 +1,349 @@
+use crate::layout::{build_lines, layout_paragraphs, Line, Paragraph};
+use crate::text::{Ruling, TextRun};
+
+/// A table found on a page. Boundaries are in default user space.
+pub(crate) struct Table {
+    /// Column boundaries, left to right; one more than the number of grid columns.
+    pub columns: Vec<f32>,
+    /// Row boundaries, top to bottom; one more than the number of rows.
+    pub rows: Vec<f32>,
+    /// The cells of each row, left to right.
+    pub cells: Vec<Vec<Cell>>,
+    /// Whether the table is drawn with ruling lines, rather than only aligned in columns.
+    pub ruled: bool,
+}
+
+pub(crate) struct Cell {
+    pub paragraphs: Vec<Paragraph>,
+    /// Number of grid columns the cell spans.
+    pub span: usize,
+}
+
+impl Table {
+    pub fn top(&self) -> f32 {
+        self.rows[0]
+    }
+}
+
+/// Rulings closer than this are treated as one line, and lines that miss each other by less
+/// still intersect.
+const TOLERANCE: f32 = 2.0;
+
+/// Finds the tables on a page and returns them, top first, with the runs that are not part
+/// of any table.
+///
+/// Tables drawn with ruling lines are found first: horizontal and vertical rulings that
+/// intersect form a grid, whose cells take the text inside them. Cells with no vertical
+/// ruling between them are merged. In the remaining text, consecutive lines that split into
+/// the same three or more short columns are taken as a table without rulings.
+pub(crate) fn extract_tables(runs: Vec<TextRun>, rulings: &[Ruling]) -> (Vec<Table>, Vec<TextRun>) {
+    let mut tables = Vec::new();
+    let mut runs = runs;
+    for grid in ruled_grids(rulings) {
+        let (inside, outside): (Vec<TextRun>, Vec<TextRun>) = runs
+            .into_iter()
+            .partition(|run| grid.cell_at(run).is_some());
+        runs = outside;
+        tables.push(grid.into_table(inside));
+    }
+
+    let (aligned, runs) = aligned_tables(runs);
+    tables.extend(aligned);
+    tables.sort_by(|a, b| b.top().total_cmp(&a.top()));
+    (tables, runs)
+}
+
+/// A grid of ruling lines, with the vertical rulings that separate its cells.
+struct Grid {
+    columns: Vec<f32>,
+    rows: Vec<f32>,
+    verticals: Vec<Ruling>,
+}
+
+impl Grid {
+    /// Row and grid column of the run's center, if it lies inside the grid.
+    fn cell_at(&self, run: &TextRun) -> Option<(usize, usize)> {
+        let x = run.x + run.width / 2.0;
+        let y = run.y + run.size * 0.3;
+        let row = self.rows.windows(2).position(|r| r[0] >= y && y > r[1])?;
+        let column = self
+            .columns
+            .windows(2)
+            .position(|c| c[0] <= x && x < c[1])?;
+        Some((row, column))
+    }
+
+    /// Whether a vertical ruling separates grid columns `column - 1` and `column` in `row`.
+    fn separated(&self, row: usize, column: usize) -> bool {
+        let x = self.columns[column];
+        let y = (self.rows[row] + self.rows[row + 1]) / 2.0;
+        self.verticals.iter().any(|v| {
+            (v.position - x).abs() <= TOLERANCE
+                && v.start - TOLERANCE <= y
+                && y <= v.end + TOLERANCE
+        })
+    }
+
+    fn into_table(self, runs: Vec<TextRun>) -> Table {
+        let row_count = self.rows.len() - 1;
+        let column_count = self.columns.len() - 1;
+        // The first grid column of each cell, per row and grid column
+        let starts: Vec<Vec<usize>> = (0..row_count)
+            .map(|row| {
+                let mut start = 0;
+                (0..column_count)
+                    .map(|column| {
+                        if column > 0 && self.separated(row, column) {
+                            start = column;
+                        }
+                        start
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut cell_runs: Vec<Vec<Vec<TextRun>>> = vec![vec![Vec::new(); column_count]; row_count];
+        for run in runs {
+            if let Some((row, column)) = self.cell_at(&run) {
+                cell_runs[row][starts[row][column]].push(run);
+            }
+        }
+
+        let cells = cell_runs
+            .into_iter()
+            .zip(&starts)
+            .map(|(row, starts)| {
+                row.into_iter()
+                    .enumerate()
+                    .filter(|(column, _)| starts[*column] == *column)
+                    .map(|(column, runs)| Cell {
+                        paragraphs: layout_paragraphs(runs),
+                        span: starts.iter().filter(|&&s| s == column).count(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Table {
+            columns: self.columns,
+            rows: self.rows,
+            cells,
+            ruled: true,
+        }
+    }
+}
+
+/// Grids of at least two cells formed by intersecting rulings.
+fn ruled_grids(rulings: &[Ruling]) -> Vec<Grid> {
+    // Edges of thin filled rectangles and hairline gaps leave short pieces; drop them
+    let rulings: Vec<Ruling> = merge_rulings(rulings)
+        .into_iter()
+        .filter(|r| r.end - r.start > TOLERANCE * 2.0)
+        .collect();
+
+    // Union-find over rulings that intersect
+    let mut parent: Vec<usize> = (0..rulings.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for (i, a) in rulings.iter().enumerate() {
+        for (j, b) in rulings.iter().enumerate().skip(i + 1) {
+            if a.horizontal != b.horizontal && intersect(a, b) {
+                let (ra, rb) = (root(&mut parent, i), root(&mut parent, j));
+                parent[ra] = rb;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<Ruling>> = Vec::new();
+    let mut group_of: Vec<Option<usize>> = vec![None; rulings.len()];
+    for (i, ruling) in rulings.iter().enumerate() {
+        let r = root(&mut parent, i);
+        let group = *group_of[r].get_or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(*ruling);
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|group| {
+            let (horizontals, verticals): (Vec<Ruling>, Vec<Ruling>) =
+                group.into_iter().partition(|r| r.horizontal);
+            let columns = distinct(verticals.iter().map(|r| r.position).collect());
+            let mut rows = distinct(horizontals.iter().map(|r| r.position).collect());
+            rows.reverse();
+            let cells = (columns.len().saturating_sub(1)) * (rows.len().saturating_sub(1));
+            if cells < 2 {
+                return None;
+            }
+            Some(Grid {
+                columns,
+                rows,
+                verticals,
+            })
+        })
+        .collect()
+}
+
+fn intersect(a: &Ruling, b: &Ruling) -> bool {
+    a.start - TOLERANCE <= b.position
+        && b.position <= a.end + TOLERANCE
+        && b.start - TOLERANCE <= a.position
+        && a.position <= b.end + TOLERANCE
+}
+
+/// Joins collinear rulings that overlap or touch, e.g. cell borders drawn one cell at a time.
+fn merge_rulings(rulings: &[Ruling]) -> Vec<Ruling> {
+    let mut sorted = rulings.to_vec();
+    sorted.sort_by(|a, b| {
+        a.horizontal
+            .cmp(&b.horizontal)
+            .then(a.position.total_cmp(&b.position))
+            .then(a.start.total_cmp(&b.start))
+    });
+    let mut merged: Vec<Ruling> = Vec::new();
+    for ruling in sorted {
+        let joined = merged.iter_mut().rev().find(|m| {
+            m.horizontal == ruling.horizontal
+                && (m.position - ruling.position).abs() <= TOLERANCE
+                && ruling.start <= m.end + TOLERANCE
+                && m.start <= ruling.end + TOLERANCE
+        });
+        match joined {
+            Some(m) => {
+                m.start = m.start.min(ruling.start);
+                m.end = m.end.max(ruling.end);
+            }
+            None => merged.push(ruling),
+        }
+    }
+    merged
+}
+
+/// The values sorted ascending, with values within [`TOLERANCE`] of each other merged.
+fn distinct(mut values: Vec<f32>) -> Vec<f32> {
+    values.sort_by(f32::total_cmp);
+    values.dedup_by(|next, kept| *next - *kept <= TOLERANCE);
+    values
+}
+
+/// Minimum number of rows and columns of a table without rulings.
+const MIN_ALIGNED_ROWS: usize = 3;
+const MIN_ALIGNED_COLUMNS: usize = 3;
+
+/// Cells of tables have a few words each; columns of running text have more.
+const MAX_WORDS_PER_CELL: f32 = 4.0;
+
+/// Finds tables without rulings, see [`extract_tables`].
+fn aligned_tables(runs: Vec<TextRun>) -> (Vec<Table>, Vec<TextRun>) {
+    // Pieces of lines on the same baseline form a row
+    let mut rows: Vec<Vec<Line>> = Vec::new();
+    for line in build_lines(runs) {
+        match rows.last_mut() {
+            Some(row) if (row[0].y - line.y).abs() <= row[0].size.max(line.size) * 0.5 => {
+                row.push(line)
+            }
+            _ => rows.push(vec![line]),
+        }
+    }
+
+    let mut tables = Vec::new();
+    let mut rest: Vec<TextRun> = Vec::new();
+    let mut start = 0;
+    while start < rows.len() {
+        // The longest stretch of closely spaced rows that are split into pieces
+        let mut end = start;
+        while end < rows.len() && rows[end].len() >= 2 {
+            if end > start {
+                let (prev, row) = (&rows[end - 1][0], &rows[end][0]);
+                if prev.y - row.y > prev.size.max(row.size) * 2.5 {
+                    break;
+                }
+            }
+            end += 1;
+        }
+
+        match aligned_table(&rows[start..end]) {
+            Some(table) => {
+                tables.push(table);
+                start = end;
+            }
+            None => {
+                rest.extend(rows[start].drain(..).flat_map(|line| line.runs));
+                start += 1;
+            }
+        }
+    }
+    (tables, rest)
+}
+
+/// The rows as a table, if their pieces line up in enough columns.
+fn aligned_table(rows: &[Vec<Line>]) -> Option<Table> {
+    if rows.len() < MIN_ALIGNED_ROWS {
+        return None;
+    }
+    let lines = || rows.iter().flatten();
+    let words: usize = lines()
+        .map(|line| line.text().split_whitespace().count())
+        .sum();
+    if words as f32 > lines().count() as f32 * MAX_WORDS_PER_CELL {
+        return None;
+    }
+
+    // Gutters are the gaps no line crosses
+    let mut spans: Vec<(f32, f32)> = lines().map(|l| (l.left, l.right)).collect();
+    spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut columns = vec![spans[0].0];
+    let mut covered_to = spans[0].1;
+    for &(start, end) in &spans[1..] {
+        if start > covered_to {
+            columns.push((covered_to + start) / 2.0);
+        }
+        covered_to = covered_to.max(end);
+    }
+    columns.push(covered_to);
+    if columns.len() - 1 < MIN_ALIGNED_COLUMNS {
+        return None;
+    }
+
+    let baselines: Vec<(f32, f32)> = rows.iter().map(|row| (row[0].y, row[0].size)).collect();
+    let mut boundaries = vec![baselines[0].0 + baselines[0].1];
+    for pair in baselines.windows(2) {
+        boundaries.push((pair[0].0 + pair[1].0 + pair[1].1 * 0.5) / 2.0);
+    }
+    let (last_y, last_size) = baselines[baselines.len() - 1];
+    boundaries.push(last_y - last_size * 0.4);
+
+    let cells = rows
+        .iter()
+        .map(|row| {
+            let mut cells: Vec<Vec<TextRun>> = vec![Vec::new(); columns.len() - 1];
+            for line in row {
+                let center = (line.left + line.right) / 2.0;
+                let column = columns
+                    .windows(2)
+                    .position(|c| center < c[1])
+                    .unwrap_or(cells.len() - 1);
+                cells[column].extend(line.runs.iter().cloned());
+            }
+            cells
+                .into_iter()
+                .map(|runs| Cell {
+                    paragraphs: layout_paragraphs(runs),
+                    span: 1,
+                })
+                .collect()
+        })
+        .collect();
+    Some(Table {
+        columns,
+        rows: boundaries,
+        cells,
+        ruled: false,
+    })
+}
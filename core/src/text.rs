@@ -0,0 +1,463 @@
+use crate::font::{Font, FontCache, FontStyle};
+use crate::xobject;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::rc::Rc;
+
+/// A piece of text shown by one string operand, positioned in default user space
+/// (origin at the bottom left of the page, y pointing up).
+#[derive(Debug, Clone)]
+pub(crate) struct TextRun {
+    pub text: String,
+    /// Start of the run on the baseline.
+    pub x: f32,
+    pub y: f32,
+    /// Advance of the run along the baseline.
+    pub width: f32,
+    /// Font size in user space, including any scaling by the text and transformation matrices.
+    pub size: f32,
+    pub style: Rc<FontStyle>,
+    /// Fill color as RGB.
+    pub color: [u8; 3],
+}
+
+/// An image XObject drawn on the page, with its bounding box in default user space.
+#[derive(Debug, Clone)]
+pub(crate) struct PageImage {
+    pub id: ObjectId,
+    /// Bottom left corner.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A horizontal or vertical line segment stroked or filled on the page, e.g. a table border.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ruling {
+    pub horizontal: bool,
+    /// y of a horizontal ruling, x of a vertical one.
+    pub position: f32,
+    /// Extent along the ruling, `start <= end`.
+    pub start: f32,
+    pub end: f32,
+}
+
+/// What a page's content stream draws, as far as document conversion cares.
+#[derive(Debug, Default)]
+pub(crate) struct PageContent {
+    /// Text runs in content stream order.
+    pub runs: Vec<TextRun>,
+    pub images: Vec<PageImage>,
+    pub rulings: Vec<Ruling>,
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `a` then `b`, i.e. the matrix product `a × b`.
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+fn transform(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (x * m[0] + y * m[2] + m[4], x * m[1] + y * m[3] + m[5])
+}
+
+fn translate(tx: f32, ty: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Option<Rc<Font>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    /// Horizontal scaling (`Tz`) as a factor.
+    scaling: f32,
+    leading: f32,
+    rise: f32,
+    fill: [u8; 3],
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            ctm: IDENTITY,
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scaling: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+            fill: [0, 0, 0],
+        }
+    }
+}
+
+/// Form XObjects nested deeper than this are not followed.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Interprets page content streams and collects the text they show.
+pub(crate) struct ContentWalker<'a> {
+    doc: &'a Document,
+    fonts: &'a mut FontCache,
+    content: PageContent,
+}
+
+impl<'a> ContentWalker<'a> {
+    pub fn new(doc: &'a Document, fonts: &'a mut FontCache) -> Self {
+        ContentWalker {
+            doc,
+            fonts,
+            content: PageContent::default(),
+        }
+    }
+
+    /// The text runs of a page, in content stream order. Text inside form XObjects is included.
+    pub fn page_runs(self, page_id: ObjectId) -> Vec<TextRun> {
+        self.page_content(page_id).runs
+    }
+
+    /// The text, images and ruling lines of a page.
+    pub fn page_content(mut self, page_id: ObjectId) -> PageContent {
+        let content = self.doc.get_page_content(page_id).unwrap_or_default();
+        let resources = page_resources(self.doc, page_id);
+        self.walk(&content, resources, GraphicsState::default(), 0);
+        self.content
+    }
+
+    fn walk(
+        &mut self,
+        content: &[u8],
+        resources: Option<&'a Dictionary>,
+        mut state: GraphicsState,
+        depth: usize,
+    ) {
+        let Ok(content) = Content::decode(content) else {
+            return;
+        };
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut tm = IDENTITY;
+        let mut tlm = IDENTITY;
+        // Segments of the path under construction, in user space
+        let mut path: Vec<((f32, f32), (f32, f32))> = Vec::new();
+        let mut point = (0.0, 0.0);
+        let mut subpath_start = (0.0, 0.0);
+
+        for op in &content.operations {
+            let operands = &op.operands;
+            let num = |i: usize| operands.get(i).and_then(number).unwrap_or(0.0);
+            match op.operator.as_str() {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(saved) = stack.pop() {
+                        state = saved;
+                    }
+                }
+                "cm" if operands.len() == 6 => {
+                    let m = [num(0), num(1), num(2), num(3), num(4), num(5)];
+                    state.ctm = multiply(&m, &state.ctm);
+                }
+                "g" | "rg" | "k" | "sc" | "scn" => {
+                    let components: Vec<f32> = operands.iter().filter_map(number).collect();
+                    if let Some(fill) = rgb(&components) {
+                        state.fill = fill;
+                    }
+                }
+                // Setting a color space resets the color to its initial value, black for the
+                // device spaces
+                "cs" => state.fill = [0, 0, 0],
+                "m" => {
+                    point = transform(&state.ctm, num(0), num(1));
+                    subpath_start = point;
+                }
+                "l" => {
+                    let to = transform(&state.ctm, num(0), num(1));
+                    path.push((point, to));
+                    point = to;
+                }
+                // Curves are never rulings; only the current point matters
+                "c" => point = transform(&state.ctm, num(4), num(5)),
+                "v" | "y" => point = transform(&state.ctm, num(2), num(3)),
+                "h" => {
+                    path.push((point, subpath_start));
+                    point = subpath_start;
+                }
+                "re" => {
+                    let (x, y, w, h) = (num(0), num(1), num(2), num(3));
+                    let corners = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)]
+                        .map(|(x, y)| transform(&state.ctm, x, y));
+                    for i in 0..4 {
+                        path.push((corners[i], corners[(i + 1) % 4]));
+                    }
+                    point = corners[0];
+                    subpath_start = point;
+                }
+                "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" => {
+                    if matches!(op.operator.as_str(), "s" | "b" | "b*") {
+                        path.push((point, subpath_start));
+                    }
+                    self.content
+                        .rulings
+                        .extend(path.drain(..).filter_map(|(a, b)| ruling(a, b)));
+                }
+                "n" => path.clear(),
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    state.font = operands
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .zip(resources)
+                        .and_then(|(name, res)| self.fonts.get(self.doc, res, name));
+                    state.font_size = num(1);
+                }
+                "Tc" => state.char_spacing = num(0),
+                "Tw" => state.word_spacing = num(0),
+                "Tz" => state.scaling = num(0) / 100.0,
+                "TL" => state.leading = num(0),
+                "Ts" => state.rise = num(0),
+                "Td" | "TD" => {
+                    if op.operator == "TD" {
+                        state.leading = -num(1);
+                    }
+                    tlm = multiply(&translate(num(0), num(1)), &tlm);
+                    tm = tlm;
+                }
+                "Tm" if operands.len() == 6 => {
+                    tlm = [num(0), num(1), num(2), num(3), num(4), num(5)];
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = multiply(&translate(0.0, -state.leading), &tlm);
+                    tm = tlm;
+                }
+                "Tj" | "'" | "\"" => {
+                    if op.operator != "Tj" {
+                        if op.operator == "\"" {
+                            state.word_spacing = num(0);
+                            state.char_spacing = num(1);
+                        }
+                        tlm = multiply(&translate(0.0, -state.leading), &tlm);
+                        tm = tlm;
+                    }
+                    if let Some(Object::String(bytes, _)) = operands.last() {
+                        self.show(bytes, &state, &mut tm);
+                    }
+                }
+                "TJ" => {
+                    let Some(Ok(items)) = operands.first().map(Object::as_array) else {
+                        continue;
+                    };
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => self.show(bytes, &state, &mut tm),
+                            other => {
+                                let adjust = number(other).unwrap_or(0.0);
+                                let tx = -adjust / 1000.0 * state.font_size * state.scaling;
+                                tm = multiply(&translate(tx, 0.0), &tm);
+                            }
+                        }
+                    }
+                }
+                "Do" => {
+                    let Some((id, stream)) = operands
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .zip(resources)
+                        .and_then(|(name, res)| xobject::lookup(self.doc, res, name))
+                    else {
+                        continue;
+                    };
+                    match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                        Ok(b"Image") => {
+                            if let Some(id) = id {
+                                self.content.images.push(image_bounds(id, &state.ctm));
+                            }
+                        }
+                        Ok(b"Form") if depth < MAX_FORM_DEPTH => {
+                            let form_resources = stream
+                                .dict
+                                .get(b"Resources")
+                                .ok()
+                                .and_then(|o| self.doc.dereference(o).ok())
+                                .and_then(|(_, o)| o.as_dict().ok());
+                            let mut form_state = state.clone();
+                            let matrix = stream
+                                .dict
+                                .get(b"Matrix")
+                                .and_then(Object::as_array)
+                                .ok()
+                                .filter(|m| m.len() == 6)
+                                .map(|m| {
+                                    let v: Vec<f32> =
+                                        m.iter().map(|o| number(o).unwrap_or(0.0)).collect();
+                                    [v[0], v[1], v[2], v[3], v[4], v[5]]
+                                })
+                                .unwrap_or(IDENTITY);
+                            form_state.ctm = multiply(&matrix, &state.ctm);
+                            let data = stream
+                                .decompressed_content()
+                                .unwrap_or_else(|_| stream.content.clone());
+                            self.walk(&data, form_resources.or(resources), form_state, depth + 1);
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Shows one string: decodes it, records it as a run and advances the text matrix.
+    fn show(&mut self, bytes: &[u8], state: &GraphicsState, tm: &mut Matrix) {
+        let Some(font) = &state.font else {
+            return;
+        };
+        let start = transform(&multiply(tm, &state.ctm), 0.0, state.rise);
+
+        let mut text = String::new();
+        for glyph in font.decode(bytes) {
+            let mut advance = glyph.width * state.font_size + state.char_spacing;
+            if glyph.is_space {
+                advance += state.word_spacing;
+            }
+            *tm = multiply(&translate(advance * state.scaling, 0.0), tm);
+            text.push_str(&glyph.text);
+        }
+
+        let trm = multiply(tm, &state.ctm);
+        let end = transform(&trm, 0.0, state.rise);
+        // Length of the text space unit vector (0, font size) in user space
+        let (vx, vy) = (trm[2] * state.font_size, trm[3] * state.font_size);
+        if !text.is_empty() {
+            self.content.runs.push(TextRun {
+                text,
+                x: start.0,
+                y: start.1,
+                width: ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt(),
+                size: (vx * vx + vy * vy).sqrt(),
+                style: font.style.clone(),
+                color: state.fill,
+            });
+        }
+    }
+}
+
+/// The segment from `a` to `b` as a ruling, if it is horizontal or vertical.
+fn ruling(a: (f32, f32), b: (f32, f32)) -> Option<Ruling> {
+    const TOLERANCE: f32 = 0.5;
+    let (dx, dy) = ((b.0 - a.0).abs(), (b.1 - a.1).abs());
+    if dy <= TOLERANCE && dx > TOLERANCE {
+        Some(Ruling {
+            horizontal: true,
+            position: (a.1 + b.1) / 2.0,
+            start: a.0.min(b.0),
+            end: a.0.max(b.0),
+        })
+    } else if dx <= TOLERANCE && dy > TOLERANCE {
+        Some(Ruling {
+            horizontal: false,
+            position: (a.0 + b.0) / 2.0,
+            start: a.1.min(b.1),
+            end: a.1.max(b.1),
+        })
+    } else {
+        None
+    }
+}
+
+/// Images fill the unit square of the CTM in effect when they are drawn.
+fn image_bounds(id: ObjectId, ctm: &Matrix) -> PageImage {
+    let corners =
+        [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| transform(ctm, x, y));
+    let left = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min);
+    let right = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max);
+    let bottom = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min);
+    let top = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max);
+    PageImage {
+        id,
+        x: left,
+        y: bottom,
+        width: right - left,
+        height: top - bottom,
+    }
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(i) => Some(*i as f32),
+        Object::Real(r) => Some(*r),
+        _ => None,
+    }
+}
+
+/// A gray, RGB or CMYK color as RGB, by the number of components.
+fn rgb(components: &[f32]) -> Option<[u8; 3]> {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match *components {
+        [gray] => Some([channel(gray); 3]),
+        [r, g, b] => Some([channel(r), channel(g), channel(b)]),
+        [c, m, y, k] => Some([
+            channel((1.0 - c) * (1.0 - k)),
+            channel((1.0 - m) * (1.0 - k)),
+            channel((1.0 - y) * (1.0 - k)),
+        ]),
+        _ => None,
+    }
+}
+
+/// The page's `/Resources`, inherited from its `/Pages` ancestors if it has none itself.
+fn page_resources(doc: &Document, page_id: ObjectId) -> Option<&Dictionary> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..64 {
+        if let Ok(resources) = node.get(b"Resources") {
+            return doc
+                .dereference(resources)
+                .ok()
+                .and_then(|(_, o)| o.as_dict().ok());
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Joins runs into plain text, in content stream order. A run starts a new line when its
+/// baseline moves by more than half the font size; runs on one line are separated by a space
+/// when there is a visible gap between them.
+pub(crate) fn runs_to_text(runs: &[TextRun]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&TextRun> = None;
+    for run in runs {
+        if let Some(prev) = previous {
+            let size = prev.size.max(run.size).max(1.0);
+            if (run.y - prev.y).abs() > size * 0.5 {
+                text.push('\n');
+            } else {
+                let gap = run.x - (prev.x + prev.width);
+                let spaced = text.ends_with(' ') || run.text.starts_with(' ');
+                if gap.abs() > size * 0.15 && !spaced {
+                    text.push(' ');
+                }
+            }
+        }
+        text.push_str(&run.text);
+        previous = Some(run);
+    }
+    text
+}
@@ -0,0 +1,236 @@
+use crate::layout::{Block, Headings, PageLayout, Paragraph};
+use crate::table::Table;
+use crate::text::{PageImage, TextRun};
+use crate::xobject::decode_image;
+use docx_rs::{Docx, Pic, Run, RunFonts, Style, StyleType, TableCell, TableRow, WidthType};
+use lopdf::{Document, ObjectId};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// Width of the text area of docx-rs' default page (A4 with 3 cm side margins), in points.
+const TEXT_WIDTH: f32 = 425.0;
+
+/// EMUs (the unit of drawing sizes) per point.
+const EMU_PER_POINT: f32 = 12700.0;
+
+/// Builds a Word document from the laid out pages of `doc`. Every page after the first starts
+/// on a new page; headings get the built-in `heading N` styles so they show up in the
+/// navigation pane and table of contents.
+///
+/// Images and tables are placed before the first paragraph that starts below their top edge.
+/// Images get their own paragraph, indented by their distance from the page's left content
+/// edge, at their size on the page (shrunk to fit the text width if needed).
+pub(crate) fn build_docx(doc: &Document, pages: &[PageLayout]) -> Docx {
+    let paragraphs = || pages.iter().flat_map(|page| &page.paragraphs);
+    let headings = Headings::detect(paragraphs());
+    let levels = paragraphs()
+        .filter_map(|p| headings.level(p))
+        .max()
+        .unwrap_or(0);
+
+    let mut docx = Docx::new();
+    for level in 1..=levels {
+        docx = docx.add_style(
+            Style::new(format!("Heading{}", level), StyleType::Paragraph)
+                .name(format!("heading {}", level))
+                .based_on("Normal")
+                .next("Normal")
+                .bold()
+                .outline_lvl(level - 1),
+        );
+    }
+
+    let mut pictures = PictureCache::default();
+    for (index, page) in pages.iter().enumerate() {
+        let left = page.left();
+        let mut converted: Vec<Converted> = Vec::new();
+        for block in page.blocks() {
+            match block {
+                Block::Paragraph(p) => {
+                    let paragraph = docx_paragraph(p);
+                    converted.push(Converted::Paragraph(Box::new(match headings.level(p) {
+                        Some(level) => paragraph.style(&format!("Heading{}", level)),
+                        None => paragraph,
+                    })));
+                }
+                Block::Image(image) => converted.extend(
+                    pictures
+                        .paragraph(doc, image, left)
+                        .map(|p| Converted::Paragraph(Box::new(p))),
+                ),
+                Block::Table(table) => {
+                    converted.push(Converted::Table(Box::new(docx_table(table))))
+                }
+            }
+        }
+
+        // Tables cannot carry the page break, and pages without content still need one so
+        // the page count is preserved
+        if index > 0 && !matches!(converted.first(), Some(Converted::Paragraph(_))) {
+            converted.insert(0, Converted::Paragraph(Box::default()));
+        }
+        for (i, block) in converted.into_iter().enumerate() {
+            docx = match block {
+                Converted::Paragraph(paragraph) if i == 0 && index > 0 => {
+                    docx.add_paragraph((*paragraph).page_break_before(true))
+                }
+                Converted::Paragraph(paragraph) => docx.add_paragraph(*paragraph),
+                Converted::Table(table) => docx.add_table(*table),
+            };
+        }
+    }
+    docx
+}
+
+enum Converted {
+    Paragraph(Box<docx_rs::Paragraph>),
+    Table(Box<docx_rs::Table>),
+}
+
+/// A Word table with the PDF table's column widths, shrunk to fit the text width if needed.
+/// Tables that are only aligned in columns get no borders.
+fn docx_table(table: &Table) -> docx_rs::Table {
+    let total = table.columns[table.columns.len() - 1] - table.columns[0];
+    let scale = (TEXT_WIDTH / total.max(1.0)).min(1.0);
+    // Widths are in twentieths of a point
+    let grid: Vec<usize> = table
+        .columns
+        .windows(2)
+        .map(|c| ((c[1] - c[0]) * scale * 20.0) as usize)
+        .collect();
+
+    let rows = table
+        .cells
+        .iter()
+        .map(|row| {
+            let mut column = 0;
+            let cells = row
+                .iter()
+                .map(|cell| {
+                    let width = grid[column..(column + cell.span).min(grid.len())]
+                        .iter()
+                        .sum();
+                    column += cell.span;
+                    let mut result = TableCell::new().width(width, WidthType::Dxa);
+                    if cell.span > 1 {
+                        result = result.grid_span(cell.span);
+                    }
+                    // Word requires a paragraph in every cell
+                    if cell.paragraphs.is_empty() {
+                        return result.add_paragraph(docx_rs::Paragraph::new());
+                    }
+                    for paragraph in &cell.paragraphs {
+                        result = result.add_paragraph(docx_paragraph(paragraph));
+                    }
+                    result
+                })
+                .collect();
+            TableRow::new(cells)
+        })
+        .collect();
+
+    let result = if table.ruled {
+        docx_rs::Table::new(rows)
+    } else {
+        docx_rs::Table::without_borders(rows)
+    };
+    result.set_grid(grid)
+}
+
+/// One Word run per stretch of text with the same formatting.
+fn docx_paragraph(paragraph: &Paragraph) -> docx_rs::Paragraph {
+    let mut result = docx_rs::Paragraph::new();
+    let mut pending: Option<(String, &TextRun)> = None;
+    for (text, run) in paragraph.pieces() {
+        match &mut pending {
+            Some((pending_text, first)) if same_format(first, run) => pending_text.push_str(&text),
+            _ => {
+                if let Some((text, first)) = pending.take() {
+                    result = result.add_run(docx_run(text, first));
+                }
+                pending = Some((text, run));
+            }
+        }
+    }
+    if let Some((text, first)) = pending {
+        result = result.add_run(docx_run(text, first));
+    }
+    result
+}
+
+/// Font size in half points, the unit Word uses.
+fn half_points(size: f32) -> usize {
+    (size * 2.0).round().max(2.0) as usize
+}
+
+fn same_format(a: &TextRun, b: &TextRun) -> bool {
+    a.style == b.style && a.color == b.color && half_points(a.size) == half_points(b.size)
+}
+
+fn docx_run(text: String, run: &TextRun) -> Run {
+    let mut result = Run::new().add_text(text).size(half_points(run.size));
+    let family = run.style.family.as_str();
+    if !family.is_empty() {
+        result = result.fonts(
+            RunFonts::new()
+                .ascii(family)
+                .hi_ansi(family)
+                .east_asia(family)
+                .cs(family),
+        );
+    }
+    if run.style.bold {
+        result = result.bold();
+    }
+    if run.style.italic {
+        result = result.italic();
+    }
+    if run.color != [0, 0, 0] {
+        let [r, g, b] = run.color;
+        result = result.color(format!("{:02X}{:02X}{:02X}", r, g, b));
+    }
+    result
+}
+
+/// Images converted to PNG, the only format docx-rs embeds, by object so that images drawn
+/// on several pages are only decoded once.
+#[derive(Default)]
+struct PictureCache {
+    pngs: HashMap<ObjectId, Option<(Vec<u8>, u32, u32)>>,
+}
+
+impl PictureCache {
+    /// A paragraph holding `image`, or `None` if it cannot be decoded.
+    fn paragraph(
+        &mut self,
+        doc: &Document,
+        image: &PageImage,
+        left: f32,
+    ) -> Option<docx_rs::Paragraph> {
+        let (png, width_px, height_px) = self
+            .pngs
+            .entry(image.id)
+            .or_insert_with(|| {
+                let decoded = decode_image(doc, image.id)?;
+                let mut png = Cursor::new(Vec::new());
+                decoded.write_to(&mut png, image::ImageFormat::Png).ok()?;
+                Some((png.into_inner(), decoded.width(), decoded.height()))
+            })
+            .clone()?;
+
+        let scale = (TEXT_WIDTH / image.width.max(1.0)).min(1.0);
+        let (width, height) = (image.width * scale, image.height * scale);
+        let indent = (image.x - left).clamp(0.0, TEXT_WIDTH - width);
+        let pic = Pic::new_with_dimensions(png, width_px, height_px).size(
+            (width * EMU_PER_POINT) as u32,
+            (height * EMU_PER_POINT) as u32,
+        );
+        let paragraph = docx_rs::Paragraph::new().add_run(Run::new().add_image(pic));
+        Some(if indent >= 1.0 {
+            // Indents are in twentieths of a point
+            paragraph.indent(Some((indent * 20.0) as i32), None, None, None)
+        } else {
+            paragraph
+        })
+    }
+}
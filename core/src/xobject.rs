@@ -0,0 +1,881 @@
+use image::error::{ImageFormatHint, ParameterError, ParameterErrorKind, UnsupportedError};
+use image::metadata::Orientation;
+use image::{AnimationDecoder, DynamicImage, GrayImage, ImageDecoder, ImageError, ImageFormat};
+use image::{RgbImage, RgbaImage};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::io::Cursor;
+
+/// Decodes an image XObject into pixels.
+///
+/// Handles JPEG (`DCTDecode`) data, Group 4 fax (`CCITTFaxDecode`) data and raw samples
+/// behind `FlateDecode`/`LZWDecode` or no filter, in gray, RGB, CMYK, ICC based and indexed
+/// color spaces, with a soft mask as alpha. Stencil masks and images in JPEG 2000, JBIG2 or
+/// Group 3 fax encoding are not supported.
+pub(crate) fn decode_image(doc: &Document, id: ObjectId) -> Option<DynamicImage> {
+    let stream = doc.get_object(id).ok()?.as_stream().ok()?;
+    let dict = &stream.dict;
+    if dict
+        .get(b"ImageMask")
+        .and_then(Object::as_bool)
+        .unwrap_or(false)
+    {
+        return None;
+    }
+    let image = decode_samples(doc, stream)?;
+
+    let mask = dict
+        .get(b"SMask")
+        .and_then(Object::as_reference)
+        .ok()
+        .and_then(|mask_id| doc.get_object(mask_id).ok())
+        .and_then(|o| o.as_stream().ok())
+        .and_then(|mask| decode_samples(doc, mask))
+        .map(|mask| mask.into_luma8())
+        .filter(|mask| mask.dimensions() == (image.width(), image.height()));
+    Some(match mask {
+        Some(mask) => {
+            let mut rgba: RgbaImage = image.into_rgba8();
+            for (pixel, alpha) in rgba.pixels_mut().zip(mask.pixels()) {
+                pixel.0[3] = alpha.0[0];
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+        None => image,
+    })
+}
+
+/// Largest image, in pixels, that is decoded; bigger `/Width` × `/Height` is taken as damage.
+const MAX_IMAGE_PIXELS: usize = 1 << 27;
+
+fn decode_samples(doc: &Document, stream: &Stream) -> Option<DynamicImage> {
+    let dict = &stream.dict;
+    let filters = stream.filters().unwrap_or_default();
+
+    if filters.last().map(String::as_str) == Some("DCTDecode") {
+        let data = decompress(stream, &filters[..filters.len() - 1])?;
+        return image::load_from_memory_with_format(&data, ImageFormat::Jpeg).ok();
+    }
+    if filters.last().map(String::as_str) == Some("CCITTFaxDecode") {
+        let data = decompress(stream, &filters[..filters.len() - 1])?;
+        return decode_group4(dict, &data);
+    }
+    if !filters
+        .iter()
+        .all(|f| f == "FlateDecode" || f == "LZWDecode")
+    {
+        return None;
+    }
+    let data = decompress(stream, &filters)?;
+
+    let dimension = |key: &[u8]| usize::try_from(dict.get(key).and_then(Object::as_i64).ok()?).ok();
+    let (width, height) = (dimension(b"Width")?, dimension(b"Height")?);
+    let bits = match dict.get(b"BitsPerComponent") {
+        Ok(_) => dimension(b"BitsPerComponent")?,
+        Err(_) => 8,
+    };
+    if width == 0 || height == 0 || !matches!(bits, 1 | 2 | 4 | 8 | 16) {
+        return None;
+    }
+    let pixels = width
+        .checked_mul(height)
+        .filter(|&pixels| pixels <= MAX_IMAGE_PIXELS)?;
+    let space = dict
+        .get(b"ColorSpace")
+        .ok()
+        .map_or(Some(ColorSpace::Gray), |o| ColorSpace::parse(doc, o))?;
+    // A `/Decode` array starting with `1 0` inverts the samples, as in many bilevel scans
+    let invert = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|d| Some((number(d.first()?)?, number(d.get(1)?)?)))
+        .is_some_and(|(min, max)| min > max);
+
+    let components = space.components();
+    let row_bytes = width
+        .checked_mul(components)?
+        .checked_mul(bits)?
+        .div_ceil(8);
+    if data.len() < row_bytes.checked_mul(height)? {
+        return None;
+    }
+    let max = ((1u32 << bits) - 1) as f32;
+    let mut samples = Vec::with_capacity(pixels.checked_mul(components)?);
+    for row in data.chunks_exact(row_bytes).take(height) {
+        for i in 0..width * components {
+            let value = match bits {
+                8 => row[i] as u32,
+                16 => row[i * 2] as u32,
+                _ => {
+                    let bit = i * bits;
+                    (row[bit / 8] as u32 >> (8 - bits - bit % 8)) & ((1 << bits) - 1)
+                }
+            };
+            let value = if invert && bits != 16 {
+                (max as u32) - value.min(max as u32)
+            } else {
+                value
+            };
+            samples.push(value);
+        }
+    }
+
+    let (width, height) = (u32::try_from(width).ok()?, u32::try_from(height).ok()?);
+    let scale = |v: u32| -> u8 {
+        if bits == 8 || bits == 16 {
+            v as u8
+        } else {
+            (v as f32 * 255.0 / max).round() as u8
+        }
+    };
+    match space {
+        ColorSpace::Gray => {
+            GrayImage::from_raw(width, height, samples.into_iter().map(scale).collect())
+                .map(DynamicImage::ImageLuma8)
+        }
+        ColorSpace::Rgb => {
+            RgbImage::from_raw(width, height, samples.into_iter().map(scale).collect())
+                .map(DynamicImage::ImageRgb8)
+        }
+        ColorSpace::Cmyk => {
+            let pixels = samples
+                .chunks_exact(4)
+                .flat_map(|p| cmyk_to_rgb([scale(p[0]), scale(p[1]), scale(p[2]), scale(p[3])]))
+                .collect();
+            RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+        }
+        ColorSpace::Indexed { base, palette } => {
+            let n = base.components();
+            let pixels = samples
+                .into_iter()
+                .flat_map(|index| {
+                    let start = index as usize * n;
+                    let entry = palette.get(start..start + n).unwrap_or(&[0, 0, 0, 0][..n]);
+                    match *base {
+                        ColorSpace::Gray => [entry[0]; 3],
+                        ColorSpace::Cmyk => cmyk_to_rgb([entry[0], entry[1], entry[2], entry[3]]),
+                        _ => [entry[0], entry[1], entry[2]],
+                    }
+                })
+                .collect();
+            RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+        }
+    }
+}
+
+/// A bilevel image from Group 4 fax data. 0 bits are black unless `/BlackIs1` is set.
+fn decode_group4(dict: &Dictionary, data: &[u8]) -> Option<DynamicImage> {
+    let params = match dict.get(b"DecodeParms").ok()? {
+        Object::Array(params) => params.last()?.as_dict().ok()?,
+        params => params.as_dict().ok()?,
+    };
+    let param = |key: &[u8]| params.get(key).ok();
+    if param(b"K").and_then(|k| k.as_i64().ok()).unwrap_or(0) >= 0 {
+        return None;
+    }
+    let width = param(b"Columns").map_or(Some(1728), |c| c.as_i64().ok())?;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok()?;
+    let (width, height) = (u16::try_from(width).ok()?, u16::try_from(height).ok()?);
+    if width as usize * height as usize > MAX_IMAGE_PIXELS {
+        return None;
+    }
+    let black_is_1 = param(b"BlackIs1").is_some_and(|b| b.as_bool().unwrap_or(false));
+    let invert = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|d| Some((number(d.first()?)?, number(d.get(1)?)?)))
+        .is_some_and(|(min, max)| min > max);
+
+    let mut samples = Vec::with_capacity(width as usize * height as usize);
+    fax::decoder::decode_g4(data.iter().copied(), width, Some(height), |line| {
+        samples.extend(fax::decoder::pels(line, width).map(|color| {
+            // A 1 bit shows white unless the samples are inverted
+            let bit = (color == fax::Color::Black) == black_is_1;
+            if bit != invert {
+                255
+            } else {
+                0
+            }
+        }));
+    })?;
+    samples.resize(width as usize * height as usize, 255);
+    GrayImage::from_raw(width as u32, height as u32, samples).map(DynamicImage::ImageLuma8)
+}
+
+/// The stream's data with `filters` applied; other filters are left to the caller.
+fn decompress(stream: &Stream, filters: &[String]) -> Option<Vec<u8>> {
+    if filters.is_empty() {
+        return Some(stream.content.clone());
+    }
+    // lopdf refuses to decompress image streams, so hand it a copy that is not marked as one
+    let mut dict = stream.dict.clone();
+    dict.remove(b"Subtype");
+    dict.set(
+        "Filter",
+        filters
+            .iter()
+            .map(|f| Object::Name(f.as_bytes().to_vec()))
+            .collect::<Vec<_>>(),
+    );
+    Stream::new(dict, stream.content.clone())
+        .decompressed_content()
+        .ok()
+}
+
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    Indexed {
+        base: Box<ColorSpace>,
+        palette: Vec<u8>,
+    },
+}
+
+impl ColorSpace {
+    fn parse(doc: &Document, object: &Object) -> Option<ColorSpace> {
+        let object = doc.dereference(object).ok()?.1;
+        if let Ok(name) = object.as_name() {
+            return Self::by_name(name);
+        }
+        let array = object.as_array().ok()?;
+        let family = array.first()?.as_name().ok()?;
+        match family {
+            b"ICCBased" => {
+                let profile = doc.dereference(array.get(1)?).ok()?.1.as_stream().ok()?;
+                match profile.dict.get(b"N").and_then(Object::as_i64).ok()? {
+                    1 => Some(ColorSpace::Gray),
+                    3 => Some(ColorSpace::Rgb),
+                    4 => Some(ColorSpace::Cmyk),
+                    _ => None,
+                }
+            }
+            b"Indexed" | b"I" => {
+                let base = Self::parse(doc, array.get(1)?)?;
+                if matches!(base, ColorSpace::Indexed { .. }) {
+                    return None;
+                }
+                let palette = match doc.dereference(array.get(3)?).ok()?.1 {
+                    Object::String(bytes, _) => bytes.clone(),
+                    Object::Stream(stream) => stream
+                        .decompressed_content()
+                        .unwrap_or_else(|_| stream.content.clone()),
+                    _ => return None,
+                };
+                Some(ColorSpace::Indexed {
+                    base: Box::new(base),
+                    palette,
+                })
+            }
+            b"CalGray" => Some(ColorSpace::Gray),
+            b"CalRGB" => Some(ColorSpace::Rgb),
+            _ => Self::by_name(family),
+        }
+    }
+
+    fn by_name(name: &[u8]) -> Option<ColorSpace> {
+        match name {
+            b"DeviceGray" | b"G" | b"CalGray" => Some(ColorSpace::Gray),
+            b"DeviceRGB" | b"RGB" | b"CalRGB" => Some(ColorSpace::Rgb),
+            b"DeviceCMYK" | b"CMYK" => Some(ColorSpace::Cmyk),
+            _ => None,
+        }
+    }
+
+    fn components(&self) -> usize {
+        match self {
+            ColorSpace::Gray | ColorSpace::Indexed { .. } => 1,
+            ColorSpace::Rgb => 3,
+            ColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+fn cmyk_to_rgb([c, m, y, k]: [u8; 4]) -> [u8; 3] {
+    let channel = |v: u8| ((255 - v as u32) * (255 - k as u32) / 255) as u8;
+    [channel(c), channel(m), channel(y)]
+}
+
+fn number(object: &Object) -> Option<f32> {
+    match object {
+        Object::Integer(i) => Some(*i as f32),
+        Object::Real(r) => Some(*r),
+        _ => None,
+    }
+}
+
+/// The image XObject or form XObject named `name` in the `/XObject` entry of `resources`.
+pub(crate) fn lookup<'a>(
+    doc: &'a Document,
+    resources: &'a Dictionary,
+    name: &[u8],
+) -> Option<(Option<ObjectId>, &'a Stream)> {
+    let xobjects = resources
+        .get(b"XObject")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())?;
+    let (id, object) = doc.dereference(xobjects.get(name).ok()?).ok()?;
+    Some((id, object.as_stream().ok()?))
+}
+
+/// One image of an image file as an image XObject, ready to be added to a document.
+pub(crate) struct ImageXObject {
+    /// Size of the stored samples in pixels
+    pub width: u32,
+    pub height: u32,
+    /// How the samples are turned for display, from the file's EXIF data
+    pub orientation: Orientation,
+    stream: Stream,
+    /// The alpha channel, stored as the image's `/SMask`
+    mask: Option<Stream>,
+}
+
+impl ImageXObject {
+    /// Encodes the images of an image file without loss: every page of a TIFF, every frame
+    /// of an animated GIF, WebP or PNG, or else its one image. JPEG data is kept as it is,
+    /// other formats are decoded and stored as compressed samples in their own color space
+    /// and bit depth, with any transparency as a soft mask.
+    pub fn from_file_data(data: &[u8]) -> Result<Vec<Self>, ImageError> {
+        Ok(frames(data)?
+            .into_iter()
+            .map(|(frame, orientation)| {
+                let mut image = match frame {
+                    Frame::Jpeg(jpeg) => Self::from_jpeg(data, jpeg),
+                    Frame::Cmyk {
+                        width,
+                        height,
+                        samples,
+                    } => Self::from_cmyk(width, height, samples),
+                    Frame::Pixels(image) => Self::from_image(image),
+                };
+                image.orientation = orientation;
+                image
+            })
+            .collect())
+    }
+
+    /// Width and height in pixels as displayed.
+    pub fn display_size(&self) -> (u32, u32) {
+        match self.orientation {
+            Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+
+    /// The operands of a `cm` operator that draws the image, turned for display, into the
+    /// rectangle at `x`, `y` of `width` x `height`.
+    pub fn placement(&self, x: f32, y: f32, width: f32, height: f32) -> [f32; 6] {
+        // Maps the unit square the image is drawn in to itself: displayed
+        // u' = a u + b v + c and v' = d u + e v + f, with v pointing up
+        let [a, b, c, d, e, f] = match self.orientation {
+            Orientation::NoTransforms => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            Orientation::FlipHorizontal => [-1.0, 0.0, 1.0, 0.0, 1.0, 0.0],
+            Orientation::Rotate180 => [-1.0, 0.0, 1.0, 0.0, -1.0, 1.0],
+            Orientation::FlipVertical => [1.0, 0.0, 0.0, 0.0, -1.0, 1.0],
+            Orientation::Rotate90FlipH => [0.0, -1.0, 1.0, -1.0, 0.0, 1.0],
+            Orientation::Rotate90 => [0.0, 1.0, 0.0, -1.0, 0.0, 1.0],
+            Orientation::Rotate270FlipH => [0.0, 1.0, 0.0, 1.0, 0.0, 0.0],
+            Orientation::Rotate270 => [0.0, -1.0, 1.0, 1.0, 0.0, 0.0],
+        };
+        [
+            width * a,
+            height * d,
+            width * b,
+            height * e,
+            x + width * c,
+            y + height * f,
+        ]
+    }
+
+    /// Stores a photo as JPEG at `quality` (1 to 100), for pixels that were changed after
+    /// decoding and have no original data left to keep.
+    pub fn from_photo(image: DynamicImage, quality: u8) -> Self {
+        let mut data = Vec::new();
+        let encoded = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, quality)
+            .encode_image(&image);
+        match encoded.ok().and_then(|_| JpegHeader::parse(&data)) {
+            Some(jpeg) => Self::from_jpeg(&data, jpeg),
+            None => Self::from_image(image),
+        }
+    }
+
+    fn from_jpeg(data: &[u8], jpeg: JpegHeader) -> Self {
+        let mut dict = Dictionary::new();
+        dict.set("Type", "XObject");
+        dict.set("Subtype", "Image");
+        dict.set("Width", jpeg.width as i64);
+        dict.set("Height", jpeg.height as i64);
+        dict.set("BitsPerComponent", 8);
+        dict.set("Filter", "DCTDecode");
+        match jpeg.components {
+            1 => dict.set("ColorSpace", "DeviceGray"),
+            4 => {
+                dict.set("ColorSpace", "DeviceCMYK");
+                // Adobe applications write CMYK JPEGs inverted
+                if jpeg.adobe {
+                    dict.set(
+                        "Decode",
+                        [1, 0, 1, 0, 1, 0, 1, 0].map(Object::Integer).to_vec(),
+                    );
+                }
+            }
+            _ => dict.set("ColorSpace", "DeviceRGB"),
+        }
+        ImageXObject {
+            width: jpeg.width,
+            height: jpeg.height,
+            orientation: Orientation::NoTransforms,
+            stream: Stream::new(dict, data.to_vec()),
+            mask: None,
+        }
+    }
+
+    /// Stores decoded pixels without loss.
+    pub fn from_image(image: DynamicImage) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let big_endian =
+            |samples: &[u16]| -> Vec<u8> { samples.iter().flat_map(|s| s.to_be_bytes()).collect() };
+        // Samples as stored in the PDF, with the alpha channel still interleaved if there is one
+        let (color_space, bits, samples, alpha) = match image {
+            DynamicImage::ImageLuma8(gray) => ("DeviceGray", 8, gray.into_raw(), false),
+            DynamicImage::ImageLumaA8(gray) => ("DeviceGray", 8, gray.into_raw(), true),
+            DynamicImage::ImageRgb8(rgb) => ("DeviceRGB", 8, rgb.into_raw(), false),
+            DynamicImage::ImageRgba8(rgba) => ("DeviceRGB", 8, rgba.into_raw(), true),
+            DynamicImage::ImageLuma16(gray) => ("DeviceGray", 16, big_endian(&gray), false),
+            DynamicImage::ImageLumaA16(gray) => ("DeviceGray", 16, big_endian(&gray), true),
+            DynamicImage::ImageRgb16(rgb) => ("DeviceRGB", 16, big_endian(&rgb), false),
+            DynamicImage::ImageRgba16(rgba) => ("DeviceRGB", 16, big_endian(&rgba), true),
+            // Floating point images have no PDF equivalent
+            image if image.color().has_alpha() => {
+                ("DeviceRGB", 8, image.into_rgba8().into_raw(), true)
+            }
+            image => ("DeviceRGB", 8, image.into_rgb8().into_raw(), false),
+        };
+        let components = if color_space == "DeviceGray" { 1 } else { 3 };
+
+        let (samples, mask) = if alpha {
+            let bytes = bits / 8;
+            let pixel = (components + 1) * bytes;
+            let mut color = Vec::with_capacity(samples.len() / (components + 1) * components);
+            let mut mask = Vec::with_capacity(samples.len() / (components + 1));
+            for p in samples.chunks_exact(pixel) {
+                color.extend_from_slice(&p[..components * bytes]);
+                mask.extend_from_slice(&p[components * bytes..]);
+            }
+            // Fully opaque images need no mask
+            let opaque = mask.iter().all(|&a| a == 255);
+            (color, (!opaque).then_some(mask))
+        } else {
+            (samples, None)
+        };
+
+        ImageXObject {
+            width,
+            height,
+            orientation: Orientation::NoTransforms,
+            stream: sample_stream(width, height, color_space, bits as i64, samples),
+            mask: mask.map(|mask| sample_stream(width, height, "DeviceGray", bits as i64, mask)),
+        }
+    }
+
+    /// 8-bit CMYK samples, kept in CMYK.
+    fn from_cmyk(width: u32, height: u32, samples: Vec<u8>) -> Self {
+        ImageXObject {
+            width,
+            height,
+            orientation: Orientation::NoTransforms,
+            stream: sample_stream(width, height, "DeviceCMYK", 8, samples),
+            mask: None,
+        }
+    }
+
+    /// Adds the image, and its mask if it has one, to `doc`.
+    pub fn add_to(self, doc: &mut Document) -> ObjectId {
+        let mut stream = self.stream;
+        if let Some(mask) = self.mask {
+            stream.dict.set("SMask", doc.add_object(mask));
+        }
+        doc.add_object(stream)
+    }
+}
+
+/// One image of an image file as read, before it is stored.
+enum Frame {
+    /// The file is a JPEG that can be embedded as it is
+    Jpeg(JpegHeader),
+    /// 8-bit CMYK samples
+    Cmyk {
+        width: u32,
+        height: u32,
+        samples: Vec<u8>,
+    },
+    Pixels(DynamicImage),
+}
+
+/// The images of an image file with their orientation: every page of a TIFF, every frame of
+/// an animated GIF, WebP or PNG, or else its one image.
+fn frames(data: &[u8]) -> Result<Vec<(Frame, Orientation)>, ImageError> {
+    // HEIC/HEIF has no decoder in Rust
+    if data.get(4..8) == Some(b"ftyp")
+        && data
+            .get(8..12)
+            .is_some_and(|brand| matches!(brand, b"heic" | b"heix" | b"heif" | b"mif1" | b"msf1"))
+    {
+        return Err(ImageError::Unsupported(UnsupportedError::from(
+            ImageFormatHint::Name("HEIC".to_string()),
+        )));
+    }
+    let format = image::guess_format(data).ok();
+    match format {
+        Some(ImageFormat::Jpeg) => {
+            if let Some(jpeg) = JpegHeader::parse(data) {
+                return Ok(vec![(Frame::Jpeg(jpeg), orientation(data))]);
+            }
+        }
+        Some(ImageFormat::Tiff) => {
+            let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data))
+                .map_err(|e| decoding_error(ImageFormat::Tiff, e))?;
+            if decoder.more_images() {
+                return tiff_pages(&mut decoder).map_err(|e| decoding_error(ImageFormat::Tiff, e));
+            }
+        }
+        Some(ImageFormat::Gif) => {
+            let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(data))?;
+            return animation_frames(decoder);
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(data))?;
+            if decoder.has_animation() {
+                return animation_frames(decoder);
+            }
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = image::codecs::png::PngDecoder::new(Cursor::new(data))?;
+            if decoder.is_apng()? {
+                return animation_frames(decoder.apng()?);
+            }
+        }
+        _ => {}
+    }
+
+    let mut decoder = image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let image = DynamicImage::from_decoder(decoder)?;
+    Ok(vec![(Frame::Pixels(image), orientation)])
+}
+
+/// Decodes the images of an image file, as [`ImageXObject::from_file_data`] would store them,
+/// into pixels turned upright.
+pub(crate) fn decode_image_file(data: &[u8]) -> Result<Vec<DynamicImage>, ImageError> {
+    frames(data)?
+        .into_iter()
+        .map(|(frame, orientation)| {
+            let mut image = match frame {
+                Frame::Jpeg(_) => image::load_from_memory_with_format(data, ImageFormat::Jpeg)?,
+                Frame::Cmyk {
+                    width,
+                    height,
+                    samples,
+                } => {
+                    let pixels = samples
+                        .chunks_exact(4)
+                        .flat_map(|p| cmyk_to_rgb([p[0], p[1], p[2], p[3]]))
+                        .collect();
+                    RgbImage::from_raw(width, height, pixels)
+                        .map(DynamicImage::ImageRgb8)
+                        .ok_or(ImageError::Parameter(ParameterError::from_kind(
+                            ParameterErrorKind::DimensionMismatch,
+                        )))?
+                }
+                Frame::Pixels(image) => image,
+            };
+            image.apply_orientation(orientation);
+            Ok(image)
+        })
+        .collect()
+}
+
+/// An image XObject holding `samples`, compressed if that makes them smaller. Black and white
+/// images are packed at 1 bit per pixel, or stored as Group 4 fax data if that is smaller.
+fn sample_stream(
+    width: u32,
+    height: u32,
+    color_space: &str,
+    bits: i64,
+    samples: Vec<u8>,
+) -> Stream {
+    let dict = |bits: i64| {
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => width as i64,
+            "Height" => height as i64,
+            "ColorSpace" => color_space,
+            "BitsPerComponent" => bits,
+        }
+    };
+    if let Some(packed) = bilevel(&samples, color_space, bits, width) {
+        let mut stream = Stream::new(dict(1), packed);
+        let _ = stream.compress();
+        if width <= u16::MAX as u32 && height <= u16::MAX as u32 {
+            let fax = group4(&samples, width);
+            if fax.len() < stream.content.len() {
+                let mut dict = dict(1);
+                dict.set("Filter", "CCITTFaxDecode");
+                dict.set(
+                    "DecodeParms",
+                    dictionary! {
+                        "K" => -1,
+                        "Columns" => width as i64,
+                        "Rows" => height as i64,
+                    },
+                );
+                stream = Stream::new(dict, fax);
+            }
+        }
+        return stream;
+    }
+    let mut stream = Stream::new(dict(bits), samples);
+    // Leaves the samples uncompressed only if that is smaller
+    let _ = stream.compress();
+    stream
+}
+
+/// Black and white samples, one byte each, as CCITT Group 4 fax data. Rows are at most 65535
+/// pixels wide.
+pub(crate) fn group4(samples: &[u8], width: u32) -> Vec<u8> {
+    let mut fax = fax::encoder::Encoder::new(fax::VecWriter::new());
+    for row in samples.chunks_exact(width.max(1) as usize) {
+        let pels = row.iter().map(|&p| {
+            if p == 0 {
+                fax::Color::Black
+            } else {
+                fax::Color::White
+            }
+        });
+        // The writer never fails
+        let _ = fax.encode_line(pels, width as u16);
+    }
+    match fax.finish() {
+        Ok(writer) => writer.finish(),
+        Err(never) => match never {},
+    }
+}
+
+/// The EXIF orientation of an image file, upright if it has none.
+fn orientation(data: &[u8]) -> Orientation {
+    image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+/// Every frame of an animation, as shown in turn.
+fn animation_frames<'a>(
+    decoder: impl AnimationDecoder<'a>,
+) -> Result<Vec<(Frame, Orientation)>, ImageError> {
+    decoder
+        .into_frames()
+        .map(|frame| {
+            let image = DynamicImage::ImageRgba8(frame?.into_buffer());
+            Ok((Frame::Pixels(image), Orientation::NoTransforms))
+        })
+        .collect()
+}
+
+/// Every page of a TIFF file, starting from the current one.
+fn tiff_pages<R: std::io::Read + std::io::Seek>(
+    decoder: &mut tiff::decoder::Decoder<R>,
+) -> tiff::TiffResult<Vec<(Frame, Orientation)>> {
+    use tiff::decoder::DecodingResult;
+    use tiff::ColorType;
+
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions()?;
+        let orientation = decoder
+            .find_tag_unsigned::<u8>(tiff::tags::Tag::Orientation)?
+            .and_then(Orientation::from_exif)
+            .unwrap_or(Orientation::NoTransforms);
+        let color_type = decoder.colortype()?;
+        let image = match (color_type, decoder.read_image()?) {
+            (ColorType::CMYK(8), DecodingResult::U8(samples)) => Some(Frame::Cmyk {
+                width,
+                height,
+                samples,
+            }),
+            (ColorType::Gray(1), DecodingResult::U8(packed)) => {
+                let row_bytes = width.div_ceil(8) as usize;
+                let samples = packed
+                    .chunks_exact(row_bytes)
+                    .flat_map(|row| {
+                        (0..width as usize).map(move |x| {
+                            if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                                255
+                            } else {
+                                0
+                            }
+                        })
+                    })
+                    .collect();
+                GrayImage::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageLuma8)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::Gray(8), DecodingResult::U8(samples)) => {
+                GrayImage::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageLuma8)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::GrayA(8), DecodingResult::U8(samples)) => {
+                image::GrayAlphaImage::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageLumaA8)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::RGB(8), DecodingResult::U8(samples)) => {
+                RgbImage::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageRgb8)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::RGBA(8), DecodingResult::U8(samples)) => {
+                RgbaImage::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageRgba8)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::Gray(16), DecodingResult::U16(samples)) => {
+                image::ImageBuffer::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageLuma16)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::GrayA(16), DecodingResult::U16(samples)) => {
+                image::ImageBuffer::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageLumaA16)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::RGB(16), DecodingResult::U16(samples)) => {
+                image::ImageBuffer::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageRgb16)
+                    .map(Frame::Pixels)
+            }
+            (ColorType::RGBA(16), DecodingResult::U16(samples)) => {
+                image::ImageBuffer::from_raw(width, height, samples)
+                    .map(DynamicImage::ImageRgba16)
+                    .map(Frame::Pixels)
+            }
+            _ => None,
+        };
+        let image = image.ok_or(tiff::TiffError::UnsupportedError(
+            tiff::TiffUnsupportedError::UnsupportedColorType(color_type),
+        ))?;
+        pages.push((image, orientation));
+
+        if !decoder.more_images() {
+            return Ok(pages);
+        }
+        decoder.next_image()?;
+    }
+}
+
+fn decoding_error(
+    format: ImageFormat,
+    e: impl std::error::Error + Send + Sync + 'static,
+) -> ImageError {
+    ImageError::Decoding(image::error::DecodingError::new(format.into(), e))
+}
+
+/// 8-bit gray samples packed at 1 bit per pixel, if every sample is black or white. Rows
+/// start on a byte boundary.
+fn bilevel(samples: &[u8], color_space: &str, bits: i64, width: u32) -> Option<Vec<u8>> {
+    if color_space != "DeviceGray" || bits != 8 || !samples.iter().all(|&s| s == 0 || s == 255) {
+        return None;
+    }
+    let width = width as usize;
+    let mut packed = Vec::with_capacity(samples.len().div_ceil(8));
+    for row in samples.chunks_exact(width.max(1)) {
+        for byte in row.chunks(8) {
+            let mut bits = 0u8;
+            for (i, &sample) in byte.iter().enumerate() {
+                if sample == 255 {
+                    bits |= 0x80 >> i;
+                }
+            }
+            packed.push(bits);
+        }
+    }
+    Some(packed)
+}
+
+/// The parts of a JPEG's frame header that the image dictionary needs.
+struct JpegHeader {
+    width: u32,
+    height: u32,
+    components: u8,
+    /// An Adobe `APP14` segment precedes the frame
+    adobe: bool,
+}
+
+impl JpegHeader {
+    /// Reads the segments up to the first frame header. Only 8-bit JPEGs with their height
+    /// in the header can be embedded as they are.
+    fn parse(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return None;
+        }
+        let mut adobe = false;
+        let mut pos = 2;
+        while pos + 4 <= data.len() {
+            if data[pos] != 0xFF {
+                return None;
+            }
+            let marker = data[pos + 1];
+            // Fill bytes and markers without a length
+            if marker == 0xFF {
+                pos += 1;
+                continue;
+            }
+            if matches!(marker, 0x01 | 0xD0..=0xD7) {
+                pos += 2;
+                continue;
+            }
+            let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            let segment = data.get(pos + 4..pos + 2 + length.max(2))?;
+            match marker {
+                0xEE if segment.starts_with(b"Adobe") => adobe = true,
+                // Start of frame; C4, C8 and CC are other markers in the same range
+                0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    let [precision, h0, h1, w0, w1, components, ..] = *segment else {
+                        return None;
+                    };
+                    let height = u16::from_be_bytes([h0, h1]) as u32;
+                    let width = u16::from_be_bytes([w0, w1]) as u32;
+                    if precision != 8 || height == 0 || width == 0 {
+                        return None;
+                    }
+                    if !matches!(components, 1 | 3 | 4) {
+                        return None;
+                    }
+                    return Some(JpegHeader {
+                        width,
+                        height,
+                        components,
+                        adobe,
+                    });
+                }
+                // Start of scan before any frame header
+                0xDA => return None,
+                _ => {}
+            }
+            pos += 2 + length;
+        }
+        None
+    }
+}
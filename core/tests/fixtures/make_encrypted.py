@@ -0,0 +1,210 @@
+#!/usr/bin/env python3
+"""Writes the encrypted PDF fixtures of the pdf_core tests.
+
+The files are built here, independently of pdf_core and lopdf, so the tests check the
+decryption against a separate implementation of the standard security handler. Pages,
+catalog and info dictionary live in a compressed object stream, which is encrypted as a
+whole like any other stream. Both files open with the user password "user" or the owner
+password "owner".
+
+Needs the `cryptography` package. Run from this directory:
+
+    python3 make_encrypted.py
+"""
+
+import hashlib
+import os
+import struct
+import zlib
+
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+
+PADDING = bytes.fromhex(
+    "28BF4E5E4E758A4164004E56FFFA01082E2E00B6D0683E802F0CA9FE6453697A"
+)
+FILE_ID = bytes.fromhex("0123456789ABCDEF0123456789ABCDEF")
+PERMISSIONS = -3904  # everything but printing
+
+
+def rc4(key, data):
+    s = list(range(256))
+    j = 0
+    for i in range(256):
+        j = (j + s[i] + key[i % len(key)]) % 256
+        s[i], s[j] = s[j], s[i]
+    out = bytearray()
+    i = j = 0
+    for byte in data:
+        i = (i + 1) % 256
+        j = (j + s[i]) % 256
+        s[i], s[j] = s[j], s[i]
+        out.append(byte ^ s[(s[i] + s[j]) % 256])
+    return bytes(out)
+
+
+def aes_cbc(key, iv, data):
+    encryptor = Cipher(algorithms.AES(key), modes.CBC(iv)).encryptor()
+    return encryptor.update(data) + encryptor.finalize()
+
+
+def aes_encrypt(key, data):
+    pad = 16 - len(data) % 16
+    iv = os.urandom(16)
+    return iv + aes_cbc(key, iv, data + bytes([pad]) * pad)
+
+
+def padded(password):
+    return (password.encode("latin-1") + PADDING)[:32]
+
+
+class Aes128:
+    """Revision 4 with the AESV2 crypt filter."""
+
+    version, revision, length, cfm = 4, 4, 128, "AESV2"
+
+    def __init__(self, user, owner):
+        key = hashlib.md5(padded(owner)).digest()
+        for _ in range(50):
+            key = hashlib.md5(key).digest()
+        o = rc4(key, padded(user))
+        for i in range(1, 20):
+            o = rc4(bytes(b ^ i for b in key), o)
+        self.o = o
+
+        h = hashlib.md5(
+            padded(user) + o + struct.pack("<i", PERMISSIONS) + FILE_ID
+        ).digest()
+        for _ in range(50):
+            h = hashlib.md5(h).digest()
+        self.key = h
+
+        u = rc4(self.key, hashlib.md5(PADDING + FILE_ID).digest())
+        for i in range(1, 20):
+            u = rc4(bytes(b ^ i for b in self.key), u)
+        self.u = u + bytes(16)
+
+    def extra(self):
+        return ""
+
+    def encrypt(self, num, data):
+        key = hashlib.md5(
+            self.key + struct.pack("<i", num)[:3] + b"\0\0" + b"sAlT"
+        ).digest()
+        return aes_encrypt(key, data)
+
+
+def hash_r6(password, salt, udata=b""):
+    k = hashlib.sha256(password + salt + udata).digest()
+    i = 0
+    while True:
+        e = aes_cbc(k[:16], k[16:32], (password + k + udata) * 64)
+        k = [hashlib.sha256, hashlib.sha384, hashlib.sha512][sum(e[:16]) % 3](e).digest()
+        i += 1
+        if i >= 64 and e[-1] <= i - 32:
+            return k[:32]
+
+
+class Aes256:
+    """Revision 6 with the AESV3 crypt filter."""
+
+    version, revision, length, cfm = 5, 6, 256, "AESV3"
+
+    def __init__(self, user, owner):
+        user, owner = user.encode(), owner.encode()
+        self.key = os.urandom(32)
+        salts = os.urandom(16)
+        self.u = hash_r6(user, salts[:8]) + salts
+        self.ue = aes_cbc(hash_r6(user, salts[8:]), bytes(16), self.key)
+        salts = os.urandom(16)
+        self.o = hash_r6(owner, salts[:8], self.u) + salts
+        self.oe = aes_cbc(hash_r6(owner, salts[8:], self.u), bytes(16), self.key)
+        perms = struct.pack("<i", PERMISSIONS) + b"\xff\xff\xff\xffTadb" + os.urandom(4)
+        encryptor = Cipher(algorithms.AES(self.key), modes.ECB()).encryptor()
+        self.perms = encryptor.update(perms) + encryptor.finalize()
+
+    def extra(self):
+        return f"/OE <{self.oe.hex()}> /UE <{self.ue.hex()}> /Perms <{self.perms.hex()}>"
+
+    def encrypt(self, num, data):
+        return aes_encrypt(self.key, data)
+
+
+def write(path, handler, pdf_version, catalog_extra=""):
+    # Objects 1-4 and 8 go into the object stream 5
+    compressed = {
+        1: f"<< /Type /Catalog /Pages 2 0 R {catalog_extra}>>",
+        2: "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>",
+        3: "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] "
+        "/Resources << /Font << /F1 << /Type /Font /Subtype /Type1 "
+        "/BaseFont /Helvetica >> >> >> /Contents 6 0 R >>",
+        4: "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] "
+        "/Resources << /Font << /F1 << /Type /Font /Subtype /Type1 "
+        "/BaseFont /Helvetica >> >> >> /Contents 7 0 R >>",
+        8: "<< /Title (Encrypted fixture) /Producer (make_encrypted.py) >>",
+    }
+    header, body = [], b""
+    for num, text in compressed.items():
+        header.append(f"{num} {len(body)}")
+        body += text.encode() + b"\n"
+    header = (" ".join(header) + "\n").encode()
+    object_stream = zlib.compress(header + body)
+
+    out = bytearray(f"%PDF-{pdf_version}\n%\xe2\xe3\xcf\xd3\n".encode("latin-1"))
+    offsets = {}
+
+    def add(num, dictionary, stream=None):
+        offsets[num] = len(out)
+        out.extend(f"{num} 0 obj\n{dictionary}".encode())
+        if stream is not None:
+            out.extend(b"\nstream\n" + stream + b"\nendstream")
+        out.extend(b"\nendobj\n")
+
+    data = handler.encrypt(5, object_stream)
+    add(
+        5,
+        f"<< /Type /ObjStm /N {len(compressed)} /First {len(header)} "
+        f"/Filter /FlateDecode /Length {len(data)} >>",
+        data,
+    )
+    for num, page in [(6, 1), (7, 2)]:
+        data = handler.encrypt(num, f"BT /F1 24 Tf 72 720 Td (Page {page}) Tj ET".encode())
+        add(num, f"<< /Length {len(data)} >>", data)
+    add(
+        9,
+        f"<< /Filter /Standard /V {handler.version} /R {handler.revision} "
+        f"/Length {handler.length} /CF << /StdCF << /CFM /{handler.cfm} "
+        f"/AuthEvent /DocOpen /Length {handler.length // 8} >> >> "
+        f"/StmF /StdCF /StrF /StdCF /O <{handler.o.hex()}> /U <{handler.u.hex()}> "
+        f"/P {PERMISSIONS} {handler.extra()}>>",
+    )
+
+    # Cross-reference stream: type, field 2 (4 bytes), field 3 (2 bytes)
+    offsets[10] = len(out)
+    rows = b"\x00" + struct.pack(">IH", 0, 0xFFFF)
+    index_in_stream = {num: i for i, num in enumerate(compressed)}
+    for num in range(1, 11):
+        if num in compressed:
+            rows += b"\x02" + struct.pack(">IH", 5, index_in_stream[num])
+        else:
+            rows += b"\x01" + struct.pack(">IH", offsets[num], 0)
+    file_id = f"<{FILE_ID.hex()}>"
+    add(
+        10,
+        f"<< /Type /XRef /Size 11 /W [1 4 2] /Root 1 0 R /Info 8 0 R "
+        f"/Encrypt 9 0 R /ID [{file_id} {file_id}] /Length {len(rows)} >>",
+        rows,
+    )
+    out.extend(f"startxref\n{offsets[10]}\n%%EOF\n".encode())
+    with open(path, "wb") as f:
+        f.write(out)
+
+
+if __name__ == "__main__":
+    write("encrypted_objstm_aes128.pdf", Aes128("user", "owner"), "1.6")
+    write(
+        "encrypted_objstm_aes256.pdf",
+        Aes256("user", "owner"),
+        "1.7",
+        "/Extensions << /ADBE << /BaseVersion /1.7 /ExtensionLevel 8 >> "
+        "/EXMP << /BaseVersion /1.7 /ExtensionLevel 3 >> >> ",
+    )